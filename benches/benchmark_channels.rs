@@ -0,0 +1,68 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use dam::channel::ChannelElement;
+use dam::simulation::*;
+use dam::utility_contexts::*;
+
+/// Streams elements through a single channel, one at a time or in batches.
+fn stream(size: u64, capacity: usize, batched: bool) {
+    let mut parent = ProgramBuilder::default();
+    let (snd, rcv) = parent.bounded::<u64>(capacity);
+
+    let mut sender = FunctionContext::default();
+    snd.attach_sender(&sender);
+    sender.set_run(move |time| {
+        let elements = (0..size).map(|i| ChannelElement::new(time.tick() + 1, i));
+        if batched {
+            snd.enqueue_many(time, elements, 1).unwrap();
+        } else {
+            for element in elements {
+                snd.enqueue(time, element).unwrap();
+                time.incr_cycles(1);
+            }
+        }
+    });
+    parent.add_child(sender);
+
+    let mut receiver = FunctionContext::default();
+    rcv.attach_receiver(&receiver);
+    receiver.set_run(move |time| {
+        if batched {
+            while rcv.dequeue_many(time, capacity, 1).is_ok() {}
+        } else {
+            while rcv.dequeue(time).is_ok() {
+                time.incr_cycles(1);
+            }
+        }
+    });
+    parent.add_child(receiver);
+
+    parent
+        .initialize(
+            InitializationOptionsBuilder::default()
+                .run_flavor_inference(true)
+                .build()
+                .unwrap(),
+        )
+        .unwrap()
+        .run(RunOptionsBuilder::default().build().unwrap());
+}
+
+pub fn batching_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Channel_Batching");
+    let size = 1 << 14;
+    group.throughput(criterion::Throughput::Elements(size));
+    for capacity in [8, 64, 1024] {
+        for batched in [false, true] {
+            let name = if batched { "batched" } else { "single" };
+            group.bench_with_input(
+                BenchmarkId::new(name, capacity),
+                &capacity,
+                |b, &capacity| b.iter(|| stream(size, capacity, batched)),
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(channel_benches, batching_benchmark);
+criterion_main!(channel_benches);
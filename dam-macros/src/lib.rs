@@ -56,7 +56,14 @@ fn context_impl(_attrs: TokenStream, item: TokenStream, dam_path: Path) -> Token
                     }
 
                     fn name(&self) -> String {
-                        (#ident_string).into()
+                        match &self.context_info.name {
+                            Some(name) => name.clone(),
+                            None => (#ident_string).into(),
+                        }
+                    }
+
+                    fn set_id(&mut self, id: #dam_path::macro_support::Identifier) {
+                        self.context_info.id = id;
                     }
                 }
 
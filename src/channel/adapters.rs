@@ -2,17 +2,39 @@
 //! In particular, these are useful when some memory may contain elements of different types
 //! And so channels of different types may be connected to the memory.
 
-use crate::{context::Context, context_tools::DAMType, structures::TimeManager};
+use std::marker::PhantomData;
 
-use super::{ChannelElement, DequeueError, EnqueueError, PeekResult, Receiver, Sender};
+use crate::{
+    context::Context, context_tools::DAMType, datastructures::Time, structures::TimeManager,
+};
+
+use super::{
+    receiver::ReceiverFlavor,
+    utils::{EventTime, Peekable},
+    ChannelElement, DequeueError, EnqueueError, PeekResult, Receiver, Sender, TransactionTag,
+};
+
+fn conversion_error<T, U>() -> DequeueError {
+    DequeueError::Conversion(format!(
+        "{} could not be converted into {}",
+        std::any::type_name::<T>(),
+        std::any::type_name::<U>()
+    ))
+}
 
 /// An adapter for Receivers, delegating and converting all underlying operations
 pub trait RecvAdapter<U> {
     /// See: [Receiver::attach_receiver]
     fn attach_receiver(&self, ctx: &dyn Context);
 
-    /// See: [Receiver::peek]
+    /// See: [Receiver::peek]. Panics if the value cannot be converted, see [RecvAdapter::try_peek].
     fn peek(&self) -> PeekResult<U>;
+
+    /// See: [Receiver::peek]. Failed conversions are reported as errors.
+    fn try_peek(&self) -> Result<PeekResult<U>, DequeueError> {
+        Ok(self.peek())
+    }
+
     /// See: [Receiver::peek_next]
     fn peek_next(&self, manager: &TimeManager) -> Result<ChannelElement<U>, DequeueError>;
     /// See: [Receiver::dequeue]
@@ -28,31 +50,30 @@ where
     }
 
     fn peek(&self) -> PeekResult<U> {
-        match Receiver::peek(self) {
+        RecvAdapter::try_peek(self)
+            .unwrap_or_else(|_| panic!("Failed to convert the peek value into the desired type"))
+    }
+
+    fn try_peek(&self) -> Result<PeekResult<U>, DequeueError> {
+        Ok(match Receiver::peek(self) {
             PeekResult::Something(ce) => {
-                PeekResult::Something(ce.try_convert().unwrap_or_else(|_| {
-                    panic!("Failed to convert the peek value into the desired type")
-                }))
+                PeekResult::Something(ce.try_convert().map_err(|_| conversion_error::<T, U>())?)
             }
             PeekResult::Nothing(time) => PeekResult::Nothing(time),
             PeekResult::Closed => PeekResult::Closed,
-        }
+        })
     }
 
     fn peek_next(&self, manager: &TimeManager) -> Result<ChannelElement<U>, DequeueError> {
-        Receiver::peek_next(self, manager).map(|val| {
-            val.try_convert().unwrap_or_else(|_| {
-                panic!("Failed to convert the peek_next value into the desired type")
-            })
-        })
+        Receiver::peek_next(self, manager)?
+            .try_convert()
+            .map_err(|_| conversion_error::<T, U>())
     }
 
     fn dequeue(&self, manager: &TimeManager) -> Result<ChannelElement<U>, DequeueError> {
-        Receiver::dequeue(self, manager).map(|val| {
-            val.try_convert().unwrap_or_else(|_| {
-                panic!("Failed to convert the dequeued value into the desired type")
-            })
-        })
+        Receiver::dequeue(self, manager)?
+            .try_convert()
+            .map_err(|_| conversion_error::<T, U>())
     }
 }
 
@@ -90,3 +111,429 @@ where
         Sender::attach_sender(self, ctx)
     }
 }
+
+/// What an [AdaptedReceiver] sees at the head of its channel.
+enum Head<U> {
+    Ready(ChannelElement<U>),
+    Failed(Time, String),
+    Nothing(Time),
+    Closed,
+}
+
+/// A receiver with a chain of [Receiver::map], [Receiver::filter], and [Receiver::try_map] applied to it.
+/// These run inline on the receiving context, so they don't add any latency.
+/// The functions may be called several times on the same element, and so shouldn't have side effects.
+/// `F` is the composition of the chain, which maps a value to None if it was filtered out.
+pub struct AdaptedReceiver<T: Clone, U, F> {
+    inner: Receiver<T>,
+    transform: F,
+    _marker: PhantomData<fn() -> U>,
+}
+
+impl<T: DAMType> Receiver<T> {
+    fn adapt<U, F>(self, transform: F) -> AdaptedReceiver<T, U, F>
+    where
+        F: Fn(T) -> Option<Result<U, String>> + Send + Sync,
+    {
+        AdaptedReceiver {
+            inner: self,
+            transform,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Applies a function to every received value.
+    pub fn map<U>(
+        self,
+        func: impl Fn(T) -> U + Send + Sync,
+    ) -> AdaptedReceiver<T, U, impl Fn(T) -> Option<Result<U, String>> + Send + Sync> {
+        self.adapt(move |value| Some(Ok(func(value))))
+    }
+
+    /// Applies a fallible function to every received value, surfacing failures as [DequeueError::Conversion].
+    pub fn try_map<U, E: std::fmt::Display>(
+        self,
+        func: impl Fn(T) -> Result<U, E> + Send + Sync,
+    ) -> AdaptedReceiver<T, U, impl Fn(T) -> Option<Result<U, String>> + Send + Sync> {
+        self.adapt(move |value| Some(func(value).map_err(|err| err.to_string())))
+    }
+
+    /// Silently drops values which don't satisfy the predicate.
+    pub fn filter(
+        self,
+        predicate: impl Fn(&T) -> bool + Send + Sync,
+    ) -> AdaptedReceiver<T, T, impl Fn(T) -> Option<Result<T, String>> + Send + Sync> {
+        self.adapt(move |value| predicate(&value).then_some(Ok(value)))
+    }
+
+    /// Pairs up the elements of two channels, which are available once both halves have arrived.
+    pub fn zip<U: DAMType>(self, other: Receiver<U>) -> Zip<T, U> {
+        Zip {
+            left: self,
+            right: other,
+        }
+    }
+}
+
+impl<T: DAMType, U, F> AdaptedReceiver<T, U, F>
+where
+    F: Fn(T) -> Option<Result<U, String>> + Send + Sync,
+{
+    fn then<V>(
+        self,
+        next: impl Fn(U) -> Option<Result<V, String>> + Send + Sync,
+    ) -> AdaptedReceiver<T, V, impl Fn(T) -> Option<Result<V, String>> + Send + Sync> {
+        let transform = self.transform;
+        self.inner.adapt(move |value| match transform(value)? {
+            Ok(value) => next(value),
+            Err(err) => Some(Err(err)),
+        })
+    }
+
+    /// See [Receiver::map]
+    pub fn map<V>(
+        self,
+        func: impl Fn(U) -> V + Send + Sync,
+    ) -> AdaptedReceiver<T, V, impl Fn(T) -> Option<Result<V, String>> + Send + Sync> {
+        self.then(move |value| Some(Ok(func(value))))
+    }
+
+    /// See [Receiver::try_map]
+    pub fn try_map<V, E: std::fmt::Display>(
+        self,
+        func: impl Fn(U) -> Result<V, E> + Send + Sync,
+    ) -> AdaptedReceiver<T, V, impl Fn(T) -> Option<Result<V, String>> + Send + Sync> {
+        self.then(move |value| Some(func(value).map_err(|err| err.to_string())))
+    }
+
+    /// See [Receiver::filter]
+    pub fn filter(
+        self,
+        predicate: impl Fn(&U) -> bool + Send + Sync,
+    ) -> AdaptedReceiver<T, U, impl Fn(T) -> Option<Result<U, String>> + Send + Sync> {
+        self.then(move |value| predicate(&value).then_some(Ok(value)))
+    }
+
+    /// See [Receiver::attach_receiver]
+    pub fn attach_receiver(&self, ctx: &dyn Context) {
+        self.inner.attach_receiver(ctx)
+    }
+
+    fn head(&self) -> Head<U> {
+        loop {
+            let element = match self.inner.peek() {
+                PeekResult::Something(element) => element,
+                PeekResult::Nothing(time) => return Head::Nothing(time),
+                PeekResult::Closed => return Head::Closed,
+            };
+            match (self.transform)(element.data) {
+                Some(Ok(data)) => return Head::Ready(ChannelElement::new(element.time, data)),
+                Some(Err(err)) => return Head::Failed(element.time, err),
+                // Filtered out elements can only be dropped once we've reached them.
+                None if self.inner.under().discard_head() => {}
+                None => return Head::Nothing(element.time - 1),
+            }
+        }
+    }
+
+    /// See [RecvAdapter::peek]. Panics if a value fails to convert.
+    pub fn peek(&self) -> PeekResult<U> {
+        self.try_peek()
+            .unwrap_or_else(|err| panic!("Failed to convert the peek value: {err}"))
+    }
+
+    /// See [RecvAdapter::try_peek]. Failed conversions are reported as errors.
+    pub fn try_peek(&self) -> Result<PeekResult<U>, DequeueError> {
+        match self.head() {
+            Head::Ready(element) => Ok(PeekResult::Something(element)),
+            Head::Failed(_, err) => Err(DequeueError::Conversion(err)),
+            Head::Nothing(time) => Ok(PeekResult::Nothing(time)),
+            Head::Closed => Ok(PeekResult::Closed),
+        }
+    }
+
+    /// See [Receiver::peek_next]
+    pub fn peek_next(&self, manager: &TimeManager) -> Result<ChannelElement<U>, DequeueError> {
+        loop {
+            let element = self.inner.peek_next(manager)?;
+            match (self.transform)(element.data) {
+                Some(Ok(data)) => return Ok(ChannelElement::new(element.time, data)),
+                Some(Err(err)) => return Err(DequeueError::Conversion(err)),
+                None => {
+                    self.inner.under().discard_head();
+                }
+            }
+        }
+    }
+
+    /// See [Receiver::dequeue]. An element which fails to convert is still removed from the channel.
+    pub fn dequeue(&self, manager: &TimeManager) -> Result<ChannelElement<U>, DequeueError> {
+        loop {
+            let element = self.inner.dequeue(manager)?;
+            match (self.transform)(element.data) {
+                Some(Ok(data)) => return Ok(ChannelElement::new(element.time, data)),
+                Some(Err(err)) => return Err(DequeueError::Conversion(err)),
+                None => {}
+            }
+        }
+    }
+
+    /// See [Receiver::last_tag]
+    pub fn last_tag(&self) -> Option<TransactionTag> {
+        self.inner.last_tag()
+    }
+}
+
+impl<T: DAMType, U, F> Peekable for &AdaptedReceiver<T, U, F>
+where
+    F: Fn(T) -> Option<Result<U, String>> + Send + Sync,
+{
+    fn next_event(self) -> EventTime {
+        match self.head() {
+            // A failed conversion is still an event, which shows up once it is dequeued.
+            Head::Ready(ChannelElement { time, .. }) | Head::Failed(time, _) => {
+                EventTime::Ready(time)
+            }
+            Head::Nothing(time) if !time.is_infinite() => EventTime::Nothing(time),
+            Head::Nothing(_) | Head::Closed => (&self.inner).next_event(),
+        }
+    }
+}
+
+/// Two receivers whose elements are consumed in pairs, see [Receiver::zip].
+/// A pair is timestamped by whichever half arrived later.
+pub struct Zip<T: Clone, U: Clone> {
+    left: Receiver<T>,
+    right: Receiver<U>,
+}
+
+impl<T: DAMType, U: DAMType> Zip<T, U> {
+    /// Registers a context as the receiver of both channels.
+    pub fn attach_receiver(&self, ctx: &dyn Context) {
+        self.left.attach_receiver(ctx);
+        self.right.attach_receiver(ctx);
+    }
+
+    fn pair(left: ChannelElement<T>, right: ChannelElement<U>) -> ChannelElement<(T, U)> {
+        ChannelElement::new(left.time.max(right.time), (left.data, right.data))
+    }
+
+    /// The time just before `time`, saturating at 0 where the missing half's proof already covers it.
+    fn before(time: Time) -> Time {
+        Time::new(time.time().saturating_sub(1))
+    }
+
+    /// Peeks at the next pair. If either channel is closed, no further pairs can be formed.
+    pub fn peek(&self) -> PeekResult<(T, U)> {
+        match (self.left.peek(), self.right.peek()) {
+            (PeekResult::Closed, _) | (_, PeekResult::Closed) => PeekResult::Closed,
+            (PeekResult::Something(left), PeekResult::Something(right)) => {
+                PeekResult::Something(Self::pair(left, right))
+            }
+            // The missing half arrives after its proof, and the pair can't be earlier than the half we have.
+            (PeekResult::Something(present), PeekResult::Nothing(proof)) => {
+                PeekResult::Nothing(proof.max(Self::before(present.time)))
+            }
+            (PeekResult::Nothing(proof), PeekResult::Something(present)) => {
+                PeekResult::Nothing(proof.max(Self::before(present.time)))
+            }
+            (PeekResult::Nothing(left), PeekResult::Nothing(right)) => {
+                PeekResult::Nothing(left.max(right))
+            }
+        }
+    }
+
+    /// Advances until both halves of the next pair have arrived, and returns the pair without popping it.
+    pub fn peek_next(&self, manager: &TimeManager) -> Result<ChannelElement<(T, U)>, DequeueError> {
+        let left = self.left.peek_next(manager)?;
+        let right = self.right.peek_next(manager)?;
+        Ok(Self::pair(left, right))
+    }
+
+    /// Advances until both halves of the next pair have arrived, and pops them.
+    /// If either channel closes first, the other half is left in its channel.
+    pub fn dequeue(&self, manager: &TimeManager) -> Result<ChannelElement<(T, U)>, DequeueError> {
+        self.peek_next(manager)?;
+        let left = self.left.dequeue(manager)?;
+        let right = self.right.dequeue(manager)?;
+        Ok(Self::pair(left, right))
+    }
+
+    /// The last pair belongs to the left half's transaction, or the right half's if the left is untagged.
+    pub fn last_tag(&self) -> Option<TransactionTag> {
+        self.left.last_tag().or(self.right.last_tag())
+    }
+}
+
+impl<T: DAMType, U: DAMType> Peekable for &Zip<T, U> {
+    fn next_event(self) -> EventTime {
+        match self.peek() {
+            PeekResult::Something(element) => EventTime::Ready(element.time),
+            PeekResult::Nothing(time) => EventTime::Nothing(time),
+            PeekResult::Closed => EventTime::Closed(Time::infinite()),
+        }
+    }
+}
+
+/// A sender which applies a function to values before sending them, see [Sender::contramap].
+pub struct ContramappedSender<T: Clone, U, F> {
+    inner: Sender<T>,
+    func: F,
+    _marker: PhantomData<fn(U)>,
+}
+
+impl<T: DAMType> Sender<T> {
+    /// Converts values with a function before sending them, without any added latency.
+    pub fn contramap<U, F>(self, func: F) -> ContramappedSender<T, U, F>
+    where
+        F: Fn(U) -> T + Send + Sync,
+    {
+        ContramappedSender {
+            inner: self,
+            func,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: DAMType, U, F> ContramappedSender<T, U, F>
+where
+    F: Fn(U) -> T + Send + Sync,
+{
+    /// Converts values with another function first.
+    pub fn contramap<V>(
+        self,
+        func: impl Fn(V) -> U + Send + Sync,
+    ) -> ContramappedSender<T, V, impl Fn(V) -> T + Send + Sync> {
+        let outer = self.func;
+        self.inner.contramap(move |value| outer(func(value)))
+    }
+}
+
+impl<T: DAMType, U, F> SendAdapter<U> for ContramappedSender<T, U, F>
+where
+    F: Fn(U) -> T + Send + Sync,
+{
+    fn attach_sender(&self, ctx: &dyn Context) {
+        self.inner.attach_sender(ctx)
+    }
+
+    fn enqueue(&self, manager: &TimeManager, data: ChannelElement<U>) -> Result<(), EnqueueError> {
+        self.inner.enqueue(manager, data.map(&self.func))
+    }
+
+    fn wait_until_available(&self, manager: &TimeManager) -> Result<(), EnqueueError> {
+        self.inner.wait_until_available(manager)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        channel::{
+            utils::{EventTime, Peekable},
+            ChannelElement, DequeueError, PeekResult,
+        },
+        simulation::{InitializationOptionsBuilder, ProgramBuilder},
+        utility_contexts::{FunctionContext, GeneratorContext},
+    };
+
+    use super::{RecvAdapter, SendAdapter};
+
+    fn adapted(flavor_inference: bool) {
+        let mut ctx = ProgramBuilder::default();
+        let (snd, rcv) = ctx.bounded::<u64>(4);
+        let (fallible_snd, fallible_rcv) = ctx.bounded::<i32>(4);
+        let (left_snd, left_rcv) = ctx.bounded_with_latency::<u32>(2, 5, 1);
+        let (right_snd, right_rcv) = ctx.bounded::<u32>(2);
+        let (signed_snd, signed_rcv) = ctx.bounded::<i32>(2);
+
+        let snd = snd.contramap(u64::from).contramap(|x: u16| x as u32 * 2);
+        let mut sender = FunctionContext::default();
+        snd.attach_sender(&sender);
+        sender.set_run(move |time| {
+            for i in 0..32u16 {
+                snd.enqueue(time, ChannelElement::new(time.tick() + 1, i))
+                    .unwrap();
+                time.incr_cycles(1);
+            }
+        });
+        ctx.add_child(sender);
+        ctx.add_child(GeneratorContext::new(|| -4..4i32, fallible_snd));
+        // The left side has one element too many, which is left behind once the right side closes.
+        ctx.add_child(GeneratorContext::new(|| 0..17u32, left_snd));
+        ctx.add_child(GeneratorContext::new(|| 0..16u32, right_snd));
+        ctx.add_child(GeneratorContext::new(|| [-1i32, 1].into_iter(), signed_snd));
+
+        let filtered = rcv.filter(|x| x % 4 == 0).map(|x| x / 4);
+        let converted = fallible_rcv.try_map(u32::try_from);
+        let zipped = left_rcv.zip(right_rcv);
+        let mut receiver = FunctionContext::default();
+        filtered.attach_receiver(&receiver);
+        converted.attach_receiver(&receiver);
+        zipped.attach_receiver(&receiver);
+        signed_rcv.attach_receiver(&receiver);
+        receiver.set_run(move |time| {
+            for expected in 0..16 {
+                let event = (&filtered).next_event();
+                let element = filtered.dequeue(time).unwrap();
+                assert_eq!(element.data, expected);
+                if let EventTime::Ready(ready) = event {
+                    assert_eq!(ready, element.time);
+                }
+            }
+            assert!(matches!(filtered.dequeue(time), Err(DequeueError::Closed)));
+
+            for _ in -4..0 {
+                assert!(matches!(
+                    converted.dequeue(time),
+                    Err(DequeueError::Conversion(_))
+                ));
+            }
+            for expected in 0..4 {
+                assert_eq!(converted.dequeue(time).unwrap().data, expected);
+            }
+
+            for expected in 0..16 {
+                let element = zipped.dequeue(time).unwrap();
+                assert_eq!(element.data, (expected, expected));
+                // The left half always arrives last.
+                assert!(element.time.time() >= 5);
+            }
+            assert!(matches!(zipped.dequeue(time), Err(DequeueError::Closed)));
+            assert!(matches!(
+                zipped.left.peek(),
+                PeekResult::Something(ChannelElement { data: 16, .. })
+            ));
+
+            RecvAdapter::<u32>::peek_next(&signed_rcv, time).unwrap_err();
+            assert!(matches!(
+                RecvAdapter::<u32>::try_peek(&signed_rcv),
+                Err(DequeueError::Conversion(_))
+            ));
+            RecvAdapter::<u32>::dequeue(&signed_rcv, time).unwrap_err();
+            assert_eq!(
+                RecvAdapter::<u32>::dequeue(&signed_rcv, time).unwrap().data,
+                1
+            );
+        });
+        ctx.add_child(receiver);
+
+        let executed = ctx
+            .initialize(
+                InitializationOptionsBuilder::default()
+                    .run_flavor_inference(flavor_inference)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap()
+            .run(Default::default());
+        assert!(executed.passed());
+    }
+
+    #[test]
+    fn test_closure_adapters() {
+        adapted(false);
+        adapted(true);
+    }
+}
@@ -1,10 +1,10 @@
-use std::sync::atomic::AtomicUsize;
-
 use serde::{Deserialize, Serialize};
 
-static ID_COUNTER: AtomicUsize = AtomicUsize::new(0);
+use crate::datastructures::IdAllocator;
 
-/// A unique identifier for a channel. Not guaranteed stable across program runs.
+/// A unique identifier for a channel.
+/// Channels created through a [crate::simulation::ProgramBuilder] are numbered by that program, and are stable across runs as long as they are created in the same order.
+/// IDs constructed outside of a builder's scope, see [crate::simulation::ProgramBuilder::with_ids], are unique but depend on everything else constructed in the process.
 #[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
 pub struct ChannelID {
     id: usize,
@@ -12,7 +12,7 @@ pub struct ChannelID {
 
 impl ChannelID {
     fn next_id() -> usize {
-        ID_COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+        IdAllocator::next_channel()
     }
 
     /// Construct a new ChannelID
@@ -1,3 +1,8 @@
+use std::{
+    num::NonZeroU64,
+    sync::{Arc, OnceLock},
+};
+
 use crate::shim::Mutex;
 
 use crate::{
@@ -6,7 +11,7 @@ use crate::{
     view::{ContextView, TimeView},
 };
 
-use super::ChannelID;
+use super::{ChannelID, CloseError};
 
 type ViewType = Option<TimeView>;
 
@@ -21,6 +26,16 @@ pub(crate) struct ChannelSpec {
     capacity: Option<usize>,
     send_latency: u64,
     response_latency: u64,
+    bandwidth: Option<NonZeroU64>,
+    out_of_order: bool,
+    close_time: Arc<OnceLock<Time>>,
+}
+
+/// The context an end of a channel is attached to, which can be restored with [ChannelSpec::restore_sender] or [ChannelSpec::restore_receiver].
+#[derive(Clone, Default)]
+pub(crate) struct Attachment {
+    view: ViewType,
+    id: Option<Identifier>,
 }
 
 /// An inline version of the specification. This avoids needing an extra Arc/indirection to get back to the original object.
@@ -31,6 +46,7 @@ pub(crate) struct InlineSpec {
 
     sender_view: ViewType,
     receiver_view: ViewType,
+    close_time: Arc<OnceLock<Time>>,
 }
 
 impl ChannelSpec {
@@ -52,9 +68,43 @@ impl ChannelSpec {
             capacity,
             send_latency: lat,
             response_latency: resp_lat,
+            bandwidth: None,
+            out_of_order: false,
+            close_time: Default::default(),
         }
     }
 
+    /// Limits the channel to transferring a number of bits per cycle.
+    pub fn with_bandwidth(mut self, bits_per_cycle: NonZeroU64) -> Self {
+        self.bandwidth = Some(bits_per_cycle);
+        self
+    }
+
+    pub fn bandwidth(&self) -> Option<u64> {
+        self.bandwidth.map(NonZeroU64::get)
+    }
+
+    /// Delivers elements to the receiver in order of their times, instead of the order they were sent in.
+    pub fn with_out_of_order(mut self) -> Self {
+        self.out_of_order = true;
+        self
+    }
+
+    pub fn out_of_order(&self) -> bool {
+        self.out_of_order
+    }
+
+    /// Records that the sender closed the channel at a particular time. Channels can only be closed once.
+    pub fn close_at(&self, time: Time) -> Result<(), CloseError> {
+        self.close_time
+            .set(time)
+            .map_err(|_| CloseError::AlreadyClosed)
+    }
+
+    pub fn close_time(&self) -> Option<Time> {
+        self.close_time.get().copied()
+    }
+
     pub fn sender_id(&self) -> Option<Identifier> {
         *self.sender_id.lock().unwrap()
     }
@@ -84,6 +134,38 @@ impl ChannelSpec {
         *self.receiver_id.lock().unwrap() = Some(receiver.id());
     }
 
+    /// Points the endpoints at their contexts' new IDs, after the contexts were renumbered by their program.
+    pub fn renumber_endpoints(&self, renumber: impl Fn(Identifier) -> Identifier) {
+        for endpoint in [&self.sender_id, &self.receiver_id] {
+            let mut endpoint = endpoint.lock().unwrap();
+            *endpoint = endpoint.map(&renumber);
+        }
+    }
+
+    pub fn sender_attachment(&self) -> Attachment {
+        Attachment {
+            view: self.sender_view.lock().unwrap().clone(),
+            id: self.sender_id(),
+        }
+    }
+
+    pub fn receiver_attachment(&self) -> Attachment {
+        Attachment {
+            view: self.receiver_view.lock().unwrap().clone(),
+            id: self.receiver_id(),
+        }
+    }
+
+    pub fn restore_sender(&self, attachment: Attachment) {
+        *self.sender_view.lock().unwrap() = attachment.view;
+        *self.sender_id.lock().unwrap() = attachment.id;
+    }
+
+    pub fn restore_receiver(&self, attachment: Attachment) {
+        *self.receiver_view.lock().unwrap() = attachment.view;
+        *self.receiver_id.lock().unwrap() = attachment.id;
+    }
+
     pub fn capacity(&self) -> Option<usize> {
         self.capacity
     }
@@ -99,6 +181,7 @@ impl ChannelSpec {
             response_latency: self.response_latency,
             sender_view: self.sender_view.lock().unwrap().clone(),
             receiver_view: self.receiver_view.lock().unwrap().clone(),
+            close_time: self.close_time.clone(),
         }
     }
 }
@@ -119,4 +202,8 @@ impl InlineSpec {
     pub fn receiver_tlb(&self) -> Time {
         self.receiver_view.as_ref().unwrap().tick_lower_bound()
     }
+
+    pub fn close_time(&self) -> Option<Time> {
+        self.close_time.get().copied()
+    }
 }
@@ -0,0 +1,447 @@
+//! Seeded fault injection on channels, for studying how programs behave with unreliable links.
+//! Faults are configured through [crate::simulation::ProgramBuilder::inject_faults] or a [FaultPlan],
+//! and are applied by the sender as elements are enqueued.
+//! Every injected fault is logged as a [FaultEvent], and totals are reported by [crate::simulation::Executed::fault_summary].
+
+use std::{
+    hash::{Hash, Hasher},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use dam_macros::event_type_internal;
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{datastructures::Time, logging::log_event};
+
+use super::ChannelID;
+
+/// Modifies an element in place to emulate a corrupted payload, see [crate::simulation::ProgramBuilder::corrupt_with].
+pub(crate) type Corruptor<T> = Arc<dyn Fn(&mut T, &mut fastrand::Rng) + Send + Sync>;
+
+/// A span of cycles `[start, end)` during which a link cannot transfer anything.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StallWindow {
+    /// The first stalled cycle
+    pub start: u64,
+    /// The first cycle after the stall
+    pub end: u64,
+}
+
+/// The faults injected on a single channel. Probabilities are per element.
+#[derive(Builder, Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[builder(pattern = "owned")]
+#[serde(default)]
+pub struct FaultSpec {
+    /// Seeds the channel's random number generator, which is also mixed with the channel's ID.
+    #[builder(default)]
+    seed: u64,
+
+    /// Probability of silently dropping an element
+    #[builder(default)]
+    drop_probability: f64,
+
+    /// Probability of passing an element through the channel's corruptor, which must be registered via
+    /// [crate::simulation::ProgramBuilder::corrupt_with]
+    #[builder(default)]
+    corrupt_probability: f64,
+
+    /// Probability of delaying an element by [FaultSpec::spike_latency] extra cycles.
+    /// Unless the channel delivers out of order, later elements cannot overtake a delayed one.
+    #[builder(default)]
+    spike_probability: f64,
+
+    /// The extra latency of a latency spike
+    #[builder(default)]
+    spike_latency: u64,
+
+    /// Windows during which the link is held, so that elements wait until the stall ends
+    #[builder(setter(into), default)]
+    stalls: Vec<StallWindow>,
+}
+
+impl FaultSpec {
+    /// Checks that all probabilities are between 0 and 1, and that stall windows are well-formed.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        for (name, probability) in [
+            ("drop", self.drop_probability),
+            ("corrupt", self.corrupt_probability),
+            ("spike", self.spike_probability),
+        ] {
+            if !(0.0..=1.0).contains(&probability) {
+                return Err(format!(
+                    "{name} probability {probability} is not within [0, 1]"
+                ));
+            }
+        }
+        match self.stalls.iter().find(|stall| stall.start >= stall.end) {
+            Some(stall) => Err(format!("stall window {stall:?} is empty")),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn corrupts(&self) -> bool {
+        self.corrupt_probability > 0.0
+    }
+}
+
+/// Faults on a single channel within a [FaultPlan].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChannelFaults {
+    /// The channel to inject faults on
+    pub channel: ChannelID,
+
+    /// The faults to inject
+    #[serde(flatten)]
+    pub faults: FaultSpec,
+}
+
+/// A set of faults which can be loaded from JSON or TOML, and applied via [crate::simulation::ProgramBuilder::apply_fault_plan].
+/// Channels are referred to by ID, which is stable as long as the program is built the same way.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct FaultPlan {
+    /// The faulty channels
+    #[serde(default)]
+    pub channels: Vec<ChannelFaults>,
+}
+
+/// Errors from loading a [FaultPlan]
+#[derive(Error, Debug)]
+pub enum FaultPlanError {
+    /// The plan could not be read
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    /// The plan could not be parsed
+    #[error("Could not parse fault plan: {0}")]
+    Parse(String),
+}
+
+impl FaultPlan {
+    /// Parses a plan from JSON.
+    pub fn from_json(text: &str) -> Result<Self, FaultPlanError> {
+        serde_json::from_str(text).map_err(|err| FaultPlanError::Parse(err.to_string()))
+    }
+
+    /// Parses a plan from TOML.
+    #[cfg(feature = "toml")]
+    pub fn from_toml(text: &str) -> Result<Self, FaultPlanError> {
+        toml::from_str(text).map_err(|err| FaultPlanError::Parse(err.to_string()))
+    }
+
+    /// Reads a plan from a file, choosing the format by extension.
+    /// Files ending in `.toml` require the `toml` feature, and everything else is treated as JSON.
+    pub fn from_path(path: impl AsRef<std::path::Path>) -> Result<Self, FaultPlanError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            #[cfg(feature = "toml")]
+            Some("toml") => Self::from_toml(&text),
+            #[cfg(not(feature = "toml"))]
+            Some("toml") => Err(FaultPlanError::Parse(
+                "TOML fault plans require the `toml` feature".to_string(),
+            )),
+            _ => Self::from_json(&text),
+        }
+    }
+}
+
+/// Logged whenever a fault is injected.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+#[event_type_internal]
+pub enum FaultEvent {
+    /// An element was dropped
+    Dropped(ChannelID),
+    /// An element was corrupted
+    Corrupted(ChannelID),
+    /// An element was delayed by a number of extra cycles
+    LatencySpike(ChannelID, u64),
+    /// An element was held back until a stall ended
+    Stalled(ChannelID, Time),
+}
+
+/// The number of faults injected on a channel over a run.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FaultSummary {
+    /// The faulty channel
+    pub channel: ChannelID,
+    /// Elements which were dropped
+    pub dropped: u64,
+    /// Elements which were corrupted
+    pub corrupted: u64,
+    /// Elements which were delayed by a latency spike
+    pub latency_spikes: u64,
+    /// Elements which were held back by a stall
+    pub stalled: u64,
+}
+
+/// Fault totals, shared between a channel and its sender so that they outlive the sender.
+#[derive(Default)]
+pub(crate) struct FaultCounts {
+    dropped: AtomicU64,
+    corrupted: AtomicU64,
+    latency_spikes: AtomicU64,
+    stalled: AtomicU64,
+}
+
+impl FaultCounts {
+    pub(crate) fn summarize(&self, channel: ChannelID) -> FaultSummary {
+        FaultSummary {
+            channel,
+            dropped: self.dropped.load(Ordering::Relaxed),
+            corrupted: self.corrupted.load(Ordering::Relaxed),
+            latency_spikes: self.latency_spikes.load(Ordering::Relaxed),
+            stalled: self.stalled.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The sender-side state of a faulty channel.
+pub(crate) struct FaultInjector<T> {
+    channel: ChannelID,
+    spec: FaultSpec,
+    rng: fastrand::Rng,
+    corruptor: Option<Corruptor<T>>,
+    counts: Arc<FaultCounts>,
+    // Only tracked for in-order channels.
+    last_arrival: Option<Time>,
+}
+
+impl<T> FaultInjector<T> {
+    pub(crate) fn new(
+        channel: ChannelID,
+        spec: FaultSpec,
+        corruptor: Option<Corruptor<T>>,
+        counts: Arc<FaultCounts>,
+        in_order: bool,
+    ) -> Self {
+        let mut hasher = rustc_hash::FxHasher::default();
+        (spec.seed, channel).hash(&mut hasher);
+        Self {
+            channel,
+            rng: fastrand::Rng::with_seed(hasher.finish()),
+            spec,
+            corruptor,
+            counts,
+            last_arrival: in_order.then(|| Time::new(0)),
+        }
+    }
+
+    fn roll(&mut self, probability: f64) -> bool {
+        probability > 0.0 && self.rng.f64() < probability
+    }
+
+    /// Returns when a transfer which is ready at `ready` can start, after waiting out any stalls.
+    pub(crate) fn stall(&mut self, ready: Time) -> Time {
+        let mut time = ready;
+        while let Some(stall) = self
+            .spec
+            .stalls
+            .iter()
+            .find(|stall| stall.start <= time.time() && time.time() < stall.end)
+        {
+            time = Time::new(stall.end);
+        }
+        if time > ready {
+            self.counts.stalled.fetch_add(1, Ordering::Relaxed);
+            log_event(&FaultEvent::Stalled(self.channel, time)).unwrap();
+        }
+        time
+    }
+
+    /// Applies the remaining faults to an element, returning its extra latency, or None if it was dropped.
+    pub(crate) fn inject(&mut self, data: &mut T) -> Option<u64> {
+        if self.roll(self.spec.drop_probability) {
+            self.counts.dropped.fetch_add(1, Ordering::Relaxed);
+            log_event(&FaultEvent::Dropped(self.channel)).unwrap();
+            return None;
+        }
+
+        if self.roll(self.spec.corrupt_probability) {
+            let corruptor = self.corruptor.as_ref().unwrap_or_else(|| {
+                panic!(
+                    "Channel {:?} corrupts elements without a corruptor",
+                    self.channel
+                )
+            });
+            corruptor(data, &mut self.rng);
+            self.counts.corrupted.fetch_add(1, Ordering::Relaxed);
+            log_event(&FaultEvent::Corrupted(self.channel)).unwrap();
+        }
+
+        if self.roll(self.spec.spike_probability) {
+            self.counts.latency_spikes.fetch_add(1, Ordering::Relaxed);
+            log_event(&FaultEvent::LatencySpike(
+                self.channel,
+                self.spec.spike_latency,
+            ))
+            .unwrap();
+            Some(self.spec.spike_latency)
+        } else {
+            Some(0)
+        }
+    }
+
+    /// Keeps elements of in-order channels behind earlier delayed ones, returning the adjusted arrival time.
+    pub(crate) fn arrive(&mut self, arrival: Time) -> Time {
+        match &mut self.last_arrival {
+            Some(last_arrival) => {
+                *last_arrival = (*last_arrival).max(arrival);
+                *last_arrival
+            }
+            None => arrival,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU64;
+
+    use crate::{
+        simulation::{InitializationError, InitializationOptions, ProgramBuilder, RunOptions},
+        utility_contexts::{CollectorContext, ConsumerContext, GeneratorContext},
+    };
+
+    use super::{FaultPlan, FaultSpecBuilder, FaultSummary, StallWindow};
+
+    /// Returns the received (time, value) pairs and the fault summary of a faulty pipeline.
+    fn faulty_pipeline(seed: u64) -> (Vec<(u64, u32)>, Vec<FaultSummary>) {
+        let mut ctx = ProgramBuilder::default();
+        let (snd, rcv) = ctx.bounded::<u32>(4);
+        ctx.inject_faults(
+            snd.id(),
+            FaultSpecBuilder::default()
+                .seed(seed)
+                .drop_probability(0.1)
+                .corrupt_probability(0.1)
+                .spike_probability(0.1)
+                .spike_latency(20)
+                .stalls(vec![StallWindow { start: 50, end: 80 }])
+                .build()
+                .unwrap(),
+        );
+        ctx.corrupt_with(&snd, |value, _| *value = u32::MAX);
+        ctx.add_child(GeneratorContext::new(|| 0..256u32, snd));
+
+        let (collector, handle) = CollectorContext::new(rcv);
+        ctx.add_child(collector);
+
+        let executed = ctx
+            .initialize(InitializationOptions::default())
+            .unwrap()
+            .run(RunOptions::default());
+        assert!(executed.passed());
+        let seen = handle
+            .times()
+            .iter()
+            .zip(handle.values())
+            .map(|(time, value)| (time.time(), value))
+            .collect();
+        (seen, executed.fault_summary())
+    }
+
+    #[test]
+    fn test_faults_are_reproducible() {
+        let (seen, summary) = faulty_pipeline(3);
+        assert_eq!(summary.len(), 1);
+        let summary = summary[0];
+        assert_eq!(seen.len() as u64, 256 - summary.dropped);
+        assert_eq!(
+            seen.iter().filter(|(_, value)| *value == u32::MAX).count() as u64,
+            summary.corrupted
+        );
+        assert!(summary.dropped > 0 && summary.corrupted > 0 && summary.latency_spikes > 0);
+        assert!(summary.stalled > 0);
+        assert!(seen.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+        // Only elements which departed before the stall, possibly with a spike, can arrive during it.
+        assert!(seen.iter().all(|(time, _)| !(71..81).contains(time)));
+
+        assert_eq!(faulty_pipeline(3), (seen.clone(), vec![summary]));
+        assert_ne!(faulty_pipeline(4).0, seen);
+    }
+
+    #[test]
+    fn test_stalls_hold_the_link() {
+        let mut ctx = ProgramBuilder::default();
+        // Each element occupies the link for 4 cycles.
+        let (snd, rcv) = ctx.bounded_with_bandwidth::<u32>(8, 1, 1, NonZeroU64::new(8).unwrap());
+        ctx.inject_faults(
+            snd.id(),
+            FaultSpecBuilder::default()
+                .stalls(vec![StallWindow { start: 10, end: 20 }])
+                .build()
+                .unwrap(),
+        );
+        ctx.add_child(GeneratorContext::new(|| 0..6u32, snd));
+        let (collector, handle) = CollectorContext::new(rcv);
+        ctx.add_child(collector);
+        let executed = ctx
+            .initialize(InitializationOptions::default())
+            .unwrap()
+            .run(RunOptions::default());
+        assert!(executed.passed());
+
+        // The fourth element waits out the stall, and everything behind it still waits for the link.
+        let times: Vec<_> = handle.times().iter().map(|time| time.time()).collect();
+        assert_eq!(times, vec![4, 8, 12, 24, 28, 32]);
+        assert_eq!(executed.fault_summary()[0].stalled, 1);
+    }
+
+    #[test]
+    fn test_spikes_overtake_out_of_order() {
+        let mut ctx = ProgramBuilder::default();
+        let (snd, rcv) = ctx.bounded_out_of_order::<u32>(8, 1, 1);
+        ctx.inject_faults(
+            snd.id(),
+            FaultSpecBuilder::default()
+                .seed(5)
+                .spike_probability(0.5)
+                .spike_latency(10)
+                .build()
+                .unwrap(),
+        );
+        ctx.add_child(GeneratorContext::new(|| 0..32u32, snd));
+        let (collector, handle) = CollectorContext::new(rcv);
+        ctx.add_child(collector);
+        let executed = ctx
+            .initialize(InitializationOptions::default())
+            .unwrap()
+            .run(RunOptions::default());
+        assert!(executed.passed());
+        assert!(executed.fault_summary()[0].latency_spikes > 0);
+
+        // Elements which weren't delayed are delivered ahead of earlier ones which were.
+        let times = handle.times();
+        assert!(times.windows(2).all(|pair| pair[0] <= pair[1]));
+        let mut values = handle.values();
+        assert_ne!(values, (0..32).collect::<Vec<_>>());
+        values.sort();
+        assert_eq!(values, (0..32).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_fault_plan() {
+        let mut ctx = ProgramBuilder::default();
+        let (snd, rcv) = ctx.bounded::<u32>(4);
+        let plan = FaultPlan::from_json(&format!(
+            r#"{{"channels": [{{"channel": {}, "corrupt_probability": 0.5}}]}}"#,
+            serde_json::to_string(&snd.id()).unwrap()
+        ))
+        .unwrap();
+        assert_eq!(plan.channels[0].channel, snd.id());
+        ctx.apply_fault_plan(&plan);
+        ctx.add_child(GeneratorContext::new(|| 0..8u32, snd));
+        ctx.add_child(ConsumerContext::new(rcv));
+
+        // The plan corrupts elements, but no corruptor was registered.
+        assert!(matches!(
+            ctx.initialize(InitializationOptions::default()),
+            Err(InitializationError::InvalidFaults(_, _))
+        ));
+    }
+}
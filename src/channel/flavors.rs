@@ -1,7 +1,12 @@
-#[derive(Clone, Copy, Debug)]
-pub(crate) enum ChannelFlavor {
+/// The implementation strategy used for a channel.
+/// Normally chosen by flavor inference, but can be forced via [crate::simulation::ProgramBuilder::force_flavor].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ChannelFlavor {
+    /// The channel is not part of a cycle, so receivers may block without checking the sender's time.
     Acyclic,
+    /// The channel may be part of a cycle, and must synchronize with its partner's time.
     Cyclic,
+    /// The channel has no receiver, and all writes are discarded.
     Void,
 }
 
@@ -2,48 +2,141 @@
 // The key feature we need here is to be able to set up a graph (i.e. pass ownership around)
 // And later swap the underlying implementation of the sender/receivers.
 
-use std::sync::Arc;
+use std::{any::Any, sync::Arc};
 
-use crate::shim::channel;
+use crate::shim::{channel, Mutex};
 
 use crate::datastructures::{sync_unsafe::SyncUnsafeCell, Identifier, Time};
 
 use super::{
-    channel_spec::ChannelSpec,
+    channel_spec::{Attachment, ChannelSpec},
+    faults::{Corruptor, FaultCounts, FaultInjector, FaultSpec, FaultSummary},
+    probe::{ChannelProbes, LatencyProbe},
     receiver::{uninitialized::UninitializedReceiver, *},
     sender::{
         bounded::{BoundedAcyclicSender, BoundedCyclicSender, BoundedData},
         unbounded::UnboundedSender,
         uninitialized::UninitializedSender,
         void::VoidSender,
-        SenderData, SenderImpl,
+        LatencyFn, LinkData, SenderData, SenderImpl, VariableLatency,
     },
-    ChannelElement, ChannelFlavor, ChannelID,
+    tap::{TapError, TapFile, TapFormatter, TapPayload, TapSlot, TapWriter},
+    ChannelElement, ChannelFlavor, ChannelID, TaggedElement,
 };
 
-pub(crate) trait ChannelHandle {
+pub(crate) trait ChannelHandle: Send + Sync {
     fn set_flavor(&self, flavor: ChannelFlavor);
     fn sender(&self) -> Option<Identifier>;
     fn receiver(&self) -> Option<Identifier>;
     fn id(&self) -> ChannelID;
     fn spec(&self) -> Arc<ChannelSpec>;
+
+    /// Returns the sender to an unattached state, after the context owning it has been dropped.
+    fn detach_sender(&self);
+
+    /// Returns the receiver to an unattached state, after the context owning it has been dropped.
+    fn detach_receiver(&self);
+
+    /// Returns the sender to an uninitialized state attached to a previously recorded context, such as after a temporary endpoint was dropped.
+    fn reattach_sender(&self, attachment: Attachment);
+
+    /// Returns the receiver to an uninitialized state attached to a previously recorded context.
+    fn reattach_receiver(&self, attachment: Attachment);
+
+    /// Injects faults on the channel. Must be called before the flavor is set.
+    fn set_faults(&self, faults: FaultSpec);
+
+    /// Whether [ChannelData::set_corruptor] was called, which is required to corrupt elements.
+    fn has_corruptor(&self) -> bool;
+
+    /// The faults injected so far, if the channel has any configured.
+    fn fault_summary(&self) -> Option<FaultSummary>;
+
+    /// Attaches a probe which starts or ends on the channel. Must be called before the flavor is set.
+    fn add_probe(&self, probe: Arc<LatencyProbe>);
+
+    /// Starts recording the channel's traffic to a file. Must be called after the flavor is set.
+    fn set_tap(&self, file: TapFile, payload: TapPayload) -> Result<(), TapError>;
+
+    /// Boxes the channel as an `Arc<ChannelData<T>>`, so that its element type can be recovered when minting new endpoints for it.
+    fn into_any(self: Arc<Self>) -> Box<dyn Any>
+    where
+        Self: 'static;
 }
 
 pub(crate) struct ChannelData<T: Clone> {
     sender: SyncUnsafeCell<SenderImpl<T>>,
     receiver: SyncUnsafeCell<ReceiverImpl<T>>,
     channel_spec: Arc<ChannelSpec>,
+
+    // Elements placed into the channel when its flavor is set, before any context runs.
+    initial: Mutex<Vec<ChannelElement<T>>>,
+
+    // Measures elements in bits, for channels with a limited bandwidth.
+    sizer: Option<fn(&T) -> usize>,
+
+    // Per-element latencies, bounded below by the spec's send latency.
+    latency_fn: Option<LatencyFn<T>>,
+
+    // Faults injected by the sender, which may be configured after the channel is constructed.
+    faults: Mutex<Option<FaultSpec>>,
+    corruptor: Mutex<Option<Corruptor<T>>>,
+    fault_counts: Arc<FaultCounts>,
+
+    // Latency probes which start or end on this channel.
+    probes: Mutex<ChannelProbes>,
+
+    // Shared by both ends, so that a tap can be installed after they are created.
+    tap: TapSlot<T>,
+    debug_formatter: Mutex<Option<TapFormatter<T>>>,
+    serde_formatter: Mutex<Option<TapFormatter<T>>>,
 }
 
 impl<T: Clone> ChannelData<T> {
     pub fn new(spec: Arc<ChannelSpec>) -> Self {
+        Self::with_initial(spec, vec![])
+    }
+
+    pub fn with_initial(spec: Arc<ChannelSpec>, initial: Vec<ChannelElement<T>>) -> Self {
         Self {
             sender: SyncUnsafeCell::new(UninitializedSender::new(spec.clone()).into()),
             receiver: SyncUnsafeCell::new(UninitializedReceiver::new(spec.clone()).into()),
             channel_spec: spec,
+            initial: Mutex::new(initial),
+            sizer: None,
+            latency_fn: None,
+            faults: Mutex::new(None),
+            corruptor: Mutex::new(None),
+            fault_counts: Default::default(),
+            probes: Mutex::new(ChannelProbes::default()),
+            tap: Default::default(),
+            debug_formatter: Mutex::new(None),
+            serde_formatter: Mutex::new(None),
         }
     }
 
+    pub fn with_latency_fn(mut self, latency: LatencyFn<T>) -> Self {
+        self.latency_fn = Some(latency);
+        self
+    }
+
+    pub fn with_sizer(mut self, sizer: fn(&T) -> usize) -> Self {
+        self.sizer = Some(sizer);
+        self
+    }
+
+    pub fn set_corruptor(&self, corruptor: Corruptor<T>) {
+        *self.corruptor.lock().unwrap() = Some(corruptor);
+    }
+
+    pub fn set_debug_formatter(&self, formatter: TapFormatter<T>) {
+        *self.debug_formatter.lock().unwrap() = Some(formatter);
+    }
+
+    pub fn set_serde_formatter(&self, formatter: TapFormatter<T>) {
+        *self.serde_formatter.lock().unwrap() = Some(formatter);
+    }
+
     #[allow(clippy::mut_from_ref)]
     pub(super) fn sender(&self) -> &mut SenderImpl<T> {
         unsafe { self.sender.get().as_mut().unwrap() }
@@ -55,38 +148,80 @@ impl<T: Clone> ChannelData<T> {
     }
 }
 
-impl<T: Clone> ChannelHandle for ChannelData<T> {
+impl<T: Clone + Send + Sync> ChannelHandle for ChannelData<T> {
     fn set_flavor(&self, flavor: ChannelFlavor) {
         let make_receiver_data = |underlying| ReceiverData::<T> {
             spec: self.channel_spec.make_inline(),
             underlying,
             head: None,
+            reorder: self.channel_spec.out_of_order().then(Default::default),
+            lookahead: Default::default(),
+            tags: Default::default(),
+            last_tag: None,
+            tap: self.tap.clone(),
+            probes: self.probes.lock().unwrap().ends.clone(),
         };
         let make_sender_data = |underlying| SenderData::<T> {
             spec: self.channel_spec.make_inline(),
             underlying,
+            link: self
+                .sizer
+                .zip(self.channel_spec.bandwidth())
+                .map(|(sizer, bandwidth)| LinkData::new(sizer, bandwidth)),
+            variable_latency: self
+                .latency_fn
+                .clone()
+                .map(|latency| VariableLatency::new(latency, !self.channel_spec.out_of_order())),
+            faults: self.faults.lock().unwrap().clone().map(|faults| {
+                FaultInjector::new(
+                    self.id(),
+                    faults,
+                    self.corruptor.lock().unwrap().clone(),
+                    self.fault_counts.clone(),
+                    !self.channel_spec.out_of_order(),
+                )
+            }),
+            tap: self.tap.clone(),
+            probes: self.probes.lock().unwrap().starts.clone(),
+            tag: None,
+            channel: self.id(),
+            next_tag: 0,
+            last_arrival: Time::new(0),
+        };
+        // Returns the number of seeded elements, which count against the capacity of bounded channels.
+        let seed = |underlying: &channel::Sender<TaggedElement<T>>| {
+            let initial = std::mem::take(&mut *self.initial.lock().unwrap());
+            let seeded = initial.len();
+            for element in initial {
+                underlying
+                    .send((element, None))
+                    .unwrap_or_else(|_| panic!("Could not seed channel {:?}", self.id()));
+            }
+            seeded
         };
         match self.channel_spec.capacity() {
             Some(capacity) => {
                 cfg_if::cfg_if! {
                     if #[cfg(feature = "unbounded-channels")] {
-                        let (tx, rx) = channel::unbounded::<ChannelElement<T>>();
+                        let (tx, rx) = channel::unbounded::<TaggedElement<T>>();
                         let (resp_t, resp_r) = channel::unbounded::<Time>();
                         // So that Rust doesn't complain about capacity being unused
                         let _ = capacity;
                     } else {
-                        let (tx, rx) = channel::bounded::<ChannelElement<T>>(capacity);
+                        let (tx, rx) = channel::bounded::<TaggedElement<T>>(capacity);
                         let (resp_t, resp_r) = channel::bounded::<Time>(capacity);
                     }
                 }
+                let seeded = seed(&tx);
                 match flavor {
                     ChannelFlavor::Acyclic => {
                         *self.sender() = BoundedAcyclicSender {
                             data: make_sender_data(tx),
                             bound: BoundedData {
                                 resp: resp_r,
-                                send_receive_delta: 0,
+                                send_receive_delta: seeded,
                             },
+                            next_response: None,
                         }
                         .into();
                         *self.receiver() = BoundedAcyclicReceiver {
@@ -100,7 +235,7 @@ impl<T: Clone> ChannelHandle for ChannelData<T> {
                             data: make_sender_data(tx),
                             bound: BoundedData {
                                 resp: resp_r,
-                                send_receive_delta: 0,
+                                send_receive_delta: seeded,
                             },
                             next_available: None,
                         }
@@ -121,6 +256,7 @@ impl<T: Clone> ChannelHandle for ChannelData<T> {
                 match flavor {
                     ChannelFlavor::Acyclic => {
                         let (snd, rcv) = channel::unbounded();
+                        seed(&snd);
 
                         *self.sender() = UnboundedSender {
                             data: make_sender_data(snd),
@@ -134,6 +270,7 @@ impl<T: Clone> ChannelHandle for ChannelData<T> {
                     }
                     ChannelFlavor::Cyclic => {
                         let (snd, rcv) = channel::unbounded();
+                        seed(&snd);
 
                         *self.sender() = UnboundedSender {
                             data: make_sender_data(snd),
@@ -166,4 +303,71 @@ impl<T: Clone> ChannelHandle for ChannelData<T> {
     fn spec(&self) -> Arc<ChannelSpec> {
         self.channel_spec.clone()
     }
+
+    fn detach_sender(&self) {
+        self.reattach_sender(Attachment::default());
+    }
+
+    fn detach_receiver(&self) {
+        self.reattach_receiver(Attachment::default());
+    }
+
+    fn reattach_sender(&self, attachment: Attachment) {
+        self.channel_spec.restore_sender(attachment);
+        *self.sender() = UninitializedSender::new(self.channel_spec.clone()).into();
+    }
+
+    fn reattach_receiver(&self, attachment: Attachment) {
+        self.channel_spec.restore_receiver(attachment);
+        *self.receiver() = UninitializedReceiver::new(self.channel_spec.clone()).into();
+    }
+
+    fn set_faults(&self, faults: FaultSpec) {
+        *self.faults.lock().unwrap() = Some(faults);
+    }
+
+    fn has_corruptor(&self) -> bool {
+        self.corruptor.lock().unwrap().is_some()
+    }
+
+    fn fault_summary(&self) -> Option<FaultSummary> {
+        self.faults
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|_| self.fault_counts.summarize(self.id()))
+    }
+
+    fn add_probe(&self, probe: Arc<LatencyProbe>) {
+        let mut probes = self.probes.lock().unwrap();
+        if probe.start() == self.id() {
+            probes.starts.push(probe.clone());
+        }
+        if probe.end() == self.id() {
+            probes.ends.push(probe);
+        }
+    }
+
+    fn set_tap(&self, file: TapFile, payload: TapPayload) -> Result<(), TapError> {
+        let formatter = match payload {
+            TapPayload::None => None,
+            TapPayload::Debug => *self.debug_formatter.lock().unwrap(),
+            TapPayload::Serde => Some(
+                self.serde_formatter
+                    .lock()
+                    .unwrap()
+                    .ok_or(TapError::NoSerializer(self.id()))?,
+            ),
+        };
+        self.tap
+            .set(TapWriter::new(self.id(), file, formatter))
+            .map_err(|_| TapError::DuplicateTap(self.id()))
+    }
+
+    fn into_any(self: Arc<Self>) -> Box<dyn Any>
+    where
+        Self: 'static,
+    {
+        Box::new(self)
+    }
 }
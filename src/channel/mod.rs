@@ -1,5 +1,6 @@
 //! Channels in DAM are Single-Producer Single-Consumer (SPSC) constructs, and are the primary form of communication between [super::context::Context]s.
 //! Blocking operations automatically handle time manipulation when used with blocking operations such as dequeue and enqueue.
+//! Multi-producer and multi-consumer channels are composed out of SPSC lanes, see [multi].
 
 mod channel_id;
 
@@ -11,15 +12,21 @@ mod events;
 
 mod flavors;
 
-pub(crate) use flavors::*;
+pub use flavors::*;
+pub use options::ChannelOptions;
 
 pub(crate) mod channel_spec;
+mod options;
 mod receiver;
 mod sender;
 
 pub(crate) mod handle;
 
 pub mod adapters;
+pub mod faults;
+pub mod multi;
+pub mod probe;
+pub mod tap;
 
 use std::sync::Arc;
 use thiserror::Error;
@@ -38,10 +45,26 @@ use self::handle::ChannelHandle;
 
 use self::receiver::terminated::TerminatedReceiver;
 use self::receiver::{ReceiverFlavor, ReceiverImpl};
-use self::sender::terminated::TerminatedSender;
+use self::sender::{closed::ClosedSender, terminated::TerminatedSender};
 
 use self::sender::{SenderFlavor, SenderImpl};
 
+/// Side-band metadata identifying the transaction an element belongs to, used by [probe] to measure end-to-end latencies.
+/// Channels carry tags alongside their elements, see [Receiver::last_tag] and [Sender::set_tag].
+/// Tags are numbered by the channel that started the transaction, so they are the same on every run of a program.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TransactionTag {
+    /// The channel which tagged the transaction
+    pub source: ChannelID,
+    /// Counts the transactions tagged by the source channel, starting from 0
+    pub id: u64,
+    /// When the transaction began
+    pub origin: Time,
+}
+
+/// What is actually sent through a channel: an element and the transaction it belongs to.
+pub(crate) type TaggedElement<T> = (ChannelElement<T>, Option<TransactionTag>);
+
 /// An item with an associated timestamp -- used for sending/receiving objects on channels and modifying contexts' owned times.
 #[derive(Clone, Debug)]
 pub struct ChannelElement<T> {
@@ -63,16 +86,21 @@ impl<T> ChannelElement<T> {
         self.time = std::cmp::max(self.time, new_time);
     }
 
+    /// Transforms the data, keeping the timestamp.
+    pub fn map<U>(self, func: impl FnOnce(T) -> U) -> ChannelElement<U> {
+        ChannelElement {
+            time: self.time,
+            data: func(self.data),
+        }
+    }
+
     /// Converts between ChannelElement types, where the underlying types are compatible.
     /// We can't blanket implement this via From/Into because there are existing impls
     pub fn convert<U>(self) -> ChannelElement<U>
     where
         T: Into<U>,
     {
-        ChannelElement {
-            time: self.time,
-            data: self.data.into(),
-        }
+        self.map(Into::into)
     }
 
     /// Attempts to convert between ChannelElement types.
@@ -100,6 +128,32 @@ pub enum PeekResult<T> {
     Closed,
 }
 
+/// The result of peeking at several elements at once, see [Receiver::peek_n].
+#[derive(Clone, Debug)]
+pub struct PeekWindow<T> {
+    /// The visible elements, in order. Note: Their timestamps MAY be in the future.
+    pub elements: Vec<ChannelElement<T>>,
+
+    /// What is known about the elements after the window.
+    pub end: WindowEnd,
+}
+
+/// How a [PeekWindow] ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WindowEnd {
+    /// The window holds as many elements as were requested.
+    Full,
+
+    /// No further element will arrive prior to or at the timestamp, as with [PeekResult::Nothing].
+    Nothing(Time),
+
+    /// Further elements may still arrive at time 0, so nothing can be promised until the sender advances.
+    Pending,
+
+    /// There are no further elements.
+    Closed,
+}
+
 impl<T> TryInto<Result<ChannelElement<T>, DequeueError>> for PeekResult<T> {
     type Error = ();
 
@@ -128,6 +182,8 @@ impl<T: DAMType> Sender<T> {
         // log_event(&{SendEvent::AttachSender(self.id, sender.id())});
         if let SenderImpl::Uninitialized(uninit) = self.under() {
             uninit.attach_sender(sender);
+            self.underlying
+                .set_debug_formatter(self::tap::debug_payload::<T>);
         } else {
             panic!("Cannot attach a context to an initialized sender!");
         }
@@ -145,10 +201,60 @@ impl<T: DAMType> Sender<T> {
         res
     }
 
+    /// Writes a sequence of elements, advancing `interval` cycles after each one.
+    /// This behaves exactly like calling [Sender::enqueue] and [TimeManager::incr_cycles] in a loop,
+    /// except that free slots are reserved in one step instead of being checked for every element.
+    /// Elements are pulled from the iterator just before they are sent, so they can be timestamped using the current time.
+    pub fn enqueue_many(
+        &self,
+        manager: &TimeManager,
+        data: impl IntoIterator<Item = ChannelElement<T>>,
+        interval: u64,
+    ) -> Result<(), EnqueueError> {
+        log_event(&SendEvent::EnqueueStart(self.id())).unwrap();
+        let res = self
+            .under()
+            .enqueue_many(manager, &mut data.into_iter(), interval);
+        log_event(&SendEvent::EnqueueFinish(self.id())).unwrap();
+        res
+    }
+
+    /// Marks the elements enqueued from now on as part of a transaction, until the tag is set again.
+    /// Contexts forward a transaction by setting the tag of the element an output was computed from, see [Receiver::last_tag].
+    pub fn set_tag(&self, tag: Option<TransactionTag>) {
+        self.under().set_tag(tag)
+    }
+
     /// Advances time forward until the channel is not full.
     pub fn wait_until_available(&self, manager: &TimeManager) -> Result<(), EnqueueError> {
         self.under().wait_until_available(manager)
     }
+
+    /// Closes the channel at a given time, which must not be earlier than the current time or the arrival of an element already sent.
+    /// The receiver sees [PeekResult::Closed] once it reaches that time, instead of whenever the sender is dropped.
+    /// Enqueueing afterwards fails with [EnqueueError::Closed], and closing again fails with [CloseError::AlreadyClosed].
+    pub fn close_at(&self, manager: &TimeManager, time: Time) -> Result<(), CloseError> {
+        if self.underlying.spec().close_time().is_some() {
+            return Err(CloseError::AlreadyClosed);
+        }
+        let current = manager.tick();
+        if time < current {
+            return Err(CloseError::InThePast {
+                requested: time,
+                current,
+            });
+        }
+        let last_arrival = self.under().last_arrival();
+        if time < last_arrival {
+            return Err(CloseError::BeforeLastArrival {
+                requested: time,
+                last_arrival,
+            });
+        }
+        self.underlying.spec().close_at(time)?;
+        *self.under() = ClosedSender::default().into();
+        Ok(())
+    }
 }
 
 impl<T: Clone> Drop for Sender<T> {
@@ -190,6 +296,27 @@ impl<T: DAMType> Receiver<T> {
         self.under().peek()
     }
 
+    /// Peeks at the next `count` elements without advancing time or blocking on the sender.
+    /// The window may be shorter if fewer elements have been sent so far, in which case [PeekWindow::end] says how long nothing else can arrive for.
+    pub fn peek_n(&self, count: usize) -> PeekWindow<T> {
+        log_event(&ReceiverEvent::Peek(self.id())).unwrap();
+        self.under().peek_n(count)
+    }
+
+    /// Advances forward in time until `count` elements are in the channel, and returns them without popping.
+    /// Returns fewer if the channel closes first, or a DequeueError if it closes before any element arrives.
+    /// `count` is clamped to the channel's capacity, as the sender could never fill a larger window.
+    pub fn peek_next_n(
+        &self,
+        manager: &TimeManager,
+        count: usize,
+    ) -> Result<Vec<ChannelElement<T>>, DequeueError> {
+        log_event(&ReceiverEvent::PeekNextStart(self.id())).unwrap();
+        let result = self.under().peek_next_n(manager, count);
+        log_event(&ReceiverEvent::PeekNextFinish(self.id())).unwrap();
+        result
+    }
+
     /// Advances forward in time until there is an element in the channel, and returns that value.
     /// If the channel is closed before another element is sent, then it returns a DequeueError instead.
     pub fn peek_next(&self, manager: &TimeManager) -> Result<ChannelElement<T>, DequeueError> {
@@ -199,6 +326,32 @@ impl<T: DAMType> Receiver<T> {
         result
     }
 
+    /// Pops up to `count` elements, advancing `interval` cycles after each one.
+    /// This behaves exactly like calling [Receiver::dequeue] and [TimeManager::incr_cycles] in a loop, stopping early if the channel closes.
+    /// Elements which have already been sent are popped as a run, without synchronizing with the sender for each one.
+    /// Only returns a DequeueError if the channel was closed before any element was dequeued.
+    pub fn dequeue_many(
+        &self,
+        manager: &TimeManager,
+        count: usize,
+        interval: u64,
+    ) -> Result<Vec<ChannelElement<T>>, DequeueError> {
+        log_event(&ReceiverEvent::DequeueStart(self.id())).unwrap();
+        let result = self.under().dequeue_many(manager, count, interval);
+        log_event(&ReceiverEvent::DequeueFinish(self.id())).unwrap();
+        result
+    }
+
+    /// The transaction of the element most recently dequeued, if it is being tracked.
+    pub fn last_tag(&self) -> Option<TransactionTag> {
+        self.under().last_tag()
+    }
+
+    /// The time that the sender closed the channel at via [Sender::close_at], if it has done so.
+    pub fn close_time(&self) -> Option<Time> {
+        self.underlying.spec().close_time()
+    }
+
     /// Advances forward in time until there is an element in the channel, and pops that value.
     /// If the channel is closed before another element is sent, then it returns a DequeueError instead.
     pub fn dequeue(&self, manager: &TimeManager) -> Result<ChannelElement<T>, DequeueError> {
@@ -227,6 +380,10 @@ pub enum DequeueError {
     /// Marks that the channel was closed without any further values.
     #[error("Dequeued from a simulation-closed channel!")]
     Closed,
+
+    /// Marks that a value was received, but an adapter failed to convert it.
+    #[error("Failed to convert a dequeued value: {0}")]
+    Conversion(String),
 }
 
 /// Errors that can occur when enqueueing into a channel.
@@ -236,3 +393,211 @@ pub enum EnqueueError {
     #[error("Enqueued to a simulation-closed channel!")]
     Closed,
 }
+
+/// Errors that can occur when closing a channel with [Sender::close_at].
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseError {
+    /// Channels cannot be closed before the sender's current time.
+    #[error("Cannot close a channel at {requested:?}, before the current time {current:?}")]
+    InThePast {
+        /// The time the channel was to be closed at
+        requested: Time,
+        /// The time of the sender
+        current: Time,
+    },
+
+    /// Channels cannot be closed before an element which was already sent arrives.
+    #[error("Cannot close a channel at {requested:?}, before its last element arrives at {last_arrival:?}")]
+    BeforeLastArrival {
+        /// The time the channel was to be closed at
+        requested: Time,
+        /// The time the last element sent arrives at the receiver
+        last_arrival: Time,
+    },
+
+    /// Channels can only be closed once.
+    #[error("The channel was already closed")]
+    AlreadyClosed,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{
+        simulation::{InitializationOptionsBuilder, ProgramBuilder},
+        utility_contexts::{CheckerContext, FunctionContext},
+    };
+
+    use super::{ChannelElement, ChannelFlavor, WindowEnd};
+
+    /// Returns the arrival times seen by the receiver, and the times that the receiver and sender finished at.
+    fn stream(
+        batched: bool,
+        flavor_inference: bool,
+        capacity: Option<usize>,
+    ) -> (Vec<u64>, u64, u64) {
+        let mut ctx = ProgramBuilder::default();
+        let (snd, rcv) = match capacity {
+            Some(capacity) => ctx.bounded_with_latency::<u32>(capacity, 3, 2),
+            None => ctx.unbounded_with_latency::<u32>(3, 2),
+        };
+        let seen = Arc::new(Mutex::new((vec![], 0, 0)));
+
+        let mut sender = FunctionContext::default();
+        snd.attach_sender(&sender);
+        let sent_handle = seen.clone();
+        sender.set_run(move |time| {
+            let elements = (0..64).map(|i| ChannelElement::new(time.tick(), i));
+            if batched {
+                snd.enqueue_many(time, elements, 2).unwrap();
+            } else {
+                for element in elements {
+                    snd.enqueue(time, element).unwrap();
+                    time.incr_cycles(2);
+                }
+            }
+            sent_handle.lock().unwrap().2 = time.tick().time();
+        });
+        ctx.add_child(sender);
+
+        let mut receiver = FunctionContext::default();
+        rcv.attach_receiver(&receiver);
+        let seen_handle = seen.clone();
+        receiver.set_run(move |time| {
+            let mut times = vec![];
+            if batched {
+                while let Ok(elements) = rcv.dequeue_many(time, 5, 3) {
+                    times.extend(elements.iter().map(|element| element.time.time()));
+                }
+            } else {
+                while let Ok(element) = rcv.dequeue(time) {
+                    times.push(element.time.time());
+                    time.incr_cycles(3);
+                }
+            }
+            let mut seen = seen_handle.lock().unwrap();
+            seen.0 = times;
+            seen.1 = time.tick().time();
+        });
+        ctx.add_child(receiver);
+
+        let executed = ctx
+            .initialize(
+                InitializationOptionsBuilder::default()
+                    .run_flavor_inference(flavor_inference)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap()
+            .run(Default::default());
+        assert!(executed.passed());
+        let result = seen.lock().unwrap().clone();
+        result
+    }
+
+    #[test]
+    fn test_batched_matches_single() {
+        for flavor_inference in [false, true] {
+            for capacity in [Some(2), Some(8), None] {
+                let single = stream(false, flavor_inference, capacity);
+                assert_eq!(single.0.len(), 64);
+                assert_eq!(stream(true, flavor_inference, capacity), single);
+            }
+        }
+    }
+
+    fn windows(flavor_inference: bool) {
+        let mut ctx = ProgramBuilder::default();
+        let (snd, rcv) = ctx.bounded::<u32>(4);
+
+        let mut sender = FunctionContext::default();
+        snd.attach_sender(&sender);
+        sender.set_run(move |time| {
+            for i in 0..32u32 {
+                snd.enqueue(time, ChannelElement::new(time.tick(), i))
+                    .unwrap();
+                time.incr_cycles(1 + u64::from(i % 5));
+            }
+        });
+        ctx.add_child(sender);
+
+        let mut receiver = FunctionContext::default();
+        rcv.attach_receiver(&receiver);
+        receiver.set_run(move |time| {
+            // Only as many elements as the channel holds can be waited for.
+            let window = rcv.peek_next_n(time, 8).unwrap();
+            assert_eq!(window.len(), 4);
+
+            let mut next = 0;
+            while let Ok(window) = rcv.peek_next_n(time, 3) {
+                let values: Vec<_> = window.iter().map(|element| element.data).collect();
+                assert_eq!(values, (next..32).take(3).collect::<Vec<_>>());
+                assert!(time.tick() >= window.last().unwrap().time);
+
+                // Peeking further never shows more than has been sent.
+                let wide = rcv.peek_n(8);
+                assert!(wide.elements.len() <= 4);
+                assert!(wide.elements.len() >= 3 || next + 3 > 32);
+                if let WindowEnd::Nothing(proof) = wide.end {
+                    if let Some(element) = rcv.peek_n(9).elements.get(wide.elements.len()) {
+                        assert!(element.time > proof);
+                    }
+                }
+
+                let element = rcv.dequeue(time).unwrap();
+                assert_eq!(element.data, next);
+                next += 1;
+                time.incr_cycles(u64::from(next % 3));
+            }
+            assert_eq!(next, 32);
+        });
+        ctx.add_child(receiver);
+
+        let executed = ctx
+            .initialize(
+                InitializationOptionsBuilder::default()
+                    .run_flavor_inference(flavor_inference)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap()
+            .run(Default::default());
+        assert!(executed.passed());
+    }
+
+    #[test]
+    fn test_peek_windows() {
+        windows(false);
+        windows(true);
+    }
+
+    #[test]
+    fn test_acyclic_wait_before_enqueue() {
+        let mut ctx = ProgramBuilder::default();
+        let (snd, rcv) = ctx.bounded::<u32>(2);
+        let id = snd.id();
+        ctx.force_flavor(id, ChannelFlavor::Acyclic);
+
+        let mut sender = FunctionContext::default();
+        snd.attach_sender(&sender);
+        sender.set_run(move |time| {
+            for i in 0..16u32 {
+                // Waiting for space any number of times must only take up a single response.
+                snd.wait_until_available(time).unwrap();
+                snd.wait_until_available(time).unwrap();
+                snd.enqueue(time, ChannelElement::new(time.tick(), i))
+                    .unwrap();
+            }
+        });
+        ctx.add_child(sender);
+        ctx.add_child(CheckerContext::new(|| 0..16u32, rcv));
+
+        let initialized = ctx.initialize(Default::default()).unwrap();
+        assert_eq!(
+            initialized.report().channel(id).unwrap().flavor,
+            ChannelFlavor::Acyclic
+        );
+        assert!(initialized.run(Default::default()).passed());
+    }
+}
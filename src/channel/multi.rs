@@ -0,0 +1,622 @@
+//! Multi-producer, multi-consumer, and broadcast channels.
+//! These are built out of one SPSC lane per producer (or consumer), so each lane is still an ordinary channel
+//! as far as flavor inference, checking, and DOT export are concerned.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use thiserror::Error;
+
+use crate::{context::Context, datastructures::Time, types::DAMType, view::TimeManager};
+
+use super::{
+    adapters::{RecvAdapter, SendAdapter},
+    handle::ChannelHandle,
+    ChannelElement, DequeueError, EnqueueError, PeekResult, Receiver, Sender,
+};
+
+/// How a [MergeReceiver] chooses between lanes whose next elements have the same timestamp.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Arbitration {
+    /// Rotates through the lanes, starting after the lane that was last dequeued from.
+    #[default]
+    RoundRobin,
+
+    /// Always prefers the lane with the lowest index.
+    FixedPriority,
+
+    /// Prefers the lane whose sending context has the lowest identifier.
+    BySenderId,
+}
+
+/// Ways that constructing a multi-lane channel can fail
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MultiChannelError {
+    /// Multi-producer, multi-consumer and broadcast channels need at least one lane
+    #[error("Multi-lane channels need at least one lane")]
+    NoLanes,
+}
+
+enum Selection<T> {
+    Lane(usize, ChannelElement<T>),
+    Nothing(Time),
+    Closed,
+}
+
+/// The receive side of a multi-producer channel, created by [crate::simulation::ProgramBuilder::mpsc].
+/// Elements from all lanes are merged in timestamp order, with ties broken by the [Arbitration] policy.
+pub struct MergeReceiver<T: Clone> {
+    lanes: Vec<Receiver<T>>,
+    arbitration: Arbitration,
+    next_lane: AtomicUsize,
+}
+
+impl<T: DAMType> MergeReceiver<T> {
+    pub(crate) fn new(lanes: Vec<Receiver<T>>, arbitration: Arbitration) -> Self {
+        Self {
+            lanes,
+            arbitration,
+            next_lane: AtomicUsize::new(0),
+        }
+    }
+
+    /// The number of producers feeding into this receiver.
+    pub fn num_lanes(&self) -> usize {
+        self.lanes.len()
+    }
+
+    /// Registers a context as the receiver of every lane.
+    pub fn attach_receiver(&self, receiver: &dyn Context) {
+        self.lanes
+            .iter()
+            .for_each(|lane| lane.attach_receiver(receiver));
+    }
+
+    fn select(&self) -> Selection<T> {
+        // No lane can produce anything at or before the horizon unless it's already visible.
+        let mut horizon: Option<Time> = None;
+        let mut candidates = vec![];
+        for (index, lane) in self.lanes.iter().enumerate() {
+            match lane.peek() {
+                PeekResult::Something(element) => candidates.push((index, element)),
+                PeekResult::Nothing(time) => {
+                    horizon = Some(horizon.map_or(time, |horizon| horizon.min(time)))
+                }
+                PeekResult::Closed => {}
+            }
+        }
+
+        let Some(earliest) = candidates.iter().map(|(_, element)| element.time).min() else {
+            return horizon.map_or(Selection::Closed, Selection::Nothing);
+        };
+        if let Some(horizon) = horizon.filter(|horizon| earliest > *horizon) {
+            return Selection::Nothing(horizon);
+        }
+
+        candidates.retain(|(_, element)| element.time == earliest);
+        let position = match self.arbitration {
+            Arbitration::FixedPriority => 0,
+            Arbitration::RoundRobin => {
+                let start = self.next_lane.load(Ordering::Relaxed);
+                let lanes = self.lanes.len();
+                (0..candidates.len())
+                    .min_by_key(|position| (candidates[*position].0 + lanes - start) % lanes)
+                    .unwrap()
+            }
+            Arbitration::BySenderId => (0..candidates.len())
+                .min_by_key(|position| {
+                    let lane = &self.lanes[candidates[*position].0];
+                    ChannelHandle::sender(lane.underlying.as_ref()).map(|id| id.id)
+                })
+                .unwrap(),
+        };
+        let (index, element) = candidates.swap_remove(position);
+        Selection::Lane(index, element)
+    }
+
+    /// Peeks the earliest element across all lanes. See [Receiver::peek].
+    pub fn peek(&self) -> PeekResult<T> {
+        match self.select() {
+            Selection::Lane(_, element) => PeekResult::Something(element),
+            Selection::Nothing(time) => PeekResult::Nothing(time),
+            Selection::Closed => PeekResult::Closed,
+        }
+    }
+
+    /// Advances forward in time until some lane has an element, and returns the earliest one.
+    /// Fails once every lane has been closed.
+    pub fn peek_next(&self, manager: &TimeManager) -> Result<ChannelElement<T>, DequeueError> {
+        self.dequeue_indexed(manager, |lane, manager| lane.peek_next(manager))
+            .map(|(_, element)| element)
+    }
+
+    /// Advances forward in time until some lane has an element, and pops the earliest one.
+    /// Fails once every lane has been closed.
+    pub fn dequeue(&self, manager: &TimeManager) -> Result<ChannelElement<T>, DequeueError> {
+        self.dequeue_with_lane(manager).map(|(_, element)| element)
+    }
+
+    /// Like [MergeReceiver::dequeue], but also returns the index of the lane the element came from.
+    pub fn dequeue_with_lane(
+        &self,
+        manager: &TimeManager,
+    ) -> Result<(usize, ChannelElement<T>), DequeueError> {
+        let result = self.dequeue_indexed(manager, |lane, manager| lane.dequeue(manager));
+        if let Ok((index, _)) = &result {
+            self.next_lane
+                .store((index + 1) % self.lanes.len(), Ordering::Relaxed);
+        }
+        result
+    }
+
+    fn dequeue_indexed(
+        &self,
+        manager: &TimeManager,
+        take: impl Fn(&Receiver<T>, &TimeManager) -> Result<ChannelElement<T>, DequeueError>,
+    ) -> Result<(usize, ChannelElement<T>), DequeueError> {
+        loop {
+            match self.select() {
+                Selection::Lane(index, _) => {
+                    return take(&self.lanes[index], manager).map(|element| (index, element))
+                }
+                Selection::Nothing(time) => manager.advance(time + 1),
+                Selection::Closed => return Err(DequeueError::Closed),
+            }
+        }
+    }
+}
+
+impl<T: DAMType> RecvAdapter<T> for MergeReceiver<T> {
+    fn attach_receiver(&self, ctx: &dyn Context) {
+        MergeReceiver::attach_receiver(self, ctx)
+    }
+
+    fn peek(&self) -> PeekResult<T> {
+        MergeReceiver::peek(self)
+    }
+
+    fn peek_next(&self, manager: &TimeManager) -> Result<ChannelElement<T>, DequeueError> {
+        MergeReceiver::peek_next(self, manager)
+    }
+
+    fn dequeue(&self, manager: &TimeManager) -> Result<ChannelElement<T>, DequeueError> {
+        MergeReceiver::dequeue(self, manager)
+    }
+}
+
+/// The send side of a multi-consumer channel, created by [crate::simulation::ProgramBuilder::spmc].
+/// [DistributeSender::enqueue] hands elements to the consumers in turn, while [DistributeSender::enqueue_to] routes explicitly.
+pub struct DistributeSender<T: Clone> {
+    lanes: Vec<Sender<T>>,
+    next_lane: AtomicUsize,
+}
+
+impl<T: DAMType> DistributeSender<T> {
+    pub(crate) fn new(lanes: Vec<Sender<T>>) -> Self {
+        Self {
+            lanes,
+            next_lane: AtomicUsize::new(0),
+        }
+    }
+
+    /// The number of consumers fed by this sender.
+    pub fn num_lanes(&self) -> usize {
+        self.lanes.len()
+    }
+
+    /// Registers a context as the sender of every lane.
+    pub fn attach_sender(&self, sender: &dyn Context) {
+        self.lanes
+            .iter()
+            .for_each(|lane| lane.attach_sender(sender));
+    }
+
+    /// Sends to the next consumer in round-robin order.
+    /// The turn only passes to the following consumer if the send succeeded.
+    pub fn enqueue(
+        &self,
+        manager: &TimeManager,
+        data: ChannelElement<T>,
+    ) -> Result<(), EnqueueError> {
+        let lane = self.next_lane.load(Ordering::Relaxed);
+        self.enqueue_to(manager, lane, data)?;
+        self.next_lane
+            .store((lane + 1) % self.lanes.len(), Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Sends to a particular consumer.
+    pub fn enqueue_to(
+        &self,
+        manager: &TimeManager,
+        lane: usize,
+        data: ChannelElement<T>,
+    ) -> Result<(), EnqueueError> {
+        self.lanes[lane].enqueue(manager, data)
+    }
+
+    /// Advances time forward until the lane that [DistributeSender::enqueue] will use next is not full.
+    pub fn wait_until_available(&self, manager: &TimeManager) -> Result<(), EnqueueError> {
+        self.lanes[self.next_lane.load(Ordering::Relaxed)].wait_until_available(manager)
+    }
+}
+
+impl<T: DAMType> SendAdapter<T> for DistributeSender<T> {
+    fn attach_sender(&self, ctx: &dyn Context) {
+        DistributeSender::attach_sender(self, ctx)
+    }
+
+    fn enqueue(&self, manager: &TimeManager, data: ChannelElement<T>) -> Result<(), EnqueueError> {
+        DistributeSender::enqueue(self, manager, data)
+    }
+
+    fn wait_until_available(&self, manager: &TimeManager) -> Result<(), EnqueueError> {
+        DistributeSender::wait_until_available(self, manager)
+    }
+}
+
+/// The send side of a broadcast channel, created by [crate::simulation::ProgramBuilder::broadcast].
+/// Every element is delivered to all receivers, so the sender is held back by the fullest branch.
+/// Unlike [crate::utility_contexts::BroadcastContext], no intermediate context is involved.
+pub struct BroadcastSender<T: Clone> {
+    branches: Vec<Sender<T>>,
+}
+
+impl<T: DAMType> BroadcastSender<T> {
+    pub(crate) fn new(branches: Vec<Sender<T>>) -> Self {
+        Self { branches }
+    }
+
+    /// The number of receivers of this broadcast.
+    pub fn num_branches(&self) -> usize {
+        self.branches.len()
+    }
+
+    /// Registers a context as the sender of every branch.
+    pub fn attach_sender(&self, sender: &dyn Context) {
+        self.branches
+            .iter()
+            .for_each(|branch| branch.attach_sender(sender));
+    }
+
+    /// Sends a copy of the element on every branch, which each add their own latency.
+    /// A branch which fails, such as one whose receiver has been dropped, does not stop the others from receiving the element,
+    /// and the first failure is returned once every branch has been tried.
+    pub fn enqueue(
+        &self,
+        manager: &TimeManager,
+        data: ChannelElement<T>,
+    ) -> Result<(), EnqueueError> {
+        // Waiting first means that every copy is sent on the same cycle.
+        let waited = self.wait_until_available(manager);
+        let sent = Self::first_error(
+            self.branches
+                .iter()
+                .map(|branch| branch.enqueue(manager, data.clone())),
+        );
+        waited.and(sent)
+    }
+
+    /// Advances time forward until no branch is full.
+    /// Like [BroadcastSender::enqueue], every branch is waited on even if an earlier one fails.
+    pub fn wait_until_available(&self, manager: &TimeManager) -> Result<(), EnqueueError> {
+        Self::first_error(
+            self.branches
+                .iter()
+                .map(|branch| branch.wait_until_available(manager)),
+        )
+    }
+
+    /// Drives every result to completion, keeping the first error.
+    fn first_error(
+        results: impl Iterator<Item = Result<(), EnqueueError>>,
+    ) -> Result<(), EnqueueError> {
+        let mut first = Ok(());
+        for result in results {
+            if first.is_ok() {
+                first = result;
+            }
+        }
+        first
+    }
+}
+
+impl<T: DAMType> SendAdapter<T> for BroadcastSender<T> {
+    fn attach_sender(&self, ctx: &dyn Context) {
+        BroadcastSender::attach_sender(self, ctx)
+    }
+
+    fn enqueue(&self, manager: &TimeManager, data: ChannelElement<T>) -> Result<(), EnqueueError> {
+        BroadcastSender::enqueue(self, manager, data)
+    }
+
+    fn wait_until_available(&self, manager: &TimeManager) -> Result<(), EnqueueError> {
+        BroadcastSender::wait_until_available(self, manager)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        channel::ChannelElement,
+        simulation::{InitializationOptionsBuilder, ProgramBuilder, RunOptions},
+        utility_contexts::{CheckerContext, FunctionContext},
+    };
+
+    use super::{Arbitration, MultiChannelError};
+
+    /// Three producers send at the same times, so the order within each timestamp is decided by arbitration.
+    fn merge(arbitration: Arbitration, flavor_inference: bool) -> Vec<(u64, u32)> {
+        let mut parent = ProgramBuilder::default();
+        let (senders, rcv) = parent.mpsc::<u32>(3, 2, arbitration).unwrap();
+        let senders: Vec<_> = senders.into_iter().enumerate().collect();
+        // Constructed in reverse, so that later lanes have lower sender ids.
+        for (lane, snd) in senders.into_iter().rev() {
+            let mut producer = FunctionContext::new();
+            snd.attach_sender(&producer);
+            producer.set_run(move |time| {
+                for i in 0..4u32 {
+                    snd.enqueue(
+                        time,
+                        ChannelElement::new(time.tick() + 1, 10 * i + lane as u32),
+                    )
+                    .unwrap();
+                    time.incr_cycles(1 + u64::from(i % 2) * lane as u64);
+                }
+            });
+            parent.add_child(producer);
+        }
+
+        let (out_snd, out_rcv) = parent.unbounded();
+        let mut consumer = FunctionContext::new();
+        rcv.attach_receiver(&consumer);
+        out_snd.attach_sender(&consumer);
+        consumer.set_run(move |time| {
+            while let Ok(element) = rcv.dequeue(time) {
+                out_snd.enqueue(time, element).unwrap();
+            }
+        });
+        parent.add_child(consumer);
+
+        let order = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let order_handle = order.clone();
+        let mut recorder = FunctionContext::new();
+        out_rcv.attach_receiver(&recorder);
+        recorder.set_run(move |time| {
+            while let Ok(element) = out_rcv.dequeue(time) {
+                order_handle
+                    .lock()
+                    .unwrap()
+                    .push((element.time.time(), element.data));
+            }
+        });
+        parent.add_child(recorder);
+
+        let executed = parent
+            .initialize(
+                InitializationOptionsBuilder::default()
+                    .run_flavor_inference(flavor_inference)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap()
+            .run(RunOptions::default());
+        assert!(executed.passed());
+        let order = order.lock().unwrap().clone();
+        order
+    }
+
+    #[test]
+    fn test_mpsc_arbitration() {
+        for flavor_inference in [false, true] {
+            for arbitration in [
+                Arbitration::RoundRobin,
+                Arbitration::FixedPriority,
+                Arbitration::BySenderId,
+            ] {
+                let order = merge(arbitration, flavor_inference);
+                assert_eq!(order.len(), 12);
+                assert!(order.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+            }
+
+            // At time 1, all three lanes are tied.
+            let first = |arbitration| -> Vec<u32> {
+                merge(arbitration, flavor_inference)[..3]
+                    .iter()
+                    .map(|(_, value)| *value)
+                    .collect()
+            };
+            assert_eq!(first(Arbitration::FixedPriority), vec![0, 1, 2]);
+            assert_eq!(first(Arbitration::BySenderId), vec![2, 1, 0]);
+        }
+    }
+
+    #[test]
+    fn test_spmc_round_robin() {
+        let mut parent = ProgramBuilder::default();
+        let (snd, receivers) = parent.spmc::<u32>(3, 2).unwrap();
+        let mut producer = FunctionContext::new();
+        snd.attach_sender(&producer);
+        producer.set_run(move |time| {
+            for i in 0..12u32 {
+                snd.enqueue(time, ChannelElement::new(time.tick() + 1, i))
+                    .unwrap();
+                time.incr_cycles(1);
+            }
+        });
+        parent.add_child(producer);
+        for (lane, rcv) in receivers.into_iter().enumerate() {
+            parent.add_child(CheckerContext::new(
+                move || (0..4u32).map(move |i| 3 * i + lane as u32),
+                rcv,
+            ));
+        }
+
+        let executed = parent
+            .initialize(Default::default())
+            .unwrap()
+            .run(RunOptions::default());
+        assert!(executed.passed());
+    }
+
+    #[test]
+    fn test_spmc_failed_send_keeps_turn() {
+        let mut parent = ProgramBuilder::default();
+        let (snd, mut receivers) = parent.spmc::<u32>(2, 2).unwrap();
+        let mut producer = FunctionContext::new();
+        snd.attach_sender(&producer);
+        producer.set_run(move |time| {
+            while snd
+                .enqueue(time, ChannelElement::new(time.tick() + 1, 0))
+                .is_ok()
+            {
+                time.incr_cycles(1);
+            }
+            // The failed send was on the first lane, which is still next in line.
+            assert!(snd
+                .enqueue(time, ChannelElement::new(time.tick() + 1, 0))
+                .is_err());
+        });
+        parent.add_child(producer);
+
+        let drained = receivers.pop().unwrap();
+        let mut drain = FunctionContext::new();
+        drained.attach_receiver(&drain);
+        drain.set_run(move |time| while drained.dequeue(time).is_ok() {});
+        parent.add_child(drain);
+
+        // The first lane's consumer leaves without receiving anything, which eventually closes the lane.
+        let abandoned = receivers.pop().unwrap();
+        let mut quitter = FunctionContext::new();
+        abandoned.attach_receiver(&quitter);
+        quitter.set_run(move |_| drop(abandoned));
+        parent.add_child(quitter);
+
+        let executed = parent
+            .initialize(Default::default())
+            .unwrap()
+            .run(RunOptions::default());
+        assert!(executed.passed());
+    }
+
+    #[test]
+    fn test_broadcast_branch_latencies() {
+        let latencies = [(1, 1), (5, 2), (10, 4)];
+        let mut parent = ProgramBuilder::default();
+        let (snd, receivers) = parent.broadcast::<u32>(2, latencies).unwrap();
+        let mut producer = FunctionContext::new();
+        snd.attach_sender(&producer);
+        producer.set_run(move |time| {
+            for i in 0..16u32 {
+                snd.enqueue(time, ChannelElement::new(time.tick(), i))
+                    .unwrap();
+                time.incr_cycles(1);
+            }
+        });
+        parent.add_child(producer);
+
+        for (rcv, (latency, _)) in receivers.into_iter().zip(latencies) {
+            let mut consumer = FunctionContext::new();
+            rcv.attach_receiver(&consumer);
+            consumer.set_run(move |time| {
+                let mut last = 0;
+                for i in 0..16u32 {
+                    let element = rcv.dequeue(time).unwrap();
+                    assert_eq!(element.data, i);
+                    // Never earlier than the branch latency, but later once backpressure kicks in.
+                    assert!(element.time.time() >= u64::from(i) + latency);
+                    assert!(element.time.time() > last);
+                    last = element.time.time();
+                    time.incr_cycles(u64::from(i % 3));
+                }
+                assert!(rcv.dequeue(time).is_err());
+            });
+            parent.add_child(consumer);
+        }
+
+        let executed = parent
+            .initialize(Default::default())
+            .unwrap()
+            .run(RunOptions::default());
+        assert!(executed.passed());
+    }
+
+    #[test]
+    fn test_broadcast_many_receivers() {
+        let mut parent = ProgramBuilder::default();
+        let (snd, receivers) = parent.broadcast::<u32>(8, vec![(1, 1); 256]).unwrap();
+        let mut producer = FunctionContext::new();
+        snd.attach_sender(&producer);
+        producer.set_run(move |time| {
+            for i in 0..32u32 {
+                snd.enqueue(time, ChannelElement::new(time.tick() + 1, i))
+                    .unwrap();
+                time.incr_cycles(1);
+            }
+        });
+        parent.add_child(producer);
+        for rcv in receivers {
+            parent.add_child(CheckerContext::new(|| 0..32u32, rcv));
+        }
+
+        let executed = parent
+            .initialize(
+                InitializationOptionsBuilder::default()
+                    .run_flavor_inference(true)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap()
+            .run(RunOptions::default());
+        assert!(executed.passed());
+    }
+
+    #[test]
+    fn test_broadcast_failed_branch() {
+        let mut parent = ProgramBuilder::default();
+        let (snd, mut receivers) = parent.broadcast::<u32>(2, [(1, 1); 2]).unwrap();
+        let mut producer = FunctionContext::new();
+        snd.attach_sender(&producer);
+        producer.set_run(move |time| {
+            let failures = (0..16u32)
+                .filter(|i| {
+                    let sent = snd.enqueue(time, ChannelElement::new(time.tick() + 1, *i));
+                    time.incr_cycles(1);
+                    sent.is_err()
+                })
+                .count();
+            // The abandoned branch fills up and fails, but the other branch still sees everything.
+            assert!(failures > 0);
+        });
+        parent.add_child(producer);
+
+        let abandoned = receivers.pop().unwrap();
+        let mut quitter = FunctionContext::new();
+        abandoned.attach_receiver(&quitter);
+        quitter.set_run(move |_| drop(abandoned));
+        parent.add_child(quitter);
+        parent.add_child(CheckerContext::new(|| 0..16u32, receivers.pop().unwrap()));
+
+        let executed = parent
+            .initialize(Default::default())
+            .unwrap()
+            .run(RunOptions::default());
+        assert!(executed.passed());
+    }
+
+    #[test]
+    fn test_no_lanes() {
+        let mut parent = ProgramBuilder::default();
+        assert_eq!(
+            parent.mpsc::<u32>(0, 2, Arbitration::default()).err(),
+            Some(MultiChannelError::NoLanes)
+        );
+        assert_eq!(
+            parent.spmc::<u32>(0, 2).err(),
+            Some(MultiChannelError::NoLanes)
+        );
+        assert_eq!(
+            parent.broadcast::<u32>(2, []).err(),
+            Some(MultiChannelError::NoLanes)
+        );
+    }
+}
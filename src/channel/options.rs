@@ -0,0 +1,145 @@
+use std::{num::NonZeroU64, sync::Arc};
+
+use crate::{datastructures::Time, types::DAMType};
+
+use super::{channel_spec::ChannelSpec, handle::ChannelData, sender::LatencyFn, ChannelElement};
+
+/// The settings of a channel, which can be combined where the specialized constructors such as
+/// [crate::simulation::ProgramBuilder::bounded_with_bandwidth] only offer one at a time.
+/// Channels are constructed from options with [crate::simulation::ProgramBuilder::channel],
+/// [crate::simulation::SubgraphBuilder::channel] or [crate::simulation::SpawnHandle::channel].
+pub struct ChannelOptions<T> {
+    capacity: Option<usize>,
+    latency: Option<u64>,
+    resp_latency: Option<u64>,
+    bandwidth: Option<NonZeroU64>,
+    sizer: Option<fn(&T) -> usize>,
+    out_of_order: bool,
+    latency_fn: Option<LatencyFn<T>>,
+    initial: Vec<ChannelElement<T>>,
+}
+
+impl<T: Clone> ChannelOptions<T> {
+    pub(crate) fn new(
+        capacity: Option<usize>,
+        latency: Option<u64>,
+        resp_latency: Option<u64>,
+    ) -> Self {
+        Self {
+            capacity,
+            latency,
+            resp_latency,
+            bandwidth: None,
+            sizer: None,
+            out_of_order: false,
+            latency_fn: None,
+            initial: Vec::new(),
+        }
+    }
+
+    /// A channel which holds at most `capacity` elements, with unit latency
+    pub fn bounded(capacity: usize) -> Self {
+        Self::new(Some(capacity), None, None)
+    }
+
+    /// An infinitely deep channel with unit latency
+    pub fn unbounded() -> Self {
+        Self::new(None, None, None)
+    }
+
+    /// Sets the latency of elements, and of the responses which free up capacity
+    pub fn latency(mut self, latency: u64, resp_latency: u64) -> Self {
+        self.latency = Some(latency);
+        self.resp_latency = Some(resp_latency);
+        self
+    }
+
+    /// Limits the channel to transferring a number of bits per cycle.
+    /// Each element occupies the link for `ceil(dam_size / bits_per_cycle)` cycles, so back-to-back elements are serialized.
+    pub fn bandwidth(mut self, bits_per_cycle: NonZeroU64) -> Self
+    where
+        T: DAMType,
+    {
+        self.bandwidth = Some(bits_per_cycle);
+        self.sizer = Some(T::dam_size);
+        self
+    }
+
+    /// Computes each element's latency from its value and send time.
+    /// Latencies are clamped to at least the channel's latency, and elements are never delivered before earlier ones
+    /// unless the channel is also [ChannelOptions::out_of_order].
+    pub fn latency_fn<F>(mut self, latency: F) -> Self
+    where
+        F: Fn(&T, Time) -> u64 + Send + Sync + 'static,
+    {
+        self.latency_fn = Some(Arc::new(latency));
+        self
+    }
+
+    /// Delivers elements in order of their times rather than the order they were sent.
+    /// Buffered elements still count against the capacity until they are dequeued.
+    pub fn out_of_order(mut self) -> Self {
+        self.out_of_order = true;
+        self
+    }
+
+    /// Seeds the channel with timestamped elements which are already there when the program starts,
+    /// such as the initial tokens on the back edge of a feedback loop.
+    /// The initial elements count against the capacity, and their times must be non-decreasing.
+    /// Otherwise, the channel starts out empty and [crate::simulation::ProgramBuilder::initialize] fails with
+    /// [crate::simulation::InitializationError::InvalidInitial], while [crate::simulation::SpawnHandle::channel] panics.
+    pub fn initial(mut self, elements: impl IntoIterator<Item = ChannelElement<T>>) -> Self {
+        self.initial = elements.into_iter().collect();
+        self
+    }
+
+    /// Explains why the initial elements can't be seeded, if they can't.
+    pub(crate) fn invalid_initial(&self) -> Option<String> {
+        if self
+            .capacity
+            .is_some_and(|capacity| self.initial.len() > capacity)
+        {
+            Some(format!(
+                "cannot seed {} elements into a channel of capacity {}",
+                self.initial.len(),
+                self.capacity.unwrap()
+            ))
+        } else if !self
+            .initial
+            .windows(2)
+            .all(|pair| pair[0].time <= pair[1].time)
+        {
+            Some("initial elements must have non-decreasing times".to_string())
+        } else {
+            None
+        }
+    }
+
+    /// Builds the specification, which must be done while the program's IDs are in scope.
+    pub(crate) fn spec(&self) -> ChannelSpec {
+        let mut spec = ChannelSpec::new(self.capacity, self.latency, self.resp_latency);
+        if let Some(bits_per_cycle) = self.bandwidth {
+            spec = spec.with_bandwidth(bits_per_cycle);
+        }
+        if self.out_of_order {
+            spec = spec.with_out_of_order();
+        }
+        spec
+    }
+
+    /// Builds the channel for a specification made by [ChannelOptions::spec].
+    /// The initial elements are left out if they are invalid, see [ChannelOptions::invalid_initial].
+    pub(crate) fn into_data(self, spec: Arc<ChannelSpec>) -> ChannelData<T> {
+        let mut data = match self.invalid_initial() {
+            Some(_) => ChannelData::new(spec),
+            None => ChannelData::with_initial(spec, self.initial),
+        };
+        if let Some(sizer) = self.sizer {
+            data = data.with_sizer(sizer);
+        }
+        if let Some(latency) = self.latency_fn {
+            data = data.with_latency_fn(latency);
+        }
+        data
+    }
+}
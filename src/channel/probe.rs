@@ -0,0 +1,321 @@
+//! End-to-end latency probes, which measure how long transactions take to travel between two channels.
+//! Channels carry a [TransactionTag] alongside each element. A probe tags untagged elements as they are enqueued onto
+//! its start channel, and records a latency the first time an element with the same tag is dequeued from its end channel.
+//! Contexts in between must copy tags from their inputs to their outputs, by passing [super::Receiver::last_tag] to
+//! [super::Sender::set_tag].
+//! The adapters in [super::adapters] and the contexts in [crate::templates] already do so.
+
+use std::{collections::hash_map::Entry, sync::Arc};
+
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{datastructures::Time, shim::Mutex};
+
+use super::{ChannelID, TransactionTag};
+
+/// The latencies observed by a probe over a run, see [crate::simulation::ProgramBuilder::probe_latency].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    /// The probe's name
+    pub name: String,
+    /// Where transactions were tagged
+    pub start: ChannelID,
+    /// Where transactions were completed
+    pub end: ChannelID,
+    /// Latency in cycles of each completed transaction, in ascending order
+    pub samples: Vec<u64>,
+    /// Transactions which passed the start channel, but never reached the end channel
+    pub unfinished: usize,
+}
+
+impl LatencyHistogram {
+    /// The number of completed transactions
+    pub fn count(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// The shortest latency
+    pub fn min(&self) -> Option<u64> {
+        self.samples.first().copied()
+    }
+
+    /// The longest latency
+    pub fn max(&self) -> Option<u64> {
+        self.samples.last().copied()
+    }
+
+    /// The average latency
+    pub fn mean(&self) -> Option<f64> {
+        (!self.samples.is_empty())
+            .then(|| self.samples.iter().sum::<u64>() as f64 / self.samples.len() as f64)
+    }
+
+    /// The smallest latency which is at least as long as `percentile` percent of the samples.
+    pub fn percentile(&self, percentile: f64) -> Option<u64> {
+        assert!(
+            (0.0..=100.0).contains(&percentile),
+            "Percentiles must be between 0 and 100"
+        );
+        let rank = (percentile / 100.0 * self.samples.len() as f64).ceil() as usize;
+        self.samples.get(rank.saturating_sub(1)).copied()
+    }
+
+    /// Counts the samples in buckets of `width` cycles, as `(lower bound, count)` pairs. Empty buckets are skipped.
+    pub fn buckets(&self, width: u64) -> Vec<(u64, usize)> {
+        assert!(width > 0, "Buckets must be at least one cycle wide");
+        let mut buckets: Vec<(u64, usize)> = vec![];
+        for sample in &self.samples {
+            let lower = sample - sample % width;
+            match buckets.last_mut() {
+                Some((last, count)) if *last == lower => *count += 1,
+                _ => buckets.push((lower, 1)),
+            }
+        }
+        buckets
+    }
+}
+
+#[derive(Default)]
+struct ProbeState {
+    // When each transaction passed the start channel, until it reaches the end channel.
+    pending: FxHashMap<(ChannelID, u64), Time>,
+    samples: Vec<u64>,
+}
+
+/// A probe between two channels, shared by the sender of the start channel and the receiver of the end channel.
+pub(crate) struct LatencyProbe {
+    name: String,
+    start: ChannelID,
+    end: ChannelID,
+    state: Mutex<ProbeState>,
+}
+
+impl LatencyProbe {
+    pub(crate) fn new(name: String, start: ChannelID, end: ChannelID) -> Self {
+        Self {
+            name,
+            start,
+            end,
+            state: Mutex::new(ProbeState::default()),
+        }
+    }
+
+    pub(crate) fn start(&self) -> ChannelID {
+        self.start
+    }
+
+    pub(crate) fn end(&self) -> ChannelID {
+        self.end
+    }
+
+    /// Records a transaction passing the start channel. Only the first pass counts, and this returns whether it was the first.
+    pub(crate) fn depart(&self, tag: TransactionTag, time: Time) -> bool {
+        match self
+            .state
+            .lock()
+            .unwrap()
+            .pending
+            .entry((tag.source, tag.id))
+        {
+            Entry::Vacant(entry) => {
+                entry.insert(time);
+                true
+            }
+            Entry::Occupied(_) => false,
+        }
+    }
+
+    /// Forgets a transaction whose first pass was recorded, but whose element couldn't be sent after all.
+    pub(crate) fn cancel(&self, tag: TransactionTag) {
+        self.state
+            .lock()
+            .unwrap()
+            .pending
+            .remove(&(tag.source, tag.id));
+    }
+
+    /// Records a transaction reaching the end channel, if it passed the start channel and hasn't arrived already.
+    pub(crate) fn arrive(&self, tag: TransactionTag, time: Time) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(departed) = state.pending.remove(&(tag.source, tag.id)) {
+            state
+                .samples
+                .push(time.time().saturating_sub(departed.time()));
+        }
+    }
+
+    pub(crate) fn histogram(&self) -> LatencyHistogram {
+        let state = self.state.lock().unwrap();
+        let mut samples = state.samples.clone();
+        samples.sort_unstable();
+        LatencyHistogram {
+            name: self.name.clone(),
+            start: self.start,
+            end: self.end,
+            samples,
+            unfinished: state.pending.len(),
+        }
+    }
+}
+
+/// The probes which start or end on a channel.
+#[derive(Default, Clone)]
+pub(crate) struct ChannelProbes {
+    pub(crate) starts: Vec<Arc<LatencyProbe>>,
+    pub(crate) ends: Vec<Arc<LatencyProbe>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        channel::{ChannelElement, ChannelOptions, EnqueueError, TransactionTag},
+        datastructures::Time,
+        simulation::{InitializationOptions, ProgramBuilder, RunOptions},
+        utility_contexts::{ConsumerContext, FunctionContext},
+        view::{ContextView, TimeViewable},
+    };
+
+    #[test]
+    fn test_latency_probe() {
+        let mut ctx = ProgramBuilder::default();
+        let (req_snd, req_rcv) = ctx.bounded::<u64>(4);
+        let (resp_snd, resp_rcv) = ctx.bounded::<u64>(4);
+        ctx.probe_latency("service", req_snd.id(), resp_snd.id());
+        let source = req_snd.id();
+
+        let mut issue = FunctionContext::default();
+        req_snd.attach_sender(&issue);
+        issue.set_run(move |time| {
+            for i in 0..8 {
+                req_snd
+                    .enqueue(time, ChannelElement::new(time.tick(), i))
+                    .unwrap();
+                time.incr_cycles(20);
+            }
+        });
+        ctx.add_child(issue);
+
+        // Tags pass through the adapters, and the element which is filtered out never completes.
+        let requests = req_rcv.map(|x| x * 2).filter(|x| *x != 6);
+        let mut service = FunctionContext::default();
+        requests.attach_receiver(&service);
+        resp_snd.attach_sender(&service);
+        service.set_run(move |time| {
+            while let Ok(request) = requests.dequeue(time) {
+                // Requests are numbered by the channel they were tagged on, in the order they were sent.
+                let tag = requests.last_tag().unwrap();
+                assert_eq!((tag.source, tag.id), (source, request.data / 2));
+                time.incr_cycles(request.data);
+                resp_snd.set_tag(Some(tag));
+                resp_snd
+                    .enqueue(time, ChannelElement::new(time.tick() + 1, request.data))
+                    .unwrap();
+            }
+        });
+        ctx.add_child(service);
+        ctx.add_child(ConsumerContext::new(resp_rcv));
+
+        let executed = ctx
+            .initialize(InitializationOptions::default())
+            .unwrap()
+            .run(RunOptions::default());
+        assert!(executed.passed());
+
+        let histograms = executed.latency_histograms();
+        assert_eq!(histograms.len(), 1);
+        let histogram = &histograms[0];
+        assert_eq!(histogram.name, "service");
+        // One cycle in each channel, plus twice the request's value in the service.
+        assert_eq!(histogram.samples, vec![2, 4, 6, 10, 12, 14, 16]);
+        assert_eq!(histogram.unfinished, 1);
+        assert_eq!(histogram.percentile(50.0), Some(10));
+        assert_eq!(histogram.buckets(5), vec![(0, 2), (5, 1), (10, 3), (15, 1)]);
+    }
+
+    #[test]
+    fn test_failed_sends_never_depart() {
+        let mut ctx = ProgramBuilder::default();
+        let (req_snd, req_rcv) = ctx.bounded::<u64>(4);
+        let (resp_snd, resp_rcv) = ctx.bounded::<u64>(4);
+        ctx.probe_latency("dropped", req_snd.id(), resp_snd.id());
+
+        // The service quits without reading anything, which closes the request channel.
+        let mut service = FunctionContext::default();
+        req_rcv.attach_receiver(&service);
+        resp_snd.attach_sender(&service);
+        service.set_run(move |_| {
+            drop((req_rcv, resp_snd));
+        });
+        let service_view = service.view();
+        ctx.add_child(service);
+        ctx.add_child(ConsumerContext::new(resp_rcv));
+
+        let mut issue = FunctionContext::default();
+        req_snd.attach_sender(&issue);
+        issue.set_run(move |time| {
+            service_view.wait_until(Time::infinite());
+            for i in 0..4 {
+                assert!(matches!(
+                    req_snd.enqueue(time, ChannelElement::new(time.tick(), i)),
+                    Err(EnqueueError::Closed)
+                ));
+            }
+        });
+        ctx.add_child(issue);
+
+        let executed = ctx
+            .initialize(InitializationOptions::default())
+            .unwrap()
+            .run(RunOptions::default());
+        assert!(executed.passed());
+        let histogram = &executed.latency_histograms()[0];
+        assert_eq!(histogram.count(), 0);
+        assert_eq!(histogram.unfinished, 0);
+    }
+
+    #[test]
+    fn test_tags_follow_reordered_elements() {
+        let mut ctx = ProgramBuilder::default();
+        // Later elements take less time to arrive, so they overtake earlier ones along with their tags.
+        let (snd, rcv) = ctx.channel(
+            ChannelOptions::bounded(8)
+                .latency_fn(|x: &u64, _| 4 * (8 - *x))
+                .out_of_order(),
+        );
+        let source = snd.id();
+
+        let mut sender = FunctionContext::default();
+        snd.attach_sender(&sender);
+        sender.set_run(move |time| {
+            for i in 0..8 {
+                snd.set_tag(Some(TransactionTag {
+                    source,
+                    id: i,
+                    origin: time.tick(),
+                }));
+                snd.enqueue(time, ChannelElement::new(time.tick(), i))
+                    .unwrap();
+            }
+        });
+        ctx.add_child(sender);
+
+        let mut receiver = FunctionContext::default();
+        rcv.attach_receiver(&receiver);
+        receiver.set_run(move |time| {
+            assert_eq!(rcv.last_tag(), None);
+            for expected in (0..8).rev() {
+                let element = rcv.dequeue(time).unwrap();
+                assert_eq!(element.data, expected);
+                assert_eq!(rcv.last_tag().map(|tag| tag.id), Some(expected));
+            }
+        });
+        ctx.add_child(receiver);
+
+        let executed = ctx
+            .initialize(InitializationOptions::default())
+            .unwrap()
+            .run(RunOptions::default());
+        assert!(executed.passed());
+    }
+}
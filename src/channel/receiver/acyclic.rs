@@ -13,12 +13,17 @@ pub(super) trait AcyclicReceiver<T: Clone>: ReceiverCommon<T> {
             Some(PeekResult::Something(data)) => return Ok(data.clone()),
         }
 
-        self.data().head = match self.data().underlying.recv() {
-            Ok(stuff) => {
+        self.data().head = match self.data().recv() {
+            Some(stuff) => {
                 manager.advance(stuff.time);
                 Some(PeekResult::Something(stuff))
             }
-            Err(_) => Some(PeekResult::Closed),
+            None => {
+                if let Some(time) = self.data().spec.close_time() {
+                    manager.advance(time);
+                }
+                Some(PeekResult::Closed)
+            }
         };
         self.data().head.clone().unwrap().try_into().unwrap()
     }
@@ -39,13 +44,16 @@ pub(super) trait AcyclicReceiver<T: Clone>: ReceiverCommon<T> {
         }
 
         // At this point, we can just block!
-        match self.data().underlying.recv() {
-            Ok(ce) => {
+        match self.data().recv() {
+            Some(ce) => {
                 self.register_recv(ce.time.max(manager.tick()));
                 manager.advance(ce.time);
                 Ok(ce)
             }
-            Err(_) => {
+            None => {
+                if let Some(time) = self.data().spec.close_time() {
+                    manager.advance(time);
+                }
                 self.data().head = Some(PeekResult::Closed);
                 Err(DequeueError::Closed)
             }
@@ -30,7 +30,7 @@ pub(super) trait CyclicReceiver<T: Clone>: ReceiverCommon<T> {
                 self.data().head = None;
                 Ok(data)
             }
-            Err(DequeueError::Closed) => result,
+            Err(_) => result,
         }
     }
 }
@@ -1,15 +1,23 @@
+use std::{collections::VecDeque, sync::Arc};
+
 use crate::shim::channel::TryRecvError;
 
 use enum_dispatch::enum_dispatch;
 
 use crate::{datastructures::Time, view::TimeManager};
 
-use self::{acyclic::AcyclicReceiver, cyclic::CyclicReceiver};
+use self::{acyclic::AcyclicReceiver, cyclic::CyclicReceiver, reorder::ReorderBuffer};
 
-use super::{channel_spec::InlineSpec, ChannelElement, DequeueError, PeekResult};
+use super::{
+    channel_spec::InlineSpec,
+    probe::LatencyProbe,
+    tap::{TapEvent, TapSlot},
+    ChannelElement, DequeueError, PeekResult, PeekWindow, TaggedElement, TransactionTag, WindowEnd,
+};
 
 mod acyclic;
 mod cyclic;
+mod reorder;
 pub mod terminated;
 pub mod uninitialized;
 
@@ -18,6 +26,38 @@ pub(super) trait ReceiverFlavor<T> {
     fn peek(&mut self) -> PeekResult<T>;
     fn peek_next(&mut self, manager: &TimeManager) -> Result<ChannelElement<T>, DequeueError>;
     fn dequeue(&mut self, manager: &TimeManager) -> Result<ChannelElement<T>, DequeueError>;
+    fn peek_n(&mut self, count: usize) -> PeekWindow<T>;
+    fn discard_head(&mut self) -> bool;
+    fn peek_next_n(
+        &mut self,
+        manager: &TimeManager,
+        count: usize,
+    ) -> Result<Vec<ChannelElement<T>>, DequeueError>;
+
+    /// The transaction of the element most recently dequeued.
+    fn last_tag(&self) -> Option<TransactionTag> {
+        None
+    }
+
+    fn dequeue_many(
+        &mut self,
+        manager: &TimeManager,
+        count: usize,
+        interval: u64,
+    ) -> Result<Vec<ChannelElement<T>>, DequeueError> {
+        let mut elements = vec![];
+        while elements.len() < count {
+            match self.dequeue(manager) {
+                Ok(element) => elements.push(element),
+                Err(_) if !elements.is_empty() => break,
+                Err(error) => return Err(error),
+            }
+            if interval > 0 {
+                manager.incr_cycles(interval);
+            }
+        }
+        Ok(elements)
+    }
 }
 
 #[enum_dispatch]
@@ -66,7 +106,61 @@ macro_rules! RegisterReceiver {
                 &mut self,
                 manager: &TimeManager,
             ) -> Result<ChannelElement<T>, DequeueError> {
-                $receiver_mode::dequeue(self, manager)
+                let result = $receiver_mode::dequeue(self, manager);
+                if let Ok(element) = &result {
+                    self.data.observe(manager.tick(), element);
+                }
+                result
+            }
+
+            fn peek_n(&mut self, count: usize) -> PeekWindow<T> {
+                ReceiverCommon::peek_n(self, count)
+            }
+
+            fn discard_head(&mut self) -> bool {
+                ReceiverCommon::discard_head(self)
+            }
+
+            fn peek_next_n(
+                &mut self,
+                manager: &TimeManager,
+                count: usize,
+            ) -> Result<Vec<ChannelElement<T>>, DequeueError> {
+                ReceiverCommon::peek_next_n(self, manager, count)
+            }
+
+            fn last_tag(&self) -> Option<TransactionTag> {
+                self.data.last_tag
+            }
+
+            fn dequeue_many(
+                &mut self,
+                manager: &TimeManager,
+                count: usize,
+                interval: u64,
+            ) -> Result<Vec<ChannelElement<T>>, DequeueError> {
+                let mut elements = Vec::with_capacity(count);
+                while elements.len() < count {
+                    // Elements which have already been sent are popped as a run, and only an empty channel needs a full dequeue.
+                    let element = match ReceiverCommon::pop_sent(self) {
+                        Some(element) => {
+                            manager.advance(element.time);
+                            self.register_recv(element.time.max(manager.tick()));
+                            self.data.observe(manager.tick(), &element);
+                            element
+                        }
+                        None => match ReceiverFlavor::dequeue(self, manager) {
+                            Ok(element) => element,
+                            Err(_) if !elements.is_empty() => break,
+                            Err(error) => return Err(error),
+                        },
+                    };
+                    elements.push(element);
+                    if interval > 0 {
+                        manager.incr_cycles(interval);
+                    }
+                }
+                Ok(elements)
             }
         }
     };
@@ -77,8 +171,113 @@ macro_rules! RegisterReceiver {
 // Holds the basic data for a receiver
 pub(super) struct ReceiverData<T> {
     pub(super) spec: InlineSpec,
-    pub(super) underlying: crate::shim::channel::Receiver<ChannelElement<T>>,
+    pub(super) underlying: crate::shim::channel::Receiver<TaggedElement<T>>,
     pub(super) head: Option<PeekResult<T>>,
+    // Only present for out-of-order channels
+    pub(super) reorder: Option<ReorderBuffer<T>>,
+    // Elements after the head which have been pulled out of the channel by peek_n, but not dequeued.
+    pub(super) lookahead: VecDeque<ChannelElement<T>>,
+    // The tags of elements which have been pulled out of the channel but not dequeued, in the order they are delivered.
+    pub(super) tags: VecDeque<Option<TransactionTag>>,
+    pub(super) last_tag: Option<TransactionTag>,
+    pub(super) tap: TapSlot<T>,
+    // Probes which end on this channel.
+    pub(super) probes: Vec<Arc<LatencyProbe>>,
+}
+
+impl<T> ReceiverData<T> {
+    /// Reports a dequeued element to the channel's tap and probes.
+    fn observe(&mut self, now: Time, element: &ChannelElement<T>) {
+        if let Some(tap) = self.tap.get() {
+            tap.record(TapEvent::Dequeue, now, element.time, &element.data);
+        }
+        // Elements are dequeued in the order they were pulled, so the element's tag is at the front.
+        self.last_tag = self.tags.pop_front().flatten();
+        if let Some(tag) = self.last_tag {
+            for probe in &self.probes {
+                probe.arrive(tag, now);
+            }
+        }
+    }
+
+    fn try_recv(&mut self) -> Result<ChannelElement<T>, TryRecvError> {
+        match self.lookahead.pop_front() {
+            Some(element) => Ok(element),
+            None => self.pull(),
+        }
+    }
+
+    /// Takes the next element out of the underlying channel, in time order for out-of-order channels.
+    fn pull(&mut self) -> Result<ChannelElement<T>, TryRecvError> {
+        let (element, tag) = self.pull_tagged()?;
+        self.tags.push_back(tag);
+        Ok(element)
+    }
+
+    fn pull_tagged(&mut self) -> Result<TaggedElement<T>, TryRecvError> {
+        let Some(reorder) = &mut self.reorder else {
+            return self.underlying.try_recv();
+        };
+        // The horizon has to be read before draining, otherwise an element sent in between could be missed.
+        let horizon = self.spec.sender_tlb() + self.spec.send_latency;
+        loop {
+            match self.underlying.try_recv() {
+                Ok(element) => reorder.insert(element),
+                Err(TryRecvError::Disconnected) => {
+                    reorder.close();
+                    break;
+                }
+                Err(TryRecvError::Empty) => break,
+            }
+        }
+        match reorder.pop(horizon, self.spec.capacity) {
+            Some(element) => Ok(element),
+            None if reorder.is_closed() => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// What to report once the sender has disconnected.
+    /// Channels closed at an explicit time only report [PeekResult::Closed] once the receiver reaches it.
+    fn closed(&self) -> PeekResult<T> {
+        match self.spec.close_time() {
+            Some(time) if time > self.spec.receiver_tlb() => PeekResult::Nothing(time - 1),
+            _ => PeekResult::Closed,
+        }
+    }
+
+    /// Blocks until an element is available, returning None if the channel was closed.
+    fn recv(&mut self) -> Option<ChannelElement<T>> {
+        if let Some(element) = self.lookahead.pop_front() {
+            return Some(element);
+        }
+        if self.reorder.is_none() {
+            let (element, tag) = self.underlying.recv().ok()?;
+            self.tags.push_back(tag);
+            return Some(element);
+        }
+        loop {
+            match self.try_recv() {
+                Ok(element) => return Some(element),
+                Err(TryRecvError::Disconnected) => return None,
+                Err(TryRecvError::Empty) => {}
+            }
+            let reorder = self.reorder.as_mut().unwrap();
+            match reorder.earliest() {
+                // The earliest element becomes deliverable once the sender passes it.
+                Some(time) if self.spec.capacity.is_none() => {
+                    let until = time.time().saturating_sub(self.spec.send_latency);
+                    self.spec.wait_until_sender(Time::new(until));
+                }
+                // A bounded sender can also release it by filling the buffer without advancing,
+                // so block on the next element, which has to arrive before the sender waits on us.
+                _ => match self.underlying.recv() {
+                    Ok(element) => reorder.insert(element),
+                    Err(_) => reorder.close(),
+                },
+            }
+        }
+    }
 }
 
 trait DataProvider<T> {
@@ -98,9 +297,15 @@ trait ReceiverCommon<T: Clone>: Responsive + DataProvider<T> {
             Some(data @ PeekResult::Something(_)) => return data.clone(),
         }
         self.try_update_head(Time::new(0));
+        let close_pending = self.data().spec.close_time().is_some();
         match &self.data().head {
             Some(x @ PeekResult::Closed) | Some(x @ PeekResult::Something(_)) => return x.clone(),
 
+            // The channel was closed at a time we haven't reached yet, so there's no need to wait on the sender.
+            Some(PeekResult::Nothing(time)) if *time >= recv_time && close_pending => {
+                return PeekResult::Nothing(*time)
+            }
+
             // This is speculative, so we should continue if it's nothing.
             Some(PeekResult::Nothing(_)) => {}
             None => unreachable!(),
@@ -112,10 +317,142 @@ trait ReceiverCommon<T: Clone>: Responsive + DataProvider<T> {
         self.data().head.clone().unwrap()
     }
 
+    /// Peeks at up to `count` elements without blocking.
+    /// Only elements which have actually been sent are visible, so the window never exceeds the channel's capacity.
+    fn peek_n(&mut self, count: usize) -> PeekWindow<T> {
+        if count == 0 {
+            return PeekWindow {
+                elements: vec![],
+                end: WindowEnd::Full,
+            };
+        }
+        let head = match self.peek() {
+            PeekResult::Something(element) => element,
+            PeekResult::Nothing(time) => {
+                return PeekWindow {
+                    elements: vec![],
+                    end: WindowEnd::Nothing(time),
+                }
+            }
+            PeekResult::Closed => {
+                return PeekWindow {
+                    elements: vec![],
+                    end: WindowEnd::Closed,
+                }
+            }
+        };
+
+        let data = self.data();
+        // Anything sent after this point arrives after the sender's current time plus the latency.
+        // This has to be read before pulling, otherwise an element sent in between could be missed.
+        let horizon = data.spec.sender_tlb() + data.spec.send_latency;
+        let mut disconnected = false;
+        while 1 + data.lookahead.len() < count {
+            match data.pull() {
+                Ok(element) => data.lookahead.push_back(element),
+                Err(TryRecvError::Disconnected) => {
+                    disconnected = true;
+                    break;
+                }
+                Err(TryRecvError::Empty) => break,
+            }
+        }
+        let elements: Vec<_> = std::iter::once(head)
+            .chain(data.lookahead.iter().take(count - 1).cloned())
+            .collect();
+
+        let end = if elements.len() == count {
+            WindowEnd::Full
+        } else if disconnected {
+            match data.closed() {
+                PeekResult::Nothing(time) => WindowEnd::Nothing(time),
+                _ => WindowEnd::Closed,
+            }
+        } else if horizon.is_infinite() {
+            WindowEnd::Closed
+        } else if horizon.time() == 0 {
+            WindowEnd::Pending
+        } else {
+            WindowEnd::Nothing(horizon - 1)
+        };
+        PeekWindow { elements, end }
+    }
+
+    /// Drops the peeked element without advancing time, as long as the receiver has already reached it.
+    fn discard_head(&mut self) -> bool {
+        let now = self.data().spec.receiver_tlb();
+        match &self.data().head {
+            Some(PeekResult::Something(element)) if element.time <= now => {}
+            _ => return false,
+        }
+        if let Some(PeekResult::Something(element)) = self.data().head.take() {
+            // The context never sees a discarded element, so it stays in the transaction it was in.
+            let last_tag = self.data().last_tag;
+            self.data().observe(now, &element);
+            self.data().last_tag = last_tag;
+        }
+        self.register_recv(now);
+        true
+    }
+
+    /// Advances time until `count` elements are visible, or the channel closes, and returns them.
+    fn peek_next_n(
+        &mut self,
+        manager: &TimeManager,
+        count: usize,
+    ) -> Result<Vec<ChannelElement<T>>, DequeueError> {
+        // The sender could never fill a window larger than the channel.
+        let count = self
+            .data()
+            .spec
+            .capacity
+            .map_or(count, |capacity| count.min(capacity));
+        loop {
+            let window = self.peek_n(count);
+            match window.end {
+                WindowEnd::Nothing(time) if time >= manager.tick() => manager.advance(time + 1),
+                WindowEnd::Nothing(_) => {
+                    // The sender is behind us, so wait for it to catch up before looking again.
+                    self.data().spec.wait_until_sender(manager.tick());
+                }
+                WindowEnd::Pending => {
+                    self.data().spec.wait_until_sender(manager.tick() + 1);
+                }
+                WindowEnd::Full | WindowEnd::Closed => {
+                    return match window.elements.last() {
+                        Some(last) => {
+                            manager.advance(last.time);
+                            Ok(window.elements)
+                        }
+                        None => Err(DequeueError::Closed),
+                    };
+                }
+            }
+        }
+    }
+
+    /// Takes the next element if it has already been sent, without waiting on the sender.
+    fn pop_sent(&mut self) -> Option<ChannelElement<T>> {
+        let head = self.data().head.take();
+        match head {
+            Some(PeekResult::Something(element)) => Some(element),
+            head => {
+                let element = match head {
+                    Some(PeekResult::Closed) => None,
+                    _ => self.data().try_recv().ok(),
+                };
+                if element.is_none() {
+                    self.data().head = head;
+                }
+                element
+            }
+        }
+    }
+
     fn try_update_head(&mut self, nothing_time: Time) {
-        self.data().head = match self.data().underlying.try_recv() {
+        self.data().head = match self.data().try_recv() {
             Ok(data) => Some(PeekResult::Something(data)),
-            Err(TryRecvError::Disconnected) => Some(PeekResult::Closed),
+            Err(TryRecvError::Disconnected) => Some(self.data().closed()),
             Err(TryRecvError::Empty) if nothing_time.is_infinite() => Some(PeekResult::Closed),
             Err(TryRecvError::Empty) => Some(PeekResult::Nothing(nothing_time)),
         };
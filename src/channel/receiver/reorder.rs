@@ -0,0 +1,59 @@
+use std::collections::VecDeque;
+
+use crate::{channel::TaggedElement, datastructures::Time};
+
+/// Holds elements of an out-of-order channel until they can be delivered in time order.
+pub(crate) struct ReorderBuffer<T> {
+    // Sorted by time, with ties kept in arrival order.
+    pending: VecDeque<TaggedElement<T>>,
+    closed: bool,
+}
+
+impl<T> Default for ReorderBuffer<T> {
+    fn default() -> Self {
+        Self {
+            pending: VecDeque::new(),
+            closed: false,
+        }
+    }
+}
+
+impl<T> ReorderBuffer<T> {
+    pub(super) fn insert(&mut self, element: TaggedElement<T>) {
+        let index = self
+            .pending
+            .partition_point(|(other, _)| other.time <= element.0.time);
+        self.pending.insert(index, element);
+    }
+
+    pub(super) fn close(&mut self) {
+        self.closed = true;
+    }
+
+    pub(super) fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// The time of the earliest buffered element.
+    pub(super) fn earliest(&self) -> Option<Time> {
+        self.pending.front().map(|(element, _)| element.time)
+    }
+
+    /// Removes the earliest element if nothing can arrive before it anymore.
+    /// Anything sent in the future arrives no earlier than `horizon`.
+    /// Once `capacity` elements are buffered, the sender cannot send again until one is dequeued,
+    /// so the earliest element is also safe.
+    pub(super) fn pop(
+        &mut self,
+        horizon: Time,
+        capacity: Option<usize>,
+    ) -> Option<TaggedElement<T>> {
+        let (earliest, _) = self.pending.front()?;
+        let full = capacity.is_some_and(|capacity| self.pending.len() >= capacity);
+        if self.closed || full || earliest.time <= horizon {
+            self.pending.pop_front()
+        } else {
+            None
+        }
+    }
+}
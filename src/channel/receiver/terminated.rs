@@ -1,5 +1,5 @@
 use crate::{
-    channel::{ChannelElement, DequeueError, PeekResult},
+    channel::{ChannelElement, DequeueError, PeekResult, PeekWindow},
     view::TimeManager,
 };
 
@@ -22,4 +22,20 @@ impl<T> ReceiverFlavor<T> for TerminatedReceiver {
     fn dequeue(&mut self, _manager: &TimeManager) -> Result<ChannelElement<T>, DequeueError> {
         panic!("Calling dequeue on a terminated receiver");
     }
+
+    fn peek_n(&mut self, _count: usize) -> PeekWindow<T> {
+        panic!("Calling peek_n on a terminated receiver");
+    }
+
+    fn discard_head(&mut self) -> bool {
+        panic!("Calling discard_head on a terminated receiver");
+    }
+
+    fn peek_next_n(
+        &mut self,
+        _manager: &TimeManager,
+        _count: usize,
+    ) -> Result<Vec<ChannelElement<T>>, DequeueError> {
+        panic!("Calling peek_next_n on a terminated receiver");
+    }
 }
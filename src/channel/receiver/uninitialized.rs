@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
 use crate::{
-    channel::{channel_spec::ChannelSpec, ChannelElement, DequeueError, PeekResult},
+    channel::{channel_spec::ChannelSpec, ChannelElement, DequeueError, PeekResult, PeekWindow},
     context::Context,
     view::TimeManager,
 };
@@ -30,6 +30,22 @@ impl<T> ReceiverFlavor<T> for UninitializedReceiver {
     fn dequeue(&mut self, _manager: &TimeManager) -> Result<ChannelElement<T>, DequeueError> {
         panic!("Calling dequeue on an uninitialized receiver");
     }
+
+    fn peek_n(&mut self, _count: usize) -> PeekWindow<T> {
+        panic!("Calling peek_n on an uninitialized receiver");
+    }
+
+    fn discard_head(&mut self) -> bool {
+        panic!("Calling discard_head on an uninitialized receiver");
+    }
+
+    fn peek_next_n(
+        &mut self,
+        _manager: &TimeManager,
+        _count: usize,
+    ) -> Result<Vec<ChannelElement<T>>, DequeueError> {
+        panic!("Calling peek_next_n on an uninitialized receiver");
+    }
 }
 
 impl UninitializedReceiver {
@@ -1,6 +1,6 @@
 use crate::shim::channel;
 use crate::{
-    channel::{ChannelElement, EnqueueError},
+    channel::{ChannelElement, EnqueueError, TransactionTag},
     datastructures::Time,
     view::TimeManager,
 };
@@ -15,6 +15,8 @@ pub(crate) struct BoundedData {
 pub(crate) struct BoundedAcyclicSender<T> {
     pub(crate) data: SenderData<T>,
     pub(crate) bound: BoundedData,
+    // A response which was drained while reserving slots, but which isn't due yet.
+    pub(crate) next_response: Option<Time>,
 }
 
 impl<T> DataProvider<T> for BoundedAcyclicSender<T> {
@@ -32,14 +34,39 @@ impl<T> BoundedProvider for BoundedAcyclicSender<T> {
         if self.bound.send_receive_delta < self.data.spec.capacity.unwrap() {
             return Ok(());
         }
-        match self.bound.resp.recv() {
+        let response = match self.next_response.take() {
+            Some(time) => Ok(time),
+            None => self.bound.resp.recv(),
+        };
+        match response {
             Ok(time) => {
                 manager.advance(time);
+                // Release the slot here, so that waiting again before enqueueing doesn't consume another response.
+                self.bound.send_receive_delta -= 1;
                 Ok(())
             }
             Err(_) => Err(EnqueueError::Closed),
         }
     }
+
+    fn reserve(&mut self, manager: &TimeManager) -> usize {
+        // Waiting on a response which is already due wouldn't advance time, so its slot can be freed right away.
+        while self.bound.send_receive_delta > 0 {
+            let response = match self.next_response.take() {
+                Some(time) => time,
+                None => match self.bound.resp.try_recv() {
+                    Ok(time) => time,
+                    Err(_) => break,
+                },
+            };
+            if response > manager.tick() {
+                self.next_response = Some(response);
+                break;
+            }
+            self.bound.send_receive_delta -= 1;
+        }
+        self.data.spec.capacity.unwrap() - self.bound.send_receive_delta
+    }
 }
 impl<T> SenderCommon<T> for BoundedAcyclicSender<T> {}
 
@@ -55,6 +82,23 @@ impl<T> SenderFlavor<T> for BoundedAcyclicSender<T> {
     ) -> Result<(), EnqueueError> {
         SenderCommon::enqueue(self, manager, data)
     }
+
+    fn enqueue_many(
+        &mut self,
+        manager: &TimeManager,
+        data: &mut dyn Iterator<Item = ChannelElement<T>>,
+        interval: u64,
+    ) -> Result<(), EnqueueError> {
+        SenderCommon::enqueue_many(self, manager, data, interval)
+    }
+
+    fn last_arrival(&self) -> Time {
+        self.data.last_arrival
+    }
+
+    fn set_tag(&mut self, tag: Option<TransactionTag>) {
+        self.data.tag = tag;
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -145,6 +189,14 @@ impl<T> BoundedProvider for BoundedCyclicSender<T> {
             }
         }
     }
+
+    fn reserve(&mut self, _manager: &TimeManager) -> usize {
+        // Responses which are already due are drained, and the next one is held onto just like when waiting.
+        if self.next_available.is_none() {
+            self.update_srd();
+        }
+        self.data.spec.capacity.unwrap() - self.bound.send_receive_delta
+    }
 }
 impl<T> DataProvider<T> for BoundedCyclicSender<T> {
     fn data(&mut self) -> &mut SenderData<T> {
@@ -165,4 +217,21 @@ impl<T> SenderFlavor<T> for BoundedCyclicSender<T> {
     ) -> Result<(), EnqueueError> {
         SenderCommon::enqueue(self, manager, data)
     }
+
+    fn enqueue_many(
+        &mut self,
+        manager: &TimeManager,
+        data: &mut dyn Iterator<Item = ChannelElement<T>>,
+        interval: u64,
+    ) -> Result<(), EnqueueError> {
+        SenderCommon::enqueue_many(self, manager, data, interval)
+    }
+
+    fn last_arrival(&self) -> Time {
+        self.data.last_arrival
+    }
+
+    fn set_tag(&mut self, tag: Option<TransactionTag>) {
+        self.data.tag = tag;
+    }
 }
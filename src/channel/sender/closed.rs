@@ -0,0 +1,35 @@
+use std::marker::PhantomData;
+
+use crate::{
+    channel::{ChannelElement, EnqueueError},
+    view::TimeManager,
+};
+
+use super::SenderFlavor;
+
+/// Left behind by [crate::channel::Sender::close_at], so that sending afterwards fails instead of panicking.
+pub(crate) struct ClosedSender<T> {
+    _marker: PhantomData<T>,
+}
+
+impl<T> SenderFlavor<T> for ClosedSender<T> {
+    fn enqueue(
+        &mut self,
+        _manager: &TimeManager,
+        _data: ChannelElement<T>,
+    ) -> Result<(), EnqueueError> {
+        Err(EnqueueError::Closed)
+    }
+
+    fn wait_until_available(&mut self, _manager: &TimeManager) -> Result<(), EnqueueError> {
+        Err(EnqueueError::Closed)
+    }
+}
+
+impl<T> Default for ClosedSender<T> {
+    fn default() -> Self {
+        Self {
+            _marker: Default::default(),
+        }
+    }
+}
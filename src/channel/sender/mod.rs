@@ -1,15 +1,24 @@
 use enum_dispatch::enum_dispatch;
 
-use crate::view::TimeManager;
+use std::sync::Arc;
+
+use crate::{datastructures::Time, view::TimeManager};
 
 use self::{
     bounded::{BoundedAcyclicSender, BoundedCyclicSender},
     unbounded::UnboundedSender,
 };
 
-use super::{channel_spec::InlineSpec, ChannelElement, EnqueueError};
+use super::{
+    channel_spec::InlineSpec,
+    faults::FaultInjector,
+    probe::LatencyProbe,
+    tap::{TapEvent, TapSlot},
+    ChannelElement, ChannelID, EnqueueError, TaggedElement, TransactionTag,
+};
 
 pub(super) mod bounded;
+pub(super) mod closed;
 pub(super) mod terminated;
 pub(super) mod unbounded;
 pub(super) mod uninitialized;
@@ -24,6 +33,29 @@ pub trait SenderFlavor<T> {
         manager: &TimeManager,
         data: ChannelElement<T>,
     ) -> Result<(), EnqueueError>;
+
+    fn enqueue_many(
+        &mut self,
+        manager: &TimeManager,
+        data: &mut dyn Iterator<Item = ChannelElement<T>>,
+        interval: u64,
+    ) -> Result<(), EnqueueError> {
+        for element in data {
+            self.enqueue(manager, element)?;
+            if interval > 0 {
+                manager.incr_cycles(interval);
+            }
+        }
+        Ok(())
+    }
+
+    /// The time the last element sent arrives at the receiver.
+    fn last_arrival(&self) -> Time {
+        Time::new(0)
+    }
+
+    /// Sets the transaction of the elements sent from now on.
+    fn set_tag(&mut self, _tag: Option<TransactionTag>) {}
 }
 
 #[enum_dispatch]
@@ -32,6 +64,7 @@ pub(super) enum SenderImpl<T> {
 
     // Terminated senders are also used to help with default initialization
     Terminated(terminated::TerminatedSender<T>),
+    Closed(closed::ClosedSender<T>),
 
     Void(void::VoidSender<T>),
     Cyclic(BoundedCyclicSender<T>),
@@ -47,7 +80,66 @@ impl<T: Clone> Default for SenderImpl<T> {
 
 pub(crate) struct SenderData<T> {
     pub(crate) spec: InlineSpec,
-    pub(crate) underlying: crate::shim::channel::Sender<ChannelElement<T>>,
+    pub(crate) underlying: crate::shim::channel::Sender<TaggedElement<T>>,
+    pub(crate) link: Option<LinkData<T>>,
+    pub(crate) variable_latency: Option<VariableLatency<T>>,
+    pub(crate) faults: Option<FaultInjector<T>>,
+    pub(crate) tap: TapSlot<T>,
+    // Probes which start on this channel.
+    pub(crate) probes: Vec<Arc<LatencyProbe>>,
+    // The transaction set by the context, which outranks tags started by probes.
+    pub(crate) tag: Option<TransactionTag>,
+    // Transactions tagged on this channel are numbered in the order they are issued.
+    pub(crate) channel: ChannelID,
+    pub(crate) next_tag: u64,
+    // The channel cannot be closed before the last element sent arrives.
+    pub(crate) last_arrival: Time,
+}
+
+/// Computes the latency of an element from its value and the time it is sent.
+pub(crate) type LatencyFn<T> = std::sync::Arc<dyn Fn(&T, Time) -> u64 + Send + Sync>;
+
+/// Per-element latencies, which never let an element overtake an earlier one unless the channel is out of order.
+pub(crate) struct VariableLatency<T> {
+    latency: LatencyFn<T>,
+    last_arrival: Option<Time>,
+}
+
+impl<T> VariableLatency<T> {
+    pub(crate) fn new(latency: LatencyFn<T>, in_order: bool) -> Self {
+        Self {
+            latency,
+            last_arrival: in_order.then(|| Time::new(0)),
+        }
+    }
+}
+
+/// Tracks the occupancy of a bandwidth-limited link, so that back-to-back transfers are serialized.
+pub(crate) struct LinkData<T> {
+    sizer: fn(&T) -> usize,
+    bits_per_cycle: u64,
+    busy_until: Time,
+}
+
+impl<T> LinkData<T> {
+    pub(crate) fn new(sizer: fn(&T) -> usize, bits_per_cycle: u64) -> Self {
+        Self {
+            sizer,
+            bits_per_cycle,
+            busy_until: Time::new(0),
+        }
+    }
+
+    /// Occupies the link for ceil(size / bandwidth) cycles (at least one) starting no earlier than `now`.
+    /// Returns the cycle on which the last bits are sent.
+    fn reserve(&mut self, now: Time, data: &T) -> Time {
+        let occupancy = ((self.sizer)(data) as u64)
+            .div_ceil(self.bits_per_cycle)
+            .max(1);
+        let start = now.max(self.busy_until);
+        self.busy_until = start + occupancy;
+        start + (occupancy - 1)
+    }
 }
 
 trait DataProvider<T> {
@@ -57,25 +149,160 @@ trait DataProvider<T> {
 trait BoundedProvider {
     fn register_send(&mut self);
     fn wait_until_available(&mut self, manager: &TimeManager) -> Result<(), EnqueueError>;
+
+    /// Frees the slots of responses which are already due, and returns how many elements can be sent without waiting.
+    fn reserve(&mut self, manager: &TimeManager) -> usize;
+}
+
+/// The transaction an element belongs to, picked before waiting for space so that the wait counts towards its latency.
+#[derive(Clone, Copy)]
+struct Issued {
+    tag: Option<TransactionTag>,
+    time: Time,
 }
 
 trait SenderCommon<T>: DataProvider<T> + BoundedProvider {
     fn enqueue(
         &mut self,
         manager: &TimeManager,
-        mut data: ChannelElement<T>,
+        data: ChannelElement<T>,
     ) -> Result<(), EnqueueError> {
+        let issued = self.issue(manager);
         if let err @ Err(_) = self.wait_until_available(manager) {
             return err;
         }
-        let min_time = manager.tick() + self.data().spec.send_latency;
-        if data.time < min_time {
-            data.update_time(min_time);
+        self.transmit(manager, data, issued)
+    }
+
+    fn enqueue_many(
+        &mut self,
+        manager: &TimeManager,
+        data: &mut dyn Iterator<Item = ChannelElement<T>>,
+        interval: u64,
+    ) -> Result<(), EnqueueError> {
+        // Slots which are known to be free, so that sending into them doesn't need to wait.
+        let mut reserved = 0;
+        for element in data {
+            let issued = self.issue(manager);
+            if reserved == 0 {
+                self.wait_until_available(manager)?;
+                reserved = self.reserve(manager);
+            }
+            reserved -= 1;
+            self.transmit(manager, element, issued)?;
+            if interval > 0 {
+                manager.incr_cycles(interval);
+            }
+        }
+        Ok(())
+    }
+
+    /// Picks the transaction of the next element, which is a new one if a probe begins here and the context hasn't set one.
+    /// Nothing is recorded until the element is sent, see [SenderCommon::depart].
+    fn issue(&mut self, manager: &TimeManager) -> Issued {
+        let time = manager.tick();
+        if self.data().probes.is_empty() {
+            return Issued {
+                tag: self.data().tag,
+                time,
+            };
+        }
+        let tag = self.data().tag.unwrap_or(TransactionTag {
+            source: self.data().channel,
+            id: self.data().next_tag,
+            origin: time,
+        });
+        Issued {
+            tag: Some(tag),
+            time,
+        }
+    }
+
+    /// Starts the element's transaction on the probes which begin here, returning the probes which hadn't seen it yet.
+    fn depart(&mut self, issued: Issued) -> Vec<Arc<LatencyProbe>> {
+        let Some(tag) = issued.tag else {
+            return vec![];
+        };
+        if self.data().probes.is_empty() {
+            return vec![];
+        }
+        if self.data().tag.is_none() {
+            self.data().next_tag += 1;
         }
         self.data()
-            .underlying
-            .send(data)
-            .map_err(|_| EnqueueError::Closed)?;
+            .probes
+            .iter()
+            .filter(|probe| probe.depart(tag, issued.time))
+            .cloned()
+            .collect()
+    }
+
+    /// Sends an element once space is available for it.
+    fn transmit(
+        &mut self,
+        manager: &TimeManager,
+        mut data: ChannelElement<T>,
+        issued: Issued,
+    ) -> Result<(), EnqueueError> {
+        // Departures are recorded before sending, since the element may reach the end of a probe right away.
+        let departed = self.depart(issued);
+        let send_latency = self.data().spec.send_latency;
+        let mut ready = manager.tick();
+        if let Some(link) = &self.data().link {
+            ready = ready.max(link.busy_until);
+        }
+        // Stalls hold the link, so the transfer and anything queued behind it wait until they end.
+        if let Some(faults) = &mut self.data().faults {
+            ready = faults.stall(ready);
+        }
+        let depart = match &mut self.data().link {
+            Some(link) => link.reserve(ready, &data.data),
+            None => ready,
+        };
+        let mut extra_latency = 0;
+        if let Some(faults) = &mut self.data().faults {
+            match faults.inject(&mut data.data) {
+                Some(latency) => extra_latency = latency,
+                // Dropped elements never reach the receiver, so they don't take up space either.
+                None => return Ok(()),
+            }
+        }
+        match &mut self.data().variable_latency {
+            Some(variable) => {
+                // The declared send latency is a lower bound, which other contexts may rely on.
+                let latency = (variable.latency)(&data.data, depart).max(send_latency);
+                let mut min_time = depart + latency + extra_latency;
+                if let Some(last_arrival) = &mut variable.last_arrival {
+                    min_time = min_time.max(*last_arrival);
+                    *last_arrival = min_time.max(data.time);
+                }
+                if data.time < min_time {
+                    data.update_time(min_time);
+                }
+            }
+            None => {
+                let min_time = depart + send_latency + extra_latency;
+                if data.time < min_time {
+                    data.update_time(min_time);
+                }
+            }
+        }
+        if let Some(faults) = &mut self.data().faults {
+            let arrival = faults.arrive(data.time);
+            data.update_time(arrival);
+        }
+        if let Some(tap) = self.data().tap.get() {
+            tap.record(TapEvent::Enqueue, manager.tick(), data.time, &data.data);
+        }
+        // Elements on out of order channels may arrive before ones sent earlier.
+        self.data().last_arrival = self.data().last_arrival.max(data.time);
+        if self.data().underlying.send((data, issued.tag)).is_err() {
+            // The receiver is gone, so the transaction never really started.
+            for probe in departed {
+                probe.cancel(issued.tag.unwrap());
+            }
+            return Err(EnqueueError::Closed);
+        }
         self.register_send();
         Ok(())
     }
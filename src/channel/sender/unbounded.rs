@@ -1,5 +1,6 @@
 use crate::{
-    channel::{ChannelElement, EnqueueError},
+    channel::{ChannelElement, EnqueueError, TransactionTag},
+    datastructures::Time,
     view::TimeManager,
 };
 
@@ -21,6 +22,10 @@ impl<T> BoundedProvider for UnboundedSender<T> {
     fn wait_until_available(&mut self, _manager: &TimeManager) -> Result<(), EnqueueError> {
         Ok(())
     }
+
+    fn reserve(&mut self, _manager: &TimeManager) -> usize {
+        usize::MAX
+    }
 }
 
 impl<T> SenderCommon<T> for UnboundedSender<T> {}
@@ -37,4 +42,21 @@ impl<T> SenderFlavor<T> for UnboundedSender<T> {
     ) -> Result<(), EnqueueError> {
         SenderCommon::enqueue(self, manager, data)
     }
+
+    fn enqueue_many(
+        &mut self,
+        manager: &TimeManager,
+        data: &mut dyn Iterator<Item = ChannelElement<T>>,
+        interval: u64,
+    ) -> Result<(), EnqueueError> {
+        SenderCommon::enqueue_many(self, manager, data, interval)
+    }
+
+    fn last_arrival(&self) -> Time {
+        self.data.last_arrival
+    }
+
+    fn set_tag(&mut self, tag: Option<TransactionTag>) {
+        self.data.tag = tag;
+    }
 }
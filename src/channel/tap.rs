@@ -0,0 +1,313 @@
+//! Passive taps, which record the traffic on a channel to a trace file without changing its timing.
+//! Taps are configured through [crate::simulation::RunOptionsBuilder::taps], and each tapped element produces one JSON line
+//! when it is enqueued and another when it is dequeued.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+    sync::{Arc, OnceLock},
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{datastructures::Time, shim::Mutex};
+
+use super::ChannelID;
+
+/// How much of each element's payload a tap records.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TapPayload {
+    /// Only record times
+    #[default]
+    None,
+
+    /// Record the payload's [Debug] representation
+    Debug,
+
+    /// Record the payload as JSON, which requires [crate::simulation::ProgramBuilder::serialize_payloads]
+    Serde,
+}
+
+/// Records a channel's traffic to a file. Taps sharing a path write to the same file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelTap {
+    /// The tapped channel
+    pub channel: ChannelID,
+    /// The trace file, which is truncated when the program starts running
+    pub path: PathBuf,
+    /// What to record about each payload
+    pub payload: TapPayload,
+}
+
+impl ChannelTap {
+    /// Taps a channel, recording only times.
+    pub fn new(channel: ChannelID, path: impl Into<PathBuf>) -> Self {
+        Self {
+            channel,
+            path: path.into(),
+            payload: TapPayload::None,
+        }
+    }
+
+    /// Also records payloads.
+    pub fn with_payload(mut self, payload: TapPayload) -> Self {
+        self.payload = payload;
+        self
+    }
+}
+
+/// Errors from setting up taps
+#[derive(Error, Debug)]
+pub enum TapError {
+    /// The trace file could not be created
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    /// Taps must refer to a channel in the program
+    #[error("Tap on unknown channel: {0:?}")]
+    UnknownChannel(ChannelID),
+
+    /// Void channels never carry any traffic, so there is nothing to record
+    #[error("Tap on void channel: {0:?}")]
+    VoidChannel(ChannelID),
+
+    /// Each channel can only be tapped once
+    #[error("Channel {0:?} was tapped more than once")]
+    DuplicateTap(ChannelID),
+
+    /// [TapPayload::Serde] needs to know how to serialize the channel's elements
+    #[error("Channel {0:?} has no serializer, see ProgramBuilder::serialize_payloads")]
+    NoSerializer(ChannelID),
+}
+
+/// Which end of the channel a [TapRecord] was taken at.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TapEvent {
+    /// The sender enqueued an element
+    Enqueue,
+    /// The receiver dequeued an element
+    Dequeue,
+}
+
+/// A single line of a trace file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TapRecord {
+    /// The tapped channel
+    pub channel: ChannelID,
+    /// Which end of the channel this was recorded at
+    pub event: TapEvent,
+    /// The time of the context performing the operation
+    pub time: Time,
+    /// The element's timestamp, which is when it is available to the receiver
+    pub element_time: Time,
+    /// The element's payload, if requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload: Option<serde_json::Value>,
+}
+
+pub(crate) type TapFile = Arc<Mutex<BufWriter<File>>>;
+
+/// Converts a payload for a [TapRecord].
+pub(crate) type TapFormatter<T> = fn(&T) -> serde_json::Value;
+
+pub(crate) fn debug_payload<T: std::fmt::Debug>(data: &T) -> serde_json::Value {
+    serde_json::Value::String(format!("{data:?}"))
+}
+
+pub(crate) fn serialize_payload<T: Serialize>(data: &T) -> serde_json::Value {
+    serde_json::to_value(data).unwrap_or(serde_json::Value::Null)
+}
+
+/// The records of a single channel, split by which end they were taken at.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct ChannelTraffic {
+    pub(crate) enqueues: Vec<TapRecord>,
+    pub(crate) dequeues: Vec<TapRecord>,
+}
+
+/// The tap installed on a channel, shared by both of its ends.
+pub(crate) struct TapWriter<T> {
+    channel: ChannelID,
+    file: TapFile,
+    payload: Option<TapFormatter<T>>,
+}
+
+/// Taps are installed when the program is initialized, after the channel's ends have been created.
+pub(crate) type TapSlot<T> = Arc<OnceLock<TapWriter<T>>>;
+
+impl<T> TapWriter<T> {
+    pub(crate) fn new(channel: ChannelID, file: TapFile, payload: Option<TapFormatter<T>>) -> Self {
+        Self {
+            channel,
+            file,
+            payload,
+        }
+    }
+
+    pub(crate) fn record(&self, event: TapEvent, time: Time, element_time: Time, data: &T) {
+        let record = TapRecord {
+            channel: self.channel,
+            event,
+            time,
+            element_time,
+            payload: self.payload.map(|format| format(data)),
+        };
+        let mut line = serde_json::to_vec(&record).expect("Tap records are always serializable");
+        line.push(b'\n');
+        self.file
+            .lock()
+            .unwrap()
+            .write_all(&line)
+            .unwrap_or_else(|err| panic!("Could not write tap for {:?}: {err}", self.channel));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        channel::ChannelID,
+        simulation::{ProgramBuilder, RunOptionsBuilder},
+        utility_contexts::{CollectorContext, GeneratorContext},
+    };
+
+    use super::{ChannelTap, TapError, TapEvent, TapPayload, TapRecord};
+
+    /// Returns the arrival times seen on each channel, with the channels tapped into `path`.
+    fn tapped(tap: bool, path: &std::path::Path) -> Vec<(ChannelID, Vec<u64>)> {
+        let mut ctx = ProgramBuilder::default();
+        let (snd, rcv) = ctx.bounded_with_latency::<u32>(2, 3, 2);
+        let (serde_snd, serde_rcv) = ctx.bounded::<u32>(2);
+        ctx.serialize_payloads(&serde_snd);
+        let taps = vec![
+            ChannelTap::new(snd.id(), path).with_payload(TapPayload::Debug),
+            ChannelTap::new(serde_snd.id(), path).with_payload(TapPayload::Serde),
+        ];
+        ctx.add_child(GeneratorContext::new(|| 0..16u32, snd));
+        ctx.add_child(GeneratorContext::new(|| 16..32u32, serde_snd));
+
+        let handles: Vec<_> = [rcv, serde_rcv]
+            .into_iter()
+            .map(|rcv| {
+                let id = rcv.id();
+                let (collector, handle) = CollectorContext::new(rcv);
+                ctx.add_child(collector);
+                (id, handle)
+            })
+            .collect();
+
+        let options = RunOptionsBuilder::default()
+            .taps(if tap { taps } else { vec![] })
+            .build()
+            .unwrap();
+        let executed = ctx.initialize(Default::default()).unwrap().run(options);
+        assert!(executed.passed());
+        handles
+            .into_iter()
+            .map(|(id, handle)| {
+                let times = handle.times().iter().map(|time| time.time()).collect();
+                (id, times)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_tap_records_traffic() {
+        let path = std::env::temp_dir().join(format!("dam-tap-{}.jsonl", std::process::id()));
+        let untapped = tapped(false, &path);
+        assert_eq!(tapped(true, &path), untapped);
+
+        let records: Vec<TapRecord> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(records.len(), 64);
+
+        let dequeues = |channel: ChannelID| -> Vec<&TapRecord> {
+            records
+                .iter()
+                .filter(|record| record.channel == channel && record.event == TapEvent::Dequeue)
+                .collect()
+        };
+        for (channel, times) in &untapped {
+            let dequeues = dequeues(*channel);
+            assert_eq!(
+                dequeues
+                    .iter()
+                    .map(|record| record.element_time.time())
+                    .collect::<Vec<_>>(),
+                *times
+            );
+            assert!(dequeues
+                .iter()
+                .all(|record| record.time >= record.element_time));
+        }
+        assert_eq!(
+            dequeues(untapped[0].0)[0].payload,
+            Some(serde_json::json!("0"))
+        );
+        assert_eq!(
+            dequeues(untapped[1].0)[15].payload,
+            Some(serde_json::json!(31))
+        );
+    }
+
+    #[test]
+    fn test_invalid_taps() {
+        let path = std::env::temp_dir().join(format!("dam-bad-tap-{}.jsonl", std::process::id()));
+        // Taps one of the channel, the void channel, or a channel outside of the program.
+        let run = |pick: fn(ChannelID, ChannelID) -> ChannelID, payload| {
+            let mut ctx = ProgramBuilder::default();
+            let (snd, rcv) = ctx.unbounded::<u32>();
+            let void = ctx.void::<u32>();
+            let tap = ChannelTap::new(pick(snd.id(), void.id()), &path).with_payload(payload);
+            ctx.add_child(GeneratorContext::new(|| 0..4u32, snd));
+            ctx.add_child(GeneratorContext::new(|| 0..4u32, void));
+            ctx.add_child(CollectorContext::new(rcv).0);
+            ctx.initialize(Default::default())
+                .unwrap()
+                .try_run(
+                    RunOptionsBuilder::default()
+                        .taps(vec![tap])
+                        .build()
+                        .unwrap(),
+                )
+                .err()
+        };
+
+        assert!(matches!(
+            run(|_, void| void, TapPayload::None),
+            Some(TapError::VoidChannel(_))
+        ));
+        assert!(matches!(
+            run(|_, _| ChannelID::new(), TapPayload::None),
+            Some(TapError::UnknownChannel(_))
+        ));
+        assert!(matches!(
+            run(|snd, _| snd, TapPayload::Serde),
+            Some(TapError::NoSerializer(_))
+        ));
+
+        // Running without checking the taps first reports the error instead of running the program.
+        let mut ctx = ProgramBuilder::default();
+        let void = ctx.void::<u32>();
+        let tap = ChannelTap::new(void.id(), &path);
+        ctx.add_child(GeneratorContext::new(|| 0..4u32, void));
+        let executed = ctx.initialize(Default::default()).unwrap().run(
+            RunOptionsBuilder::default()
+                .taps(vec![tap])
+                .build()
+                .unwrap(),
+        );
+        assert!(!executed.passed());
+        assert!(executed.summaries().is_empty());
+        executed.run_failures(|failures| {
+            assert_eq!(failures.len(), 1);
+            assert!(failures[0].to_string().contains("Tap on void channel"));
+        });
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -12,8 +12,9 @@ pub enum EventTime {
     /// Event won't happen up to timestamp -- useful if we wish to determine the first event in a list.
     Nothing(Time),
 
-    /// Event will never happen, roughly equivalent to Nothing(Infinity)
-    Closed,
+    /// Event will never happen, roughly equivalent to Nothing(Infinity).
+    /// Carries the time the channel was closed at, which is infinite unless it was closed with [Sender::close_at].
+    Closed(Time),
 }
 
 impl EventTime {
@@ -21,7 +22,7 @@ impl EventTime {
         match self {
             EventTime::Ready(time) => *time,
             EventTime::Nothing(time) => *time + 1,
-            EventTime::Closed => Time::infinite(),
+            EventTime::Closed(_) => Time::infinite(),
         }
     }
 }
@@ -53,9 +54,9 @@ impl Peekable for EventTime {
 impl<T: DAMType> Peekable for &Receiver<T> {
     fn next_event(self) -> EventTime {
         match self.peek() {
-            PeekResult::Closed => EventTime::Closed,
+            PeekResult::Closed => EventTime::Closed(self.close_time().unwrap_or(Time::infinite())),
             PeekResult::Something(time) => EventTime::Ready(time.time),
-            PeekResult::Nothing(time) if time.is_infinite() => EventTime::Closed,
+            PeekResult::Nothing(time) if time.is_infinite() => EventTime::Closed(time),
             PeekResult::Nothing(time) => EventTime::Nothing(time),
         }
     }
@@ -73,7 +74,7 @@ where
 {
     fn next_event(self) -> EventTime {
         let events = self.under.map(|thing| thing.next_event());
-        events.max().unwrap_or(EventTime::Closed)
+        events.max().unwrap_or(EventTime::Closed(Time::infinite()))
     }
 }
 
@@ -89,7 +90,7 @@ where
 {
     fn next_event(self) -> EventTime {
         let events = self.under.map(|thing| thing.next_event());
-        events.min().unwrap_or(EventTime::Closed)
+        events.min().unwrap_or(EventTime::Closed(Time::infinite()))
     }
 }
 
@@ -123,7 +124,9 @@ where
 #[cfg(test)]
 mod tests {
     use crate::{
-        simulation::ProgramBuilder,
+        channel::{ChannelElement, CloseError, EnqueueError},
+        datastructures::Time,
+        simulation::{InitializationOptionsBuilder, ProgramBuilder},
         utility_contexts::{random_trace, FunctionContext, TraceContext},
     };
 
@@ -167,13 +170,13 @@ mod tests {
                     );
                 }
 
-                (EventTime::Nothing(_), EventTime::Closed, Err(_)) => {
+                (EventTime::Nothing(_), EventTime::Closed(_), Err(_)) => {
                     // Originally was just nothing, but now we've found out that it's closed.
                     // For completeness, delegate it to the next iteration
                     // Otherwise it's possible to never test the next case.
                     continue;
                 }
-                (EventTime::Closed, EventTime::Closed, Err(_)) => {
+                (EventTime::Closed(_), EventTime::Closed(_), Err(_)) => {
                     // Next events both said closed, was closed
                     return;
                 }
@@ -188,4 +191,75 @@ mod tests {
             .unwrap()
             .run(Default::default());
     }
+
+    fn close_at(flavor_inference: bool) {
+        let mut ctx = ProgramBuilder::default();
+        let (snd, rcv) = ctx.bounded::<u32>(4);
+
+        let mut sender = FunctionContext::default();
+        snd.attach_sender(&sender);
+        sender.set_run(move |time| {
+            for i in 0..4 {
+                snd.enqueue(time, ChannelElement::new(time.tick() + 10, i))
+                    .unwrap();
+                time.incr_cycles(1);
+            }
+            assert_eq!(
+                snd.close_at(time, Time::new(2)),
+                Err(CloseError::InThePast {
+                    requested: Time::new(2),
+                    current: Time::new(4)
+                })
+            );
+            // The last element arrives at time 13, so the channel cannot be closed before then.
+            assert_eq!(
+                snd.close_at(time, Time::new(5)),
+                Err(CloseError::BeforeLastArrival {
+                    requested: Time::new(5),
+                    last_arrival: Time::new(13)
+                })
+            );
+            snd.close_at(time, Time::new(20)).unwrap();
+            assert_eq!(
+                snd.close_at(time, Time::new(30)),
+                Err(CloseError::AlreadyClosed)
+            );
+            assert!(matches!(
+                snd.enqueue(time, ChannelElement::new(time.tick(), 4)),
+                Err(EnqueueError::Closed)
+            ));
+            // The sender stays alive well past the close.
+            time.incr_cycles(1000);
+        });
+        ctx.add_child(sender);
+
+        let mut receiver = FunctionContext::default();
+        rcv.attach_receiver(&receiver);
+        receiver.set_run(move |time| {
+            for i in 0..4 {
+                assert_eq!(rcv.dequeue(time).unwrap().data, i);
+            }
+            assert!(rcv.dequeue(time).is_err());
+            assert_eq!(time.tick(), Time::new(20));
+            assert_eq!(rcv.next_event(), EventTime::Closed(Time::new(20)));
+        });
+        ctx.add_child(receiver);
+
+        let executed = ctx
+            .initialize(
+                InitializationOptionsBuilder::default()
+                    .run_flavor_inference(flavor_inference)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap()
+            .run(Default::default());
+        assert!(executed.passed());
+    }
+
+    #[test]
+    fn test_close_at() {
+        close_at(false);
+        close_at(true);
+    }
 }
@@ -81,6 +81,13 @@ pub trait Context: Send + Sync + TimeViewable + Identifiable {
         HashMap::from([(self.verbose(), HashSet::new())])
     }
 
+    /// Replaces the IDs of this context and of the children it reports in [Context::ids], see [Identifiable::set_id].
+    /// Composite contexts which override [Context::ids] should also renumber their children here.
+    fn renumber(&mut self, renumber: &mut dyn FnMut(Identifier) -> Identifier) {
+        let id = renumber(self.id());
+        self.set_id(id);
+    }
+
     /// By default all edges are connected.
     /// In the case of something like a PMU, however, we wish to be finer-grained than that.
     /// In that case, we can report channel A -> {B, C, D} means that A sends data that can be observed on B, C, and/or D.
@@ -1,19 +1,112 @@
-use std::sync::atomic::AtomicUsize;
+use std::{
+    cell::RefCell,
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
 
 use serde::{Deserialize, Serialize};
 
-/// A guaranteed unique identifier for a context.
+/// A per-program source of context and channel identifiers, owned by a [crate::simulation::ProgramBuilder].
+/// While it is installed on a thread, [Identifier::new] and [crate::channel::ChannelID::new] draw from it,
+/// so the same program always receives the same IDs.
+#[derive(Default, Debug)]
+pub(crate) struct IdAllocator {
+    contexts: AtomicUsize,
+    channels: AtomicUsize,
+}
+
+/// IDs created while no allocator is installed come from the upper half of the ID space,
+/// so that they can never collide with the IDs of a program.
+const LOOSE_BASE: usize = 1 << (usize::BITS - 1);
+
+static LOOSE_IDS: IdAllocator = IdAllocator {
+    contexts: AtomicUsize::new(LOOSE_BASE),
+    channels: AtomicUsize::new(LOOSE_BASE),
+};
+
+thread_local! {
+    // Allocators installed for the duration of a call, such as while a builder's contexts are constructed.
+    static INSTALLED_ALLOCATORS: RefCell<Vec<Arc<IdAllocator>>> = const { RefCell::new(Vec::new()) };
+}
+
+impl IdAllocator {
+    /// Makes this allocator the active one for the current thread until the guard is dropped.
+    pub(crate) fn install(self: &Arc<Self>) -> IdScope {
+        INSTALLED_ALLOCATORS.with_borrow_mut(|active| active.push(self.clone()));
+        IdScope {
+            allocator: self.clone(),
+            _thread_bound: PhantomData,
+        }
+    }
+
+    fn with_active<R>(f: impl FnOnce(&IdAllocator) -> R) -> R {
+        match INSTALLED_ALLOCATORS.with_borrow(|active| active.last().cloned()) {
+            Some(alloc) => f(&alloc),
+            None => f(&LOOSE_IDS),
+        }
+    }
+
+    pub(crate) fn next_context() -> usize {
+        Self::with_active(|alloc| alloc.contexts.fetch_add(1, Ordering::Relaxed))
+    }
+
+    pub(crate) fn next_channel() -> usize {
+        Self::with_active(|alloc| alloc.channels.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Draws a context identifier from this allocator, whether or not it is installed.
+    pub(crate) fn next_identifier(&self) -> Identifier {
+        Identifier {
+            id: self.contexts.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+}
+
+/// Keeps an [IdAllocator] active on the current thread, see [IdAllocator::install].
+/// The scope is not [Send], since dropping it on another thread would leave the allocator installed on this one.
+#[derive(Debug)]
+pub(crate) struct IdScope {
+    allocator: Arc<IdAllocator>,
+    _thread_bound: PhantomData<*const ()>,
+}
+
+impl Drop for IdScope {
+    fn drop(&mut self) {
+        INSTALLED_ALLOCATORS.with_borrow_mut(|active| {
+            if let Some(pos) = active
+                .iter()
+                .rposition(|alloc| Arc::ptr_eq(alloc, &self.allocator))
+            {
+                active.remove(pos);
+            }
+        });
+    }
+}
+
+/// A unique identifier for a context.
+/// Identifiers are allocated per-program when contexts are added to a [crate::simulation::ProgramBuilder],
+/// and are stable across runs as long as contexts are added in the same order.
 #[derive(Clone, Debug, PartialEq, Eq, Hash, Copy, Serialize, Deserialize)]
 pub struct Identifier {
     /// The actual ID
     pub id: usize,
 }
-static COUNTER: AtomicUsize = AtomicUsize::new(0);
 impl Identifier {
-    /// Obtains a new identifier by incrementing an atomic ID.
+    /// Obtains a new identifier from the [crate::simulation::ProgramBuilder] whose IDs are in scope, see [crate::simulation::ProgramBuilder::with_ids].
+    /// Outside of a builder's scope, identifiers are drawn from a process-wide range which never collides with a program's,
+    /// and are replaced by the builder once the context is passed to [crate::simulation::ProgramBuilder::add_child].
     pub fn new() -> Self {
-        let id = COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-        Self { id }
+        Self {
+            id: IdAllocator::next_context(),
+        }
+    }
+
+    /// Whether this identifier was drawn outside of any builder's scope, and still needs to be numbered by its program.
+    pub(crate) fn is_loose(&self) -> bool {
+        self.id >= LOOSE_BASE
     }
 }
 
@@ -35,7 +128,7 @@ pub struct VerboseIdentifier {
     /// The underlying identifier
     pub id: Identifier,
 
-    /// Some convenient name for debugging/visualization, usually the type of the Context.
+    /// Some convenient name for debugging/visualization, either the instance name or the type of the Context.
     pub name: String,
 }
 
@@ -44,9 +137,12 @@ pub trait Identifiable {
     /// Retrieves the identifier of the context.
     fn id(&self) -> Identifier;
 
-    /// Gets the name of the context, usually the type name of the context.
+    /// Gets the name of the context, which is the instance name if one was set and the type name otherwise.
     fn name(&self) -> String;
 
+    /// Replaces the identifier of the context, which is used to number it once it is added to a program.
+    fn set_id(&mut self, id: Identifier);
+
     /// Utility method to get both the id and the name, useful for debugging/logging.
     fn verbose(&self) -> VerboseIdentifier {
         VerboseIdentifier {
@@ -23,4 +23,15 @@ pub struct ContextInfo {
 
     /// The context's identifier
     pub id: identifier::Identifier,
+
+    /// A per-instance name, which takes precedence over the type name in [Identifiable::name].
+    pub name: Option<String>,
+}
+
+impl ContextInfo {
+    /// Sets the name used to identify this particular instance in DOT graphs, logs, and errors.
+    /// Composite contexts use `/`-separated paths for their children, such as `PMU/ReadPipeline`.
+    pub fn set_name(&mut self, name: impl Into<String>) {
+        self.name = Some(name.into());
+    }
 }
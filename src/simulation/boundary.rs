@@ -0,0 +1,523 @@
+//! Recording the traffic on a cut through a program, and replaying it so that one side of the cut can be simulated on its own.
+//! See [BoundaryRecording] and [ProgramBuilder::replay_subgraph].
+
+use std::{path::Path, sync::Arc};
+
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+
+use crate::{
+    channel::{
+        handle::ChannelData,
+        tap::{serialize_payload, ChannelTap, ChannelTraffic, TapEvent, TapPayload, TapRecord},
+        ChannelElement, ChannelID, Receiver, Sender,
+    },
+    context::Context,
+    datastructures::{Identifier, Time},
+    types::DAMType,
+    utility_contexts::{ReplayCheckerContext, ReplayContext},
+};
+
+use super::{ContextSelector, PortDirection, ProgramBuilder, ReplaceError};
+
+/// Errors from loading a [BoundaryRecording] or replaying it via [ProgramBuilder::replay_subgraph]
+#[derive(Error, Debug)]
+pub enum ReplayError {
+    /// The recording could not be read
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    /// The recording could not be parsed
+    #[error("Could not parse recording: {0}")]
+    Parse(String),
+
+    /// The context inside of the cut could not be found
+    #[error(transparent)]
+    Select(#[from] ReplaceError),
+
+    /// Channels crossing the cut must be registered with [ProgramBuilder::replayable]
+    #[error("Channel {0:?} is not replayable")]
+    NotReplayable(ChannelID),
+
+    /// A recorded payload could not be converted back into the channel's type
+    #[error("Could not decode a payload on channel {0:?}: {1}")]
+    Payload(ChannelID, String),
+}
+
+/// Traffic recorded on the channels of a cut through a program, which can be used to simulate one side of the cut on its own.
+/// Record a full run with the taps from [BoundaryRecording::taps], then load the trace and pass it to [ProgramBuilder::replay_subgraph].
+#[derive(Debug, Default, Clone)]
+pub struct BoundaryRecording {
+    traffic: FxHashMap<ChannelID, ChannelTraffic>,
+}
+
+impl BoundaryRecording {
+    /// Taps which record the channels of a cut into a single trace file.
+    /// Each channel must be registered with [ProgramBuilder::replayable] so that its payloads are recorded.
+    pub fn taps(
+        channels: impl IntoIterator<Item = ChannelID>,
+        path: impl AsRef<Path>,
+    ) -> Vec<ChannelTap> {
+        channels
+            .into_iter()
+            .map(|channel| ChannelTap::new(channel, path.as_ref()).with_payload(TapPayload::Serde))
+            .collect()
+    }
+
+    /// Groups tap records by channel. Every recorded channel is part of the cut.
+    pub fn from_records(records: impl IntoIterator<Item = TapRecord>) -> Self {
+        let mut traffic: FxHashMap<_, ChannelTraffic> = FxHashMap::default();
+        for record in records {
+            let entry = traffic.entry(record.channel).or_default();
+            match record.event {
+                TapEvent::Enqueue => entry.enqueues.push(record),
+                TapEvent::Dequeue => entry.dequeues.push(record),
+            }
+        }
+        Self { traffic }
+    }
+
+    /// Reads a trace file written by the taps from [BoundaryRecording::taps].
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, ReplayError> {
+        let text = std::fs::read_to_string(path)?;
+        let records = text
+            .lines()
+            .map(|line| {
+                serde_json::from_str(line).map_err(|err| ReplayError::Parse(err.to_string()))
+            })
+            .collect::<Result<Vec<TapRecord>, _>>()?;
+        Ok(Self::from_records(records))
+    }
+
+    /// The channels of the cut
+    pub fn channels(&self) -> Vec<ChannelID> {
+        let mut channels: Vec<_> = self.traffic.keys().copied().collect();
+        channels.sort();
+        channels
+    }
+}
+
+fn decode<T: DeserializeOwned>(record: &TapRecord) -> Result<T, ReplayError> {
+    let payload = record.payload.clone().ok_or_else(|| {
+        ReplayError::Payload(record.channel, "no payload was recorded".to_string())
+    })?;
+    serde_json::from_value(payload)
+        .map_err(|err| ReplayError::Payload(record.channel, err.to_string()))
+}
+
+/// Decodes the recorded traffic of a cut channel, returning a constructor for the context which stands in for its far side.
+pub(super) type Replayer<'a> = Box<
+    dyn Fn(PortDirection, &ChannelTraffic) -> Result<PendingReplay<'a>, ReplayError>
+        + Send
+        + Sync
+        + 'a,
+>;
+
+/// Attaches a decoded replay to its channel. Constructing it is deferred until the far side of the cut has been detached.
+pub(super) type PendingReplay<'a> = Box<dyn FnOnce() -> Box<dyn Context + 'a> + 'a>;
+
+fn replay<'a, T>(
+    channel: Arc<ChannelData<T>>,
+    direction: PortDirection,
+    traffic: &ChannelTraffic,
+) -> Result<PendingReplay<'a>, ReplayError>
+where
+    T: DAMType + PartialEq + DeserializeOwned + 'static,
+{
+    let records = match direction {
+        PortDirection::Input => &traffic.enqueues,
+        // Elements are checked in the order they were dequeued, which may differ from the order they were sent on out of order channels.
+        // Elements which were never dequeued in the full run aren't checked.
+        PortDirection::Output => &traffic.dequeues,
+    };
+    let paced = records
+        .iter()
+        .map(|record| {
+            Ok((
+                record.time,
+                ChannelElement::new(record.element_time, decode(record)?),
+            ))
+        })
+        .collect::<Result<Vec<(Time, _)>, ReplayError>>()?;
+    Ok(Box::new(move || -> Box<dyn Context + 'a> {
+        match direction {
+            PortDirection::Input => Box::new(ReplayContext::new(
+                paced,
+                Sender {
+                    underlying: channel,
+                },
+            )),
+            PortDirection::Output => Box::new(ReplayCheckerContext::new(
+                paced,
+                Receiver {
+                    underlying: channel,
+                },
+            )),
+        }
+    }))
+}
+
+impl<'a> ProgramBuilder<'a> {
+    /// Records payloads on a channel, and allows it to be replayed if it is part of a cut.
+    /// See [BoundaryRecording] and [ProgramBuilder::replay_subgraph].
+    pub fn replayable<T>(&mut self, sender: &Sender<T>)
+    where
+        T: DAMType + PartialEq + Serialize + DeserializeOwned + 'static,
+    {
+        sender
+            .underlying
+            .set_serde_formatter(serialize_payload::<T>);
+        let channel = sender.underlying.clone();
+        self.replayers.insert(
+            sender.id(),
+            Box::new(move |direction, traffic| replay(channel.clone(), direction, traffic)),
+        );
+    }
+}
+
+impl ProgramBuilder<'static> {
+    /// Reduces a program to the subgraph on one side of a recorded cut, so that it can be simulated on its own.
+    /// The subgraph contains `inside` and every context reachable from it without crossing a channel of the cut.
+    /// Everything else is dropped, and each cut channel is instead driven by a [ReplayContext] at the recorded times
+    /// or checked by a [ReplayCheckerContext] against the recorded elements.
+    /// The program must be built the same way as the recorded one, so that its channel IDs match.
+    pub fn replay_subgraph(
+        &mut self,
+        recording: &BoundaryRecording,
+        inside: impl Into<ContextSelector>,
+    ) -> Result<(), ReplayError> {
+        let seed = self.find_node(&inside.into())?;
+
+        // Hierarchical contexts may attach their children to channels, so map every ID to its top-level context.
+        let owners: FxHashMap<Identifier, usize> = self
+            .data
+            .nodes
+            .iter()
+            .enumerate()
+            .flat_map(|(index, node)| {
+                node.ids()
+                    .into_keys()
+                    .map(move |verbose| (verbose.id, index))
+            })
+            .collect();
+        let owner = |id: Option<Identifier>| id.and_then(|id| owners.get(&id).copied());
+
+        let mut kept = FxHashSet::default();
+        kept.insert(seed);
+        let mut frontier = vec![seed];
+        while let Some(node) = frontier.pop() {
+            for edge in &self.data.edges {
+                if recording.traffic.contains_key(&edge.id()) {
+                    continue;
+                }
+                let next = match (owner(edge.sender()), owner(edge.receiver())) {
+                    (Some(snd), Some(rcv)) if snd == node => rcv,
+                    (Some(snd), Some(rcv)) if rcv == node => snd,
+                    _ => continue,
+                };
+                if kept.insert(next) {
+                    frontier.push(next);
+                }
+            }
+        }
+        let inside = |id: Option<Identifier>| owner(id).is_some_and(|index| kept.contains(&index));
+
+        let mut crossing = vec![];
+        let mut removed = vec![];
+        for edge in &self.data.edges {
+            match (inside(edge.sender()), inside(edge.receiver())) {
+                (true, true) => {}
+                (false, false) => removed.push(edge.id()),
+                (false, true) => crossing.push((edge.clone(), PortDirection::Input)),
+                (true, false) => crossing.push((edge.clone(), PortDirection::Output)),
+            }
+        }
+        removed.extend(
+            self.data
+                .void_edges
+                .iter()
+                .filter(|edge| !inside(edge.sender()))
+                .map(|edge| edge.id()),
+        );
+
+        // Decode every replay before touching the builder, so that it is left unchanged if one fails.
+        let empty = ChannelTraffic::default();
+        let replays = crossing
+            .into_iter()
+            .map(|(edge, direction)| {
+                let replayer = self
+                    .replayers
+                    .get(&edge.id())
+                    .ok_or(ReplayError::NotReplayable(edge.id()))?;
+                let traffic = recording.traffic.get(&edge.id()).unwrap_or(&empty);
+                Ok((edge, direction, replayer(direction, traffic)?))
+            })
+            .collect::<Result<Vec<_>, ReplayError>>()?;
+
+        // Drop the outside contexts first, since dropping their endpoints closes the cut channels.
+        let nodes = std::mem::take(&mut self.data.nodes);
+        self.data.nodes = nodes
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, node)| kept.contains(&index).then_some(node))
+            .collect();
+        self.data.edges.retain(|edge| !removed.contains(&edge.id()));
+        self.data
+            .void_edges
+            .retain(|edge| !removed.contains(&edge.id()));
+        for id in &removed {
+            self.forget_channel(*id);
+        }
+
+        for (edge, direction, replay) in replays {
+            match direction {
+                PortDirection::Input => edge.detach_sender(),
+                PortDirection::Output => edge.detach_receiver(),
+            }
+            let replay = self.with_ids(replay);
+            self.add_node(replay);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        channel::{ChannelElement, ChannelID},
+        simulation::{InitializationOptions, ProgramBuilder, RunOptionsBuilder, Subgraph},
+        utility_contexts::{ConsumerContext, FunctionContext, GeneratorContext},
+    };
+
+    use super::{BoundaryRecording, ReplayError};
+
+    /// A generator feeding two stages and a sink, returning the channels into and out of the stages.
+    fn pipeline(increment: u32) -> (ProgramBuilder<'static>, [ChannelID; 2]) {
+        let mut ctx = ProgramBuilder::default();
+        let (gen_snd, gen_rcv) = ctx.bounded_with_latency::<u32>(2, 2, 1);
+        let (mid_snd, mid_rcv) = ctx.bounded::<u32>(2);
+        let (out_snd, out_rcv) = ctx.bounded_with_latency::<u32>(2, 3, 1);
+        ctx.replayable(&gen_snd);
+        ctx.replayable(&out_snd);
+        let cut = [gen_snd.id(), out_snd.id()];
+
+        ctx.add_child(GeneratorContext::new(|| 0..32u32, gen_snd));
+        for (name, input, output, stage) in [
+            ("double", gen_rcv, mid_snd, 2),
+            ("offset", mid_rcv, out_snd, increment),
+        ] {
+            let mut ctx_stage = FunctionContext::default();
+            ctx_stage.set_name(name);
+            input.attach_receiver(&ctx_stage);
+            output.attach_sender(&ctx_stage);
+            ctx_stage.set_run(move |time| {
+                while let Ok(element) = input.dequeue(time) {
+                    let data = if name == "double" {
+                        element.data * stage
+                    } else {
+                        element.data + stage
+                    };
+                    time.incr_cycles(u64::from(element.data % 3));
+                    // A failed checker stops receiving, which closes the channel.
+                    if output
+                        .enqueue(time, ChannelElement::new(time.tick() + 1, data))
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            });
+            ctx.add_child(ctx_stage);
+        }
+        ctx.add_child(ConsumerContext::new(out_rcv));
+        (ctx, cut)
+    }
+
+    #[test]
+    fn test_boundary_replay() {
+        let path = std::env::temp_dir().join(format!("dam-boundary-{}.jsonl", std::process::id()));
+        let (full, cut) = pipeline(1);
+        let executed = full
+            .initialize(InitializationOptions::default())
+            .unwrap()
+            .run(
+                RunOptionsBuilder::default()
+                    .taps(BoundaryRecording::taps(cut, &path))
+                    .build()
+                    .unwrap(),
+            );
+        assert!(executed.passed());
+        let recording = BoundaryRecording::from_path(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut expected = cut.to_vec();
+        expected.sort();
+        assert_eq!(recording.channels(), expected);
+
+        for (increment, passes) in [(1, true), (2, false)] {
+            let (mut partial, _) = pipeline(increment);
+            partial.replay_subgraph(&recording, "double").unwrap();
+            // Both stages, plus a replay context and a checker in place of the generator and sink.
+            assert_eq!(partial.num_children(), 4);
+            let executed = partial
+                .initialize(InitializationOptions::default())
+                .unwrap()
+                .run(Default::default());
+            assert_eq!(executed.passed(), passes);
+        }
+
+        // A replay which fails to decode leaves the full program in place.
+        let mut corrupted = recording.clone();
+        corrupted.traffic.get_mut(&cut[1]).unwrap().dequeues[0].payload = None;
+        let (mut full, _) = pipeline(1);
+        assert!(matches!(
+            full.replay_subgraph(&corrupted, "double"),
+            Err(ReplayError::Payload(..))
+        ));
+        let executed = full
+            .initialize(InitializationOptions::default())
+            .unwrap()
+            .run(Default::default());
+        assert!(executed.passed());
+    }
+
+    #[test]
+    fn test_boundary_replay_through_subgraph() {
+        let path = std::env::temp_dir().join(format!(
+            "dam-boundary-subgraph-{}.jsonl",
+            std::process::id()
+        ));
+        let stage = Subgraph::new("Stage", |sg| {
+            let input = sg.input::<u32>("in")?;
+            let output = sg.output::<u32>("out")?;
+            let mut ctx = FunctionContext::new();
+            input.attach_receiver(&ctx);
+            output.attach_sender(&ctx);
+            ctx.set_run(move |time| {
+                while let Ok(element) = input.dequeue(time) {
+                    time.incr_cycles(1);
+                    if output
+                        .enqueue(time, ChannelElement::new(time.tick() + 1, element.data + 1))
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            });
+            sg.add_child(ctx);
+            Ok(())
+        })
+        .input::<u32>("in")
+        .output::<u32>("out");
+        // Two instances in series, with the cut around the pair of them.
+        let program = || {
+            let mut ctx = ProgramBuilder::default();
+            let first = ctx.instantiate(&stage, "first");
+            let second = ctx.instantiate(&stage, "second");
+            let (gen_snd, gen_rcv) = ctx.bounded_with_latency::<u32>(2, 2, 1);
+            let (out_snd, out_rcv) = ctx.bounded::<u32>(2);
+            ctx.replayable(&gen_snd);
+            ctx.replayable(&out_snd);
+            let cut = [gen_snd.id(), out_snd.id()];
+            ctx.bind_input(first, "in", gen_rcv).unwrap();
+            ctx.connect::<u32>(first, "out", second, "in", 2).unwrap();
+            ctx.bind_output(second, "out", out_snd).unwrap();
+            ctx.add_child(GeneratorContext::new(|| 0..16u32, gen_snd));
+            ctx.add_child(ConsumerContext::new(out_rcv));
+            (ctx, cut)
+        };
+
+        let (full, cut) = program();
+        let executed = full
+            .initialize(InitializationOptions::default())
+            .unwrap()
+            .run(
+                RunOptionsBuilder::default()
+                    .taps(BoundaryRecording::taps(cut, &path))
+                    .build()
+                    .unwrap(),
+            );
+        assert!(executed.passed());
+        let recording = BoundaryRecording::from_path(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let (mut partial, _) = program();
+        // Selecting a context inside of an instance elaborates the instances first.
+        partial
+            .replay_subgraph(&recording, "first/FunctionContext")
+            .unwrap();
+        assert_eq!(partial.num_children(), 4);
+        let executed = partial
+            .initialize(InitializationOptions::default())
+            .unwrap()
+            .run(Default::default());
+        assert!(executed.passed());
+    }
+
+    #[test]
+    fn test_boundary_replay_out_of_order() {
+        let path = std::env::temp_dir().join(format!(
+            "dam-boundary-out-of-order-{}.jsonl",
+            std::process::id()
+        ));
+        // Later elements finish sooner, so the sink receives them in a different order than they were sent.
+        let program = || {
+            let mut ctx = ProgramBuilder::default();
+            let (gen_snd, gen_rcv) = ctx.bounded::<u32>(2);
+            let (out_snd, out_rcv) = ctx.bounded_out_of_order::<u32>(8, 1, 1);
+            ctx.replayable(&gen_snd);
+            ctx.replayable(&out_snd);
+            let cut = [gen_snd.id(), out_snd.id()];
+
+            let mut stage = FunctionContext::new();
+            stage.set_name("stage");
+            gen_rcv.attach_receiver(&stage);
+            out_snd.attach_sender(&stage);
+            stage.set_run(move |time| {
+                while let Ok(element) = gen_rcv.dequeue(time) {
+                    let delay = u64::from(4 - element.data % 4) * 3;
+                    if out_snd
+                        .enqueue(time, ChannelElement::new(time.tick() + delay, element.data))
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            });
+            ctx.add_child(GeneratorContext::new(|| 0..16u32, gen_snd));
+            ctx.add_child(stage);
+            ctx.add_child(ConsumerContext::new(out_rcv));
+            (ctx, cut)
+        };
+
+        let (full, cut) = program();
+        let executed = full
+            .initialize(InitializationOptions::default())
+            .unwrap()
+            .run(
+                RunOptionsBuilder::default()
+                    .taps(BoundaryRecording::taps(cut, &path))
+                    .build()
+                    .unwrap(),
+            );
+        assert!(executed.passed());
+        let recording = BoundaryRecording::from_path(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let traffic = &recording.traffic[&cut[1]];
+        let data = |records: &[crate::channel::tap::TapRecord]| -> Vec<_> {
+            records
+                .iter()
+                .map(|record| record.payload.clone())
+                .collect()
+        };
+        assert_ne!(data(&traffic.enqueues), data(&traffic.dequeues));
+
+        let (mut partial, _) = program();
+        partial.replay_subgraph(&recording, "stage").unwrap();
+        let executed = partial
+            .initialize(InitializationOptions::default())
+            .unwrap()
+            .run(Default::default());
+        assert!(executed.passed());
+    }
+}
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{num::NonZeroU64, sync::Arc};
 
 use petgraph::prelude::*;
 use rustc_hash::{FxHashMap, FxHashSet};
@@ -6,14 +6,21 @@ use rustc_hash::{FxHashMap, FxHashSet};
 use crate::{
     channel::{
         channel_spec::ChannelSpec,
+        faults::{FaultPlan, FaultSpec},
         handle::{ChannelData, ChannelHandle},
-        ChannelID, Receiver, Sender,
+        multi::{Arbitration, BroadcastSender, DistributeSender, MergeReceiver, MultiChannelError},
+        probe::LatencyProbe,
+        ChannelElement, ChannelFlavor, ChannelID, ChannelOptions, Receiver, Sender,
     },
     context::Context,
-    datastructures::Identifier,
+    datastructures::{IdAllocator, IdScope, Identifier, Time},
+    types::DAMType,
 };
 
-use super::{programdata::ProgramData, InitializationError, InitializationOptions, Initialized};
+use super::{
+    boundary::Replayer, programdata::ProgramData, ChannelReport, InitializationError,
+    InitializationOptions, InitializationReport, Initialized, SpawnHandle,
+};
 
 #[derive(Copy, Clone, Eq, Debug, PartialEq, Hash)]
 enum ChannelOrContext {
@@ -22,10 +29,20 @@ enum ChannelOrContext {
 }
 
 /// Constructor for a basic Program
+/// Each builder allocates its own IDs, so that building the same program always produces the same IDs.
+/// Channels draw from their builder when they are created, and contexts when they are added with [ProgramBuilder::add_child].
+/// A builder can be moved to another thread, as long as its element types are [Send] and [Sync].
 #[derive(Default)]
 pub struct ProgramBuilder<'a> {
-    data: ProgramData<'a>,
+    pub(super) data: ProgramData<'a>,
+    flavor_overrides: FxHashMap<ChannelID, ChannelFlavor>,
+    faults: FxHashMap<ChannelID, FaultSpec>,
+    invalid_initial: FxHashMap<ChannelID, String>,
+    pub(super) replayers: FxHashMap<ChannelID, Replayer<'a>>,
+    spawner: Option<SpawnHandle>,
+    ids: Arc<IdAllocator>,
 }
+
 impl<'a> ProgramBuilder<'a> {
     fn add_edge(&mut self, edge: Arc<dyn ChannelHandle + 'a>) {
         self.data.edges.push(edge);
@@ -35,21 +52,47 @@ impl<'a> ProgramBuilder<'a> {
         self.data.void_edges.push(edge);
     }
 
-    fn add_node(&mut self, node: Box<dyn Context + 'a>) {
+    pub(super) fn add_node(&mut self, node: Box<dyn Context + 'a>) {
         self.data.nodes.push(node);
     }
 
-    fn make_channel_with_latency<T>(
-        &mut self,
-        capacity: Option<usize>,
-        latency: Option<u64>,
-        resp_latency: Option<u64>,
-    ) -> (Sender<T>, Receiver<T>)
+    /// Starts an empty builder which draws IDs from this one, so that its channels can be moved over later.
+    pub(super) fn detached(&self) -> Self {
+        Self {
+            data: Default::default(),
+            flavor_overrides: Default::default(),
+            faults: Default::default(),
+            invalid_initial: Default::default(),
+            replayers: Default::default(),
+            spawner: None,
+            ids: self.ids.clone(),
+        }
+    }
+
+    /// Moves the channels of a [ProgramBuilder::detached] builder into this one.
+    pub(super) fn absorb_channels(&mut self, other: Self) {
+        self.data.edges.extend(other.data.edges);
+        self.data.void_edges.extend(other.data.void_edges);
+    }
+
+    /// Runs `make` with this builder's IDs in scope, so that contexts constructed inside of it are numbered by this program.
+    /// Contexts constructed elsewhere are only numbered once they are added, so this is needed when their IDs are used before then.
+    pub fn with_ids<R>(&self, make: impl FnOnce() -> R) -> R {
+        let _scope = self.ids.install();
+        make()
+    }
+
+    /// Installs this builder's IDs until the scope is dropped, for callers which need the builder mutably in the meantime.
+    pub(super) fn install_ids(&self) -> IdScope {
+        self.ids.install()
+    }
+
+    /// Registers a channel as part of the program, and creates its endpoints.
+    fn register_channel<T>(&mut self, underlying: ChannelData<T>) -> (Sender<T>, Receiver<T>)
     where
-        T: Clone + 'a,
+        T: Clone + Send + Sync + 'a,
     {
-        let spec = Arc::new(ChannelSpec::new(capacity, latency, resp_latency));
-        let underlying = Arc::new(ChannelData::new(spec));
+        let underlying = Arc::new(underlying);
         self.add_edge(underlying.clone());
 
         (
@@ -60,38 +103,167 @@ impl<'a> ProgramBuilder<'a> {
         )
     }
 
+    /// Constructs a channel with a combination of settings, see [ChannelOptions].
+    pub fn channel<T: Clone + Send + Sync + 'a>(
+        &mut self,
+        options: ChannelOptions<T>,
+    ) -> (Sender<T>, Receiver<T>) {
+        let spec = Arc::new(self.with_ids(|| options.spec()));
+        if let Some(reason) = options.invalid_initial() {
+            self.invalid_initial.insert(spec.id(), reason);
+        }
+        self.register_channel(options.into_data(spec))
+    }
+
     /// Constructs a bounded channel with unit latency
-    pub fn bounded<T: Clone + 'a>(&mut self, capacity: usize) -> (Sender<T>, Receiver<T>) {
-        self.make_channel_with_latency(Some(capacity), None, None)
+    pub fn bounded<T: Clone + Send + Sync + 'a>(
+        &mut self,
+        capacity: usize,
+    ) -> (Sender<T>, Receiver<T>) {
+        self.channel(ChannelOptions::bounded(capacity))
     }
 
     /// Constructs a bounded channel with a given latency
-    pub fn bounded_with_latency<T: Clone + 'a>(
+    pub fn bounded_with_latency<T: Clone + Send + Sync + 'a>(
+        &mut self,
+        capacity: usize,
+        latency: u64,
+        resp_latency: u64,
+    ) -> (Sender<T>, Receiver<T>) {
+        self.channel(ChannelOptions::bounded(capacity).latency(latency, resp_latency))
+    }
+
+    /// Constructs a multi-producer channel out of one bounded lane per producer.
+    /// Each sender should be attached to its own context, and the receiver merges the lanes in timestamp order.
+    /// Fails if there are no producers.
+    pub fn mpsc<T: DAMType + 'a>(
+        &mut self,
+        producers: usize,
+        capacity: usize,
+        arbitration: Arbitration,
+    ) -> Result<(Vec<Sender<T>>, MergeReceiver<T>), MultiChannelError> {
+        if producers == 0 {
+            return Err(MultiChannelError::NoLanes);
+        }
+        let (senders, receivers) = (0..producers).map(|_| self.bounded(capacity)).unzip();
+        Ok((senders, MergeReceiver::new(receivers, arbitration)))
+    }
+
+    /// Constructs a multi-consumer channel out of one bounded lane per consumer.
+    /// Each receiver should be attached to its own context.
+    /// Fails if there are no consumers.
+    pub fn spmc<T: DAMType + 'a>(
+        &mut self,
+        consumers: usize,
+        capacity: usize,
+    ) -> Result<(DistributeSender<T>, Vec<Receiver<T>>), MultiChannelError> {
+        if consumers == 0 {
+            return Err(MultiChannelError::NoLanes);
+        }
+        let (senders, receivers) = (0..consumers).map(|_| self.bounded(capacity)).unzip();
+        Ok((DistributeSender::new(senders), receivers))
+    }
+
+    /// Constructs a broadcast channel with one bounded branch per `(latency, resp_latency)` pair, where every receiver sees every element.
+    /// Fails if there are no branches.
+    pub fn broadcast<T: DAMType + 'a>(
+        &mut self,
+        capacity: usize,
+        branch_latencies: impl IntoIterator<Item = (u64, u64)>,
+    ) -> Result<(BroadcastSender<T>, Vec<Receiver<T>>), MultiChannelError> {
+        let mut branch_latencies = branch_latencies.into_iter().peekable();
+        if branch_latencies.peek().is_none() {
+            return Err(MultiChannelError::NoLanes);
+        }
+        let (senders, receivers) = branch_latencies
+            .map(|(latency, resp_latency)| {
+                self.bounded_with_latency(capacity, latency, resp_latency)
+            })
+            .unzip();
+        Ok((BroadcastSender::new(senders), receivers))
+    }
+
+    /// Constructs a bounded channel which can only transfer a limited number of bits per cycle.
+    /// Each element occupies the link for `ceil(dam_size / bits_per_cycle)` cycles, so back-to-back elements are serialized.
+    pub fn bounded_with_bandwidth<T: DAMType + 'a>(
         &mut self,
         capacity: usize,
         latency: u64,
         resp_latency: u64,
+        bits_per_cycle: NonZeroU64,
     ) -> (Sender<T>, Receiver<T>) {
-        self.make_channel_with_latency(Some(capacity), Some(latency), Some(resp_latency))
+        self.channel(
+            ChannelOptions::bounded(capacity)
+                .latency(latency, resp_latency)
+                .bandwidth(bits_per_cycle),
+        )
+    }
+
+    /// Constructs a bounded channel where each element's latency is computed from its value and send time,
+    /// such as for payload-dependent or randomly jittered interconnects.
+    /// Latencies are clamped to at least `min_latency`, and elements are never delivered before earlier ones.
+    pub fn bounded_with_latency_fn<T, F>(
+        &mut self,
+        capacity: usize,
+        min_latency: u64,
+        resp_latency: u64,
+        latency: F,
+    ) -> (Sender<T>, Receiver<T>)
+    where
+        T: Clone + Send + Sync + 'a,
+        F: Fn(&T, Time) -> u64 + Send + Sync + 'static,
+    {
+        self.channel(
+            ChannelOptions::bounded(capacity)
+                .latency(min_latency, resp_latency)
+                .latency_fn(latency),
+        )
+    }
+
+    /// Constructs a bounded channel which delivers elements in order of their times rather than the order they were sent,
+    /// such as for memory responses which complete out of order.
+    /// Buffered elements still count against the capacity until they are dequeued.
+    pub fn bounded_out_of_order<T: Clone + Send + Sync + 'a>(
+        &mut self,
+        capacity: usize,
+        latency: u64,
+        resp_latency: u64,
+    ) -> (Sender<T>, Receiver<T>) {
+        self.channel(
+            ChannelOptions::bounded(capacity)
+                .latency(latency, resp_latency)
+                .out_of_order(),
+        )
+    }
+
+    /// Constructs a bounded channel which already holds a sequence of timestamped elements when the program starts,
+    /// see [ChannelOptions::initial].
+    pub fn bounded_with_initial<T: Clone + Send + Sync + 'a>(
+        &mut self,
+        capacity: usize,
+        latency: u64,
+        initial: impl IntoIterator<Item = ChannelElement<T>>,
+    ) -> (Sender<T>, Receiver<T>) {
+        self.channel(ChannelOptions::new(Some(capacity), Some(latency), None).initial(initial))
     }
 
     /// Constructs an infinitely deep channel with unit latency
-    pub fn unbounded<T: Clone + 'a>(&mut self) -> (Sender<T>, Receiver<T>) {
-        self.make_channel_with_latency(None, None, None)
+    pub fn unbounded<T: Clone + Send + Sync + 'a>(&mut self) -> (Sender<T>, Receiver<T>) {
+        self.channel(ChannelOptions::unbounded())
     }
 
     /// Constructs an infinitely deep channel with given latency
-    pub fn unbounded_with_latency<T: Clone + 'a>(
+    pub fn unbounded_with_latency<T: Clone + Send + Sync + 'a>(
         &mut self,
         latency: u64,
         resp_latency: u64,
     ) -> (Sender<T>, Receiver<T>) {
-        self.make_channel_with_latency(None, Some(latency), Some(resp_latency))
+        self.channel(ChannelOptions::unbounded().latency(latency, resp_latency))
     }
 
     /// Constructs a channel which writes to nowhere
-    pub fn void<T: Clone + 'a>(&mut self) -> Sender<T> {
-        let spec = Arc::new(ChannelSpec::new(None, None, None));
+    pub fn void<T: Clone + Send + Sync + 'a>(&mut self) -> Sender<T> {
+        let spec = Arc::new(self.with_ids(|| ChannelSpec::new(None, None, None)));
         let underlying = Arc::new(ChannelData::new(spec));
         self.add_void_edge(underlying.clone());
         Sender { underlying }
@@ -99,140 +271,803 @@ impl<'a> ProgramBuilder<'a> {
 
     /// Registers a new context under this program.
     /// The Program now owns the child, and all children must be on board before initialization.
-    pub fn add_child<T>(&mut self, child: T)
+    /// Contexts constructed outside of [ProgramBuilder::with_ids] are numbered by this program as they are added.
+    pub fn add_child<T>(&mut self, mut child: T)
     where
         T: Context + 'a,
     {
+        self.renumber(&mut child);
         self.add_node(Box::new(child));
     }
 
+    /// Replaces the loose IDs of a context with ones from this builder, and updates the channels attached to it.
+    pub(super) fn renumber(&mut self, child: &mut dyn Context) {
+        let mut renumbered = FxHashMap::default();
+        child.renumber(&mut |id| {
+            if !id.is_loose() {
+                return id;
+            }
+            *renumbered
+                .entry(id)
+                .or_insert_with(|| self.ids.next_identifier())
+        });
+        if renumbered.is_empty() {
+            return;
+        }
+        for edge in self.data.edges.iter().chain(&self.data.void_edges) {
+            edge.spec()
+                .renumber_endpoints(|id| renumbered.get(&id).copied().unwrap_or(id));
+        }
+    }
+
+    /// Constructs a context with this builder's IDs in scope and registers it, see [ProgramBuilder::with_ids].
+    pub fn add_child_with<T, F>(&mut self, make: F)
+    where
+        T: Context + 'a,
+        F: FnOnce() -> T,
+    {
+        let child = self.with_ids(make);
+        self.add_child(child);
+    }
+
     /// Returns how many children there are in the constructed graph
     pub fn num_children(&self) -> usize {
         self.data.nodes.len()
     }
 
+    /// Creates a handle which running contexts can use to create channels and spawn new contexts.
+    /// All handles for a program share the same underlying queue, and draw IDs from this builder.
+    /// [Initialized::run] waits until every handle has been dropped, so handles should only be held by contexts in the program.
+    pub fn spawn_handle(&mut self) -> SpawnHandle {
+        self.spawner
+            .get_or_insert_with(|| {
+                let (handle, queue) = SpawnHandle::new(self.ids.clone());
+                self.data.spawn_queue = Some(queue);
+                handle
+            })
+            .clone()
+    }
+
+    /// Forces a channel to use a particular flavor, bypassing flavor inference for it.
+    /// This is useful when a context's [Context::edge_connections] is known to be conservative.
+    /// Forcing [ChannelFlavor::Acyclic] onto a channel that is part of a cycle is rejected during initialization.
+    pub fn force_flavor(&mut self, channel: ChannelID, flavor: ChannelFlavor) {
+        self.flavor_overrides.insert(channel, flavor);
+    }
+
+    /// Injects seeded faults on a channel, replacing any previously configured for it.
+    /// See [crate::channel::faults] for how faults are applied and reported.
+    pub fn inject_faults(&mut self, channel: ChannelID, faults: FaultSpec) {
+        self.faults.insert(channel, faults);
+    }
+
+    /// Injects all of the faults in a plan, as with [ProgramBuilder::inject_faults].
+    pub fn apply_fault_plan(&mut self, plan: &FaultPlan) {
+        for entry in &plan.channels {
+            self.inject_faults(entry.channel, entry.faults.clone());
+        }
+    }
+
+    /// Measures the latency of transactions from when they are enqueued onto `start` until they are first dequeued from `end`.
+    /// The histogram is reported by [super::Executed::latency_histograms]. See [crate::channel::probe] for how transactions are tracked.
+    pub fn probe_latency(&mut self, name: impl Into<String>, start: ChannelID, end: ChannelID) {
+        self.data
+            .probes
+            .push(Arc::new(LatencyProbe::new(name.into(), start, end)));
+    }
+
+    /// Allows taps on a channel to record payloads as JSON via [crate::channel::tap::TapPayload::Serde].
+    pub fn serialize_payloads<T>(&mut self, sender: &Sender<T>)
+    where
+        T: Clone + serde::Serialize + 'a,
+    {
+        sender
+            .underlying
+            .set_serde_formatter(crate::channel::tap::serialize_payload::<T>);
+    }
+
+    /// Registers how elements on a channel are corrupted when a corrupt fault is injected.
+    /// The mutator is given the channel's seeded random number generator, so corruptions are reproducible.
+    pub fn corrupt_with<T, F>(&mut self, sender: &Sender<T>, mutator: F)
+    where
+        T: Clone + Send + Sync + 'a,
+        F: Fn(&mut T, &mut fastrand::Rng) + Send + Sync + 'static,
+    {
+        sender.underlying.set_corruptor(Arc::new(mutator));
+    }
+
+    /// Drops any configuration for a channel which was removed from the program.
+    pub(super) fn forget_channel(&mut self, channel: ChannelID) {
+        self.flavor_overrides.remove(&channel);
+        self.faults.remove(&channel);
+        self.invalid_initial.remove(&channel);
+        self.replayers.remove(&channel);
+        self.data
+            .probes
+            .retain(|probe| probe.start() != channel && probe.end() != channel);
+    }
+
+    /// Maps each channel which is part of a cycle to the index of its strongly connected component.
+    fn cycle_membership(&self) -> FxHashMap<ChannelID, usize> {
+        let all_channel_ids: Vec<_> = self
+            .data
+            .edges
+            .iter()
+            .chain(self.data.void_edges.iter())
+            .map(|handle| handle.id())
+            .collect();
+
+        let mut edge_graph = DiGraph::<ChannelOrContext, ()>::new();
+        // All edges are nodes on the graph
+        // all contexts map to one or more nodes
+        let mut graph_node_map = FxHashMap::default();
+        all_channel_ids.iter().for_each(|chan_id| {
+            let handle = ChannelOrContext::ChannelID(*chan_id);
+            let node = edge_graph.add_node(handle);
+            graph_node_map.insert(handle, node);
+        });
+
+        let mut manually_managed_nodes = FxHashSet::default();
+
+        for explicit_conn in self
+            .data
+            .nodes
+            .iter()
+            .flat_map(|node| node.edge_connections())
+        {
+            for (node, mapping) in explicit_conn {
+                manually_managed_nodes.insert(node);
+                for (srcs, dsts) in mapping {
+                    let temp_node = edge_graph.add_node(ChannelOrContext::Context(node));
+                    for src in srcs {
+                        edge_graph.add_edge(
+                            *graph_node_map
+                                .get(&ChannelOrContext::ChannelID(src))
+                                .unwrap(),
+                            temp_node,
+                            (),
+                        );
+                    }
+
+                    for dst in dsts {
+                        edge_graph.add_edge(
+                            temp_node,
+                            *graph_node_map
+                                .get(&ChannelOrContext::ChannelID(dst))
+                                .unwrap(),
+                            (),
+                        );
+                    }
+                }
+            }
+        }
+
+        for (node, _) in self.data.node_identifiers() {
+            if !manually_managed_nodes.contains(&node) {
+                let handle = ChannelOrContext::Context(node);
+                graph_node_map.insert(handle, edge_graph.add_node(handle));
+            }
+        }
+
+        // Now iterate over all the edges, populating the remaining stuff.
+        for edge in self.data.edges.iter() {
+            let own_node = graph_node_map
+                .get(&ChannelOrContext::ChannelID(edge.id()))
+                .unwrap();
+            let src = edge.sender().unwrap();
+            if !manually_managed_nodes.contains(&src) {
+                // connect the source onto ourselves
+                edge_graph.add_edge(
+                    *graph_node_map.get(&ChannelOrContext::Context(src)).unwrap(),
+                    *own_node,
+                    (),
+                );
+            }
+
+            let dst = edge.receiver().unwrap();
+            if !manually_managed_nodes.contains(&dst) {
+                edge_graph.add_edge(
+                    *own_node,
+                    *graph_node_map.get(&ChannelOrContext::Context(dst)).unwrap(),
+                    (),
+                );
+            }
+        }
+
+        let sccs = petgraph::algo::tarjan_scc(&edge_graph);
+        sccs.into_iter()
+            .filter(|x| x.len() > 1)
+            .enumerate()
+            .flat_map(|(index, scc)| {
+                let graph = &edge_graph;
+                scc.into_iter().filter_map(move |node| match graph[node] {
+                    ChannelOrContext::ChannelID(id) => Some((id, index)),
+                    ChannelOrContext::Context(_) => None,
+                })
+            })
+            .collect()
+    }
+
     /// Initializes the program, and returns an [Initialized] program if successful.
     /// On error, returns a [InitializationError], which encodes the first error that occurred.
     pub fn initialize(
         mut self,
         options: InitializationOptions,
     ) -> Result<Initialized<'a>, InitializationError> {
+        self.elaborate()?;
+        if let Some((id, reason)) = self.invalid_initial.iter().next() {
+            return Err(InitializationError::InvalidInitial(*id, reason.clone()));
+        }
+
         self.data.check()?;
-        self.data
-            .void_edges
-            .iter()
-            .for_each(|edge| edge.set_flavor(crate::channel::ChannelFlavor::Void));
 
-        if options.run_flavor_inference {
-            let all_channel_ids: Vec<_> = self
+        for (id, flavor) in &self.flavor_overrides {
+            if !self.data.edges.iter().any(|edge| edge.id() == *id) {
+                return Err(InitializationError::UnknownChannel(*id));
+            }
+            if *flavor == ChannelFlavor::Void {
+                return Err(InitializationError::InvalidForcedFlavor(*id, *flavor));
+            }
+        }
+
+        for (id, faults) in &self.faults {
+            let edge = self
                 .data
                 .edges
                 .iter()
-                .chain(self.data.void_edges.iter())
-                .map(|handle| handle.id())
-                .collect();
+                .find(|edge| edge.id() == *id)
+                .ok_or(InitializationError::UnknownFaultChannel(*id))?;
+            faults
+                .validate()
+                .map_err(|reason| InitializationError::InvalidFaults(*id, reason))?;
+            if faults.corrupts() && !edge.has_corruptor() {
+                return Err(InitializationError::InvalidFaults(
+                    *id,
+                    "corrupting elements requires a corruptor".to_string(),
+                ));
+            }
+            edge.set_faults(faults.clone());
+        }
 
-            let mut edge_graph = DiGraph::<ChannelOrContext, ()>::new();
-            // All edges are nodes on the graph
-            // all contexts map to one or more nodes
-            let mut graph_node_map = FxHashMap::default();
-            all_channel_ids.iter().for_each(|chan_id| {
-                let handle = ChannelOrContext::ChannelID(*chan_id);
-                let node = edge_graph.add_node(handle);
-                graph_node_map.insert(handle, node);
-            });
+        for probe in &self.data.probes {
+            for id in [probe.start(), probe.end()] {
+                self.data
+                    .edges
+                    .iter()
+                    .find(|edge| edge.id() == id)
+                    .ok_or(InitializationError::UnknownProbeChannel(id))?
+                    .add_probe(probe.clone());
+            }
+        }
 
-            let mut manually_managed_nodes = FxHashSet::default();
+        // Cycle analysis is always run, so that the report shows every channel's SCC even without flavor inference.
+        let sccs = self.cycle_membership();
 
-            for explicit_conn in self
-                .data
-                .nodes
-                .iter()
-                .flat_map(|node| node.edge_connections())
-            {
-                for (node, mapping) in explicit_conn {
-                    manually_managed_nodes.insert(node);
-                    for (srcs, dsts) in mapping {
-                        let temp_node = edge_graph.add_node(ChannelOrContext::Context(node));
-                        for src in srcs {
-                            edge_graph.add_edge(
-                                *graph_node_map
-                                    .get(&ChannelOrContext::ChannelID(src))
-                                    .unwrap(),
-                                temp_node,
-                                (),
-                            );
-                        }
-
-                        for dst in dsts {
-                            edge_graph.add_edge(
-                                temp_node,
-                                *graph_node_map
-                                    .get(&ChannelOrContext::ChannelID(dst))
-                                    .unwrap(),
-                                (),
-                            );
-                        }
-                    }
+        let mut report = InitializationReport::default();
+        for edge in &self.data.edges {
+            let scc = sccs.get(&edge.id()).copied();
+            let (flavor, forced) = match self.flavor_overrides.get(&edge.id()) {
+                Some(ChannelFlavor::Acyclic) if scc.is_some() => {
+                    return Err(InitializationError::AcyclicInCycle(edge.id()));
                 }
-            }
-
-            for (node, _) in self.data.node_identifiers() {
-                if !manually_managed_nodes.contains(&node) {
-                    let handle = ChannelOrContext::Context(node);
-                    graph_node_map.insert(handle, edge_graph.add_node(handle));
+                Some(flavor) => (*flavor, true),
+                None if scc.is_none() && options.run_flavor_inference => {
+                    (ChannelFlavor::Acyclic, false)
                 }
+                None => (ChannelFlavor::Cyclic, false),
+            };
+            report.channels.push(ChannelReport {
+                id: edge.id(),
+                flavor,
+                scc,
+                forced,
+            });
+        }
+
+        self.data
+            .void_edges
+            .iter()
+            .for_each(|edge| edge.set_flavor(ChannelFlavor::Void));
+        report
+            .channels
+            .extend(self.data.void_edges.iter().map(|edge| ChannelReport {
+                id: edge.id(),
+                flavor: ChannelFlavor::Void,
+                scc: None,
+                forced: false,
+            }));
+
+        // The report is in the same order as the edges.
+        self.data
+            .edges
+            .iter()
+            .zip(report.channels.iter())
+            .for_each(|(edge, chan)| edge.set_flavor(chan.flavor));
+
+        self.data.nodes.iter_mut().for_each(|child| child.init());
+
+        Ok(Initialized {
+            data: self.data,
+            report,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        channel::{ChannelElement, ChannelFlavor, ChannelID, ChannelOptions},
+        datastructures::Time,
+        simulation::{InitializationError, InitializationOptionsBuilder, RunOptions},
+        utility_contexts::{CheckerContext, ConsumerContext, FunctionContext, GeneratorContext},
+    };
+
+    use super::{Identifier, NonZeroU64, ProgramBuilder};
+
+    // Generator -> Ping <-> Pong, where Ping and Pong form a cycle.
+    fn ping_pong(
+        inference: bool,
+        force: impl FnOnce(&mut ProgramBuilder, [ChannelID; 3]),
+    ) -> Result<super::Initialized<'static>, InitializationError> {
+        let mut parent = ProgramBuilder::default();
+        let (in_snd, in_rcv) = parent.bounded::<u32>(4);
+        let (ping_snd, ping_rcv) = parent.bounded::<u32>(4);
+        let (pong_snd, pong_rcv) = parent.bounded::<u32>(4);
+        let ids = [in_snd.id(), ping_snd.id(), pong_snd.id()];
+        force(&mut parent, ids);
+
+        parent.add_child(GeneratorContext::new(|| 0..8u32, in_snd));
+
+        let mut ping = FunctionContext::new();
+        in_rcv.attach_receiver(&ping);
+        ping_snd.attach_sender(&ping);
+        pong_rcv.attach_receiver(&ping);
+        ping.set_run(move |time| {
+            while let Ok(value) = in_rcv.dequeue(time) {
+                ping_snd
+                    .enqueue(time, ChannelElement::new(time.tick() + 1, value.data))
+                    .unwrap();
+                pong_rcv.dequeue(time).unwrap();
             }
+        });
+        parent.add_child(ping);
 
-            // Now iterate over all the edges, populating the remaining stuff.
-            for edge in self.data.edges.iter() {
-                let own_node = graph_node_map
-                    .get(&ChannelOrContext::ChannelID(edge.id()))
+        let mut pong = FunctionContext::new();
+        pong_snd.attach_sender(&pong);
+        let pong_out = ping_rcv;
+        pong_out.attach_receiver(&pong);
+        pong.set_run(move |time| {
+            while let Ok(value) = pong_out.dequeue(time) {
+                pong_snd
+                    .enqueue(time, ChannelElement::new(time.tick() + 1, value.data))
                     .unwrap();
-                let src = edge.sender().unwrap();
-                if !manually_managed_nodes.contains(&src) {
-                    // connect the source onto ourselves
-                    edge_graph.add_edge(
-                        *graph_node_map.get(&ChannelOrContext::Context(src)).unwrap(),
-                        *own_node,
-                        (),
-                    );
-                }
+            }
+        });
+        parent.add_child(pong);
 
-                let dst = edge.receiver().unwrap();
-                if !manually_managed_nodes.contains(&dst) {
-                    edge_graph.add_edge(
-                        *own_node,
-                        *graph_node_map.get(&ChannelOrContext::Context(dst)).unwrap(),
-                        (),
-                    );
-                }
+        parent.initialize(
+            InitializationOptionsBuilder::default()
+                .run_flavor_inference(inference)
+                .build()
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_flavor_report() {
+        let mut ids = None;
+        let initialized = ping_pong(true, |_, chans| ids = Some(chans)).unwrap();
+        let [input, ping, pong] = ids.unwrap();
+
+        let report = initialized.report();
+        assert_eq!(
+            report.channel(input).unwrap().flavor,
+            ChannelFlavor::Acyclic
+        );
+        assert_eq!(report.channel(input).unwrap().scc, None);
+        assert_eq!(report.channel(ping).unwrap().flavor, ChannelFlavor::Cyclic);
+        assert_eq!(
+            report.channel(ping).unwrap().scc,
+            report.channel(pong).unwrap().scc
+        );
+        assert!(report.channel(pong).unwrap().scc.is_some());
+
+        assert!(initialized.run(RunOptions::default()).passed());
+    }
+
+    #[test]
+    fn test_flavor_report_without_inference() {
+        let mut ids = None;
+        let initialized = ping_pong(false, |_, chans| ids = Some(chans)).unwrap();
+        let [input, ping, pong] = ids.unwrap();
+
+        // Every channel is cyclic, but the cycle is still reported.
+        let report = initialized.report();
+        assert_eq!(report.channel(input).unwrap().flavor, ChannelFlavor::Cyclic);
+        assert_eq!(report.channel(input).unwrap().scc, None);
+        assert!(report.channel(ping).unwrap().scc.is_some());
+        assert_eq!(
+            report.channel(ping).unwrap().scc,
+            report.channel(pong).unwrap().scc
+        );
+        assert!(report.to_string().contains("(SCC"));
+
+        assert!(initialized.run(RunOptions::default()).passed());
+    }
+
+    #[test]
+    fn test_forced_flavors() {
+        let mut ids = None;
+        let initialized = ping_pong(true, |parent, chans| {
+            parent.force_flavor(chans[0], ChannelFlavor::Cyclic);
+            ids = Some(chans);
+        })
+        .unwrap();
+        let input = initialized.report().channel(ids.unwrap()[0]).unwrap();
+        assert_eq!(input.flavor, ChannelFlavor::Cyclic);
+        assert!(input.forced);
+
+        let result = ping_pong(true, |parent, chans| {
+            parent.force_flavor(chans[1], ChannelFlavor::Acyclic)
+        });
+        assert!(matches!(
+            result,
+            Err(InitializationError::AcyclicInCycle(_))
+        ));
+    }
+
+    #[test]
+    fn test_forced_void_rejected() {
+        let mut parent = ProgramBuilder::default();
+        let (snd, rcv) = parent.bounded::<u32>(1);
+        parent.force_flavor(snd.id(), ChannelFlavor::Void);
+        parent.add_child(GeneratorContext::new(|| 0..1u32, snd));
+        parent.add_child(ConsumerContext::new(rcv));
+        assert!(matches!(
+            parent.initialize(Default::default()),
+            Err(InitializationError::InvalidForcedFlavor(
+                _,
+                ChannelFlavor::Void
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_initial_tokens() {
+        // A running sum, where the partial sum is carried around a back edge seeded with 0.
+        let mut parent = ProgramBuilder::default();
+        let (in_snd, in_rcv) = parent.bounded::<u32>(2);
+        let (back_snd, back_rcv) =
+            parent.bounded_with_initial(1, 1, [ChannelElement::new(Time::new(0), 0u32)]);
+        let (out_snd, out_rcv) = parent.bounded::<u32>(2);
+
+        parent.add_child(GeneratorContext::new(|| 0..8u32, in_snd));
+
+        let mut acc = FunctionContext::new();
+        in_rcv.attach_receiver(&acc);
+        back_snd.attach_sender(&acc);
+        back_rcv.attach_receiver(&acc);
+        out_snd.attach_sender(&acc);
+        acc.set_run(move |time| {
+            while let Ok(value) = in_rcv.dequeue(time) {
+                let sum = back_rcv.dequeue(time).unwrap().data + value.data;
+                let element = ChannelElement::new(time.tick() + 1, sum);
+                back_snd.enqueue(time, element.clone()).unwrap();
+                out_snd.enqueue(time, element).unwrap();
+                time.incr_cycles(1);
+            }
+        });
+        parent.add_child(acc);
+
+        parent.add_child(CheckerContext::new(
+            || {
+                (0..8u32).scan(0, |sum, x| {
+                    *sum += x;
+                    Some(*sum)
+                })
+            },
+            out_rcv,
+        ));
+
+        let executed = parent
+            .initialize(
+                InitializationOptionsBuilder::default()
+                    .run_flavor_inference(true)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap()
+            .run(RunOptions::default());
+        assert!(executed.passed());
+    }
+
+    #[test]
+    fn test_bandwidth_serializes_transfers() {
+        let mut parent = ProgramBuilder::default();
+        // Each u64 occupies a 16 bit/cycle link for 4 cycles.
+        let (snd, rcv) =
+            parent.bounded_with_bandwidth::<u64>(8, 1, 1, NonZeroU64::new(16).unwrap());
+        parent.add_child(GeneratorContext::new(|| 0..8u64, snd));
+
+        let mut consumer = FunctionContext::new();
+        rcv.attach_receiver(&consumer);
+        consumer.set_run(move |time| {
+            for i in 0..8u64 {
+                let element = rcv.dequeue(time).unwrap();
+                assert_eq!(element.time.time(), 4 * (i + 1));
+            }
+        });
+        parent.add_child(consumer);
+
+        let executed = parent
+            .initialize(Default::default())
+            .unwrap()
+            .run(RunOptions::default());
+        assert!(executed.passed());
+    }
+
+    #[test]
+    fn test_latency_fn_preserves_order() {
+        let mut parent = ProgramBuilder::default();
+        let rng = std::sync::Mutex::new(fastrand::Rng::with_seed(0x5eed));
+        // Jitter between 0 and 20 cycles, which is clamped up to the declared minimum of 2.
+        let (snd, rcv) = parent
+            .bounded_with_latency_fn::<u32, _>(4, 2, 1, move |_, _| rng.lock().unwrap().u64(0..20));
+        parent.add_child(GeneratorContext::new(|| 0..32u32, snd));
+
+        let mut consumer = FunctionContext::new();
+        rcv.attach_receiver(&consumer);
+        consumer.set_run(move |time| {
+            let mut last = 0;
+            for i in 0..32u32 {
+                let element = rcv.dequeue(time).unwrap();
+                assert_eq!(element.data, i);
+                assert!(element.time.time() >= last);
+                last = element.time.time();
             }
+        });
+        parent.add_child(consumer);
+
+        let executed = parent
+            .initialize(Default::default())
+            .unwrap()
+            .run(RunOptions::default());
+        assert!(executed.passed());
+    }
+
+    #[test]
+    fn test_combined_channel_options() {
+        let mut parent = ProgramBuilder::default();
+        // Later elements take less time to arrive, so they overtake earlier ones.
+        let (snd, rcv) = parent.channel(
+            ChannelOptions::bounded(8)
+                .latency(1, 1)
+                .latency_fn(|x: &u32, _| 4 * (8 - *x as u64))
+                .out_of_order(),
+        );
+        parent.add_child(GeneratorContext::new(|| 0..8u32, snd));
 
-            let sccs = petgraph::algo::tarjan_scc(&edge_graph);
-            let actual_sccs: FxHashSet<_> =
-                sccs.into_iter().filter(|x| x.len() > 1).flatten().collect();
+        let mut consumer = FunctionContext::new();
+        rcv.attach_receiver(&consumer);
+        consumer.set_run(move |time| {
+            let seen: Vec<_> = std::iter::from_fn(|| rcv.dequeue(time).ok())
+                .map(|element| element.data)
+                .collect();
+            assert_eq!(seen, (0..8u32).rev().collect::<Vec<_>>());
+        });
+        parent.add_child(consumer);
 
-            // One of the major things to do here is to optimize all of the edges.
-            self.data.edges.iter().for_each(|edge| {
-                let handle = graph_node_map
-                    .get(&ChannelOrContext::ChannelID(edge.id()))
+        let executed = parent
+            .initialize(Default::default())
+            .unwrap()
+            .run(RunOptions::default());
+        assert!(executed.passed());
+    }
+
+    fn out_of_order_pipeline(flavor_inference: bool) {
+        let mut parent = ProgramBuilder::default();
+        let (snd, rcv) = parent.bounded_out_of_order::<u32>(4, 1, 1);
+
+        let mut producer = FunctionContext::new();
+        snd.attach_sender(&producer);
+        producer.set_run(move |time| {
+            let mut rng = fastrand::Rng::with_seed(0x5eed);
+            for i in 0..32u32 {
+                // Each request completes some time after it was issued.
+                let completion = time.tick() + rng.u64(1..40);
+                snd.enqueue(time, ChannelElement::new(completion, i))
                     .unwrap();
-                if actual_sccs.contains(handle) {
-                    edge.set_flavor(crate::channel::ChannelFlavor::Cyclic);
-                } else {
-                    edge.set_flavor(crate::channel::ChannelFlavor::Acyclic);
-                }
-            });
-        } else {
-            self.data
-                .edges
-                .iter()
-                .for_each(|edge| edge.set_flavor(crate::channel::ChannelFlavor::Cyclic));
+                time.incr_cycles(1);
+            }
+        });
+        parent.add_child(producer);
+
+        let mut consumer = FunctionContext::new();
+        rcv.attach_receiver(&consumer);
+        consumer.set_run(move |time| {
+            let mut last = 0;
+            let mut seen = vec![];
+            while let Ok(element) = rcv.dequeue(time) {
+                assert!(element.time.time() >= last);
+                last = element.time.time();
+                seen.push(element.data);
+            }
+            seen.sort();
+            assert_eq!(seen, (0..32u32).collect::<Vec<_>>());
+        });
+        parent.add_child(consumer);
+
+        let executed = parent
+            .initialize(
+                InitializationOptionsBuilder::default()
+                    .run_flavor_inference(flavor_inference)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap()
+            .run(RunOptions::default());
+        assert!(executed.passed());
+    }
+
+    #[test]
+    fn test_out_of_order_delivery() {
+        out_of_order_pipeline(false);
+        out_of_order_pipeline(true);
+    }
+
+    #[test]
+    fn test_invalid_initial_tokens() {
+        let mut parent = ProgramBuilder::default();
+        let (_, rcv) = parent.bounded_with_initial(
+            1,
+            1,
+            (0..2u32).map(|x| ChannelElement::new(Time::new(0), x)),
+        );
+        let too_many = rcv.id();
+        assert!(matches!(
+            parent.initialize(Default::default()),
+            Err(InitializationError::InvalidInitial(id, _)) if id == too_many
+        ));
+
+        let mut parent = ProgramBuilder::default();
+        let (_, rcv) =
+            parent.bounded_with_initial(4, 1, [2, 1].map(|t| ChannelElement::new(Time::new(t), t)));
+        let decreasing = rcv.id();
+        assert!(matches!(
+            parent.initialize(Default::default()),
+            Err(InitializationError::InvalidInitial(id, _)) if id == decreasing
+        ));
+
+        // Unbounded channels can hold any number of initial elements, but they still need to be in order.
+        let mut parent = ProgramBuilder::default();
+        let (_, rcv) = parent.channel(
+            ChannelOptions::unbounded()
+                .latency(2, 1)
+                .initial([2, 1].map(|t| ChannelElement::new(Time::new(t), t))),
+        );
+        let unordered = rcv.id();
+        assert!(matches!(
+            parent.initialize(Default::default()),
+            Err(InitializationError::InvalidInitial(id, _)) if id == unordered
+        ));
+    }
+
+    /// The IDs of the most recently added contexts.
+    fn last_ids(builder: &ProgramBuilder, count: usize) -> Vec<usize> {
+        let nodes = &builder.data.nodes;
+        nodes[nodes.len() - count..]
+            .iter()
+            .map(|node| node.id().id)
+            .collect()
+    }
+
+    fn generator_consumer_ids() -> (Vec<usize>, Vec<ChannelID>) {
+        let mut parent = ProgramBuilder::default();
+        let (snd, rcv) = parent.bounded::<u32>(2);
+        let channels = vec![snd.id()];
+        parent.add_child(GeneratorContext::new(|| 0..4u32, snd));
+        parent.add_child(ConsumerContext::new(rcv));
+        let ids = last_ids(&parent, 2);
+        // The channel follows its endpoints to their new IDs.
+        let edge = &parent.data.edges[0];
+        assert_eq!(
+            (edge.sender(), edge.receiver()),
+            (
+                Some(Identifier { id: ids[0] }),
+                Some(Identifier { id: ids[1] })
+            )
+        );
+        assert!(parent
+            .initialize(Default::default())
+            .unwrap()
+            .run(RunOptions::default())
+            .passed());
+        (ids, channels)
+    }
+
+    #[test]
+    fn test_deterministic_ids() {
+        let first = generator_consumer_ids();
+        assert_eq!(first, generator_consumer_ids());
+
+        let concurrent: Vec<_> = (0..4)
+            .map(|_| std::thread::spawn(generator_consumer_ids))
+            .collect();
+        for handle in concurrent {
+            assert_eq!(first, handle.join().unwrap());
         }
+    }
 
-        self.data.nodes.iter_mut().for_each(|child| child.init());
+    /// Reports another context as one of its children.
+    #[dam_macros::context_internal]
+    struct Composite {
+        child: crate::datastructures::VerboseIdentifier,
+    }
+
+    impl crate::context::Context for Composite {
+        fn run_falliable(&mut self) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        fn ids(
+            &self,
+        ) -> std::collections::HashMap<
+            crate::datastructures::VerboseIdentifier,
+            std::collections::HashSet<crate::datastructures::VerboseIdentifier>,
+        > {
+            use crate::datastructures::Identifiable;
+            [(self.verbose(), [self.child.clone()].into())].into()
+        }
+    }
+
+    #[test]
+    fn test_duplicate_nested_id() {
+        use crate::datastructures::Identifiable;
+
+        let mut parent = ProgramBuilder::default();
+        let (snd, rcv) = parent.bounded::<u32>(1);
+        parent.add_child(GeneratorContext::new(|| 0..1u32, snd));
+        let consumer = parent.with_ids(|| ConsumerContext::new(rcv));
+        parent.add_child(Composite {
+            child: consumer.verbose(),
+            context_info: Default::default(),
+        });
+        parent.add_child(consumer);
+        assert!(matches!(
+            parent.initialize(Default::default()),
+            Err(InitializationError::DuplicateIdentifier(_))
+        ));
+    }
+
+    #[test]
+    fn test_builders_own_their_ids() {
+        let build = |ctx: &mut ProgramBuilder<'static>| {
+            let (snd, rcv) = ctx.bounded::<u32>(2);
+            ctx.add_child(GeneratorContext::new(|| 0..4u32, snd));
+            ctx.add_child(CheckerContext::new(|| 0..4u32, rcv));
+            last_ids(ctx, 2)
+        };
+        // Two builders alive on one thread each number their contexts from 0, regardless of interleaving.
+        let mut first = ProgramBuilder::default();
+        let mut second = ProgramBuilder::default();
+        assert_eq!(build(&mut first), vec![0, 1]);
+        assert_eq!(build(&mut second), vec![0, 1]);
+        assert_eq!(build(&mut first), vec![2, 3]);
 
-        Ok(Initialized { data: self.data })
+        // Builders keep their own numbering when moved to another thread.
+        let second = std::thread::spawn(move || {
+            assert_eq!(build(&mut second), vec![2, 3]);
+            second
+                .initialize(Default::default())
+                .unwrap()
+                .run(RunOptions::default())
+                .passed()
+        });
+        assert!(first
+            .initialize(Default::default())
+            .unwrap()
+            .run(RunOptions::default())
+            .passed());
+        assert!(second.join().unwrap());
     }
 }
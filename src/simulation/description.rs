@@ -0,0 +1,820 @@
+use std::any::Any;
+
+use linkme::distributed_slice;
+use rustc_hash::FxHashMap;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    channel::{ChannelID, ChannelOptions, Receiver, Sender},
+    context::Context,
+    datastructures::Identifier,
+    types::DAMType,
+    utility_contexts::{CheckerContext, ConsumerContext, GeneratorContext, PrinterContext},
+};
+
+use super::ProgramBuilder;
+
+/// A program topology which can be loaded from JSON or TOML, and instantiated via [GraphDescription::build_into].
+/// Contexts are constructed through [CONTEXT_FACTORIES], and channels through [CHANNEL_TYPES].
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct GraphDescription {
+    /// The contexts in the program
+    #[serde(default)]
+    pub contexts: Vec<ContextDescription>,
+
+    /// The channels between contexts
+    #[serde(default)]
+    pub channels: Vec<ChannelDescription>,
+}
+
+/// A single context within a [GraphDescription].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ContextDescription {
+    /// The instance name, which is also used to refer to the context's ports
+    pub name: String,
+
+    /// The [ContextFactory::kind] used to construct the context
+    pub kind: String,
+
+    /// Factory-specific parameters
+    #[serde(default)]
+    pub params: serde_json::Value,
+}
+
+/// A single channel within a [GraphDescription].
+/// Endpoints are written as `context.port`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChannelDescription {
+    /// The [ChannelType::name] of the elements carried by the channel
+    #[serde(rename = "type")]
+    pub ty: String,
+
+    /// The sending endpoint
+    pub from: String,
+
+    /// The receiving endpoint. Channels without one are void channels.
+    #[serde(default)]
+    pub to: Option<String>,
+
+    /// The channel depth, or unbounded if not specified
+    #[serde(default)]
+    pub capacity: Option<usize>,
+
+    /// The send latency, defaulting to 1
+    #[serde(default)]
+    pub latency: Option<u64>,
+
+    /// The response latency, defaulting to 1
+    #[serde(default)]
+    pub resp_latency: Option<u64>,
+}
+
+/// Errors from loading or instantiating a [GraphDescription]
+#[derive(Error, Debug)]
+pub enum DescriptionError {
+    /// The description could not be read
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    /// The description could not be parsed
+    #[error("Could not parse description: {0}")]
+    Parse(String),
+
+    /// No factory was registered for a context kind
+    #[error("Unknown context kind: {0}")]
+    UnknownKind(String),
+
+    /// No channel type was registered under a name
+    #[error("Unknown channel type: {0}")]
+    UnknownChannelType(String),
+
+    /// A channel endpoint referred to a context which does not exist
+    #[error("Unknown context: {0}")]
+    UnknownContext(String),
+
+    /// Two contexts were given the same name
+    #[error("Duplicate context: {0}")]
+    DuplicateContext(String),
+
+    /// Endpoints must be of the form `context.port`
+    #[error("Malformed endpoint: {0:?}")]
+    MalformedEndpoint(String),
+
+    /// Each port may only be connected to a single channel
+    #[error("Port {context}.{port} is connected more than once")]
+    DuplicatePort {
+        /// The context name
+        context: String,
+        /// The port name
+        port: String,
+    },
+
+    /// A factory requested a port which was not connected
+    #[error("Port {context}.{port} is not connected")]
+    MissingPort {
+        /// The context name
+        context: String,
+        /// The port name
+        port: String,
+    },
+
+    /// A connected port was not used by its context's factory
+    #[error("Port {context}.{port} is not used by its context")]
+    UnusedPort {
+        /// The context name
+        context: String,
+        /// The port name
+        port: String,
+    },
+
+    /// A port was requested with the wrong direction or element type
+    #[error("Port {context}.{port} is not {expected}")]
+    PortMismatch {
+        /// The context name
+        context: String,
+        /// The port name
+        port: String,
+        /// What the factory asked for
+        expected: String,
+    },
+
+    /// A channel had an invalid specification
+    #[error("Invalid channel {0}: latencies must be positive")]
+    InvalidChannel(String),
+
+    /// A context's parameters were rejected by its factory
+    #[error("Invalid parameters for {context}: {message}")]
+    InvalidParams {
+        /// The context name
+        context: String,
+        /// Why the parameters were rejected
+        message: String,
+    },
+
+    /// A built-in factory does not support the element type of one of its ports
+    #[error("Context {context} does not support channels of type {ty}")]
+    UnsupportedType {
+        /// The context name
+        context: String,
+        /// The channel type name
+        ty: String,
+    },
+}
+
+/// A registry of all context factories usable from a [GraphDescription]
+#[distributed_slice]
+pub static CONTEXT_FACTORIES: [ContextFactory] = [..];
+
+/// A registry of all element types usable for channels in a [GraphDescription]
+#[distributed_slice]
+pub static CHANNEL_TYPES: [ChannelType] = [..];
+
+/// Constructs a context of a particular kind from its description.
+/// Factories are registered by adding them to [CONTEXT_FACTORIES].
+pub struct ContextFactory {
+    /// The name used by [ContextDescription::kind]
+    pub kind: &'static str,
+
+    /// Constructs the context, taking its channel endpoints out of the arguments
+    pub build: BuildFn,
+}
+
+type BuildFn = fn(&mut FactoryArgs) -> Result<Box<dyn Context>, DescriptionError>;
+
+type ChannelMaker = for<'a> fn(
+    &mut ProgramBuilder<'a>,
+    &ChannelDescription,
+) -> (ChannelID, Box<dyn Any>, Option<Box<dyn Any>>);
+
+/// An element type which channels in a [GraphDescription] can carry.
+/// Types are registered by adding them to [CHANNEL_TYPES].
+/// Each entry also supplies the built-in contexts which can be attached to its channels.
+pub struct ChannelType {
+    /// The name used by [ChannelDescription::ty]
+    pub name: &'static str,
+    make: ChannelMaker,
+    generator: Option<BuildFn>,
+    checker: Option<BuildFn>,
+    consumer: Option<BuildFn>,
+    printer: Option<BuildFn>,
+}
+
+impl ChannelType {
+    /// Creates a registry entry for channels of T, which can be drained by the built-in Consumer and Printer.
+    pub const fn new<T: DAMType + 'static>(name: &'static str) -> Self {
+        Self {
+            name,
+            make: make_channel::<T>,
+            generator: None,
+            checker: None,
+            consumer: Some(consumer::<T>),
+            printer: Some(printer::<T>),
+        }
+    }
+
+    /// Creates a registry entry for channels of T, whose values the built-in Generator and Checker can also read from their parameters.
+    pub const fn with_sequences<T: DAMType + DeserializeOwned + PartialEq + 'static>(
+        name: &'static str,
+    ) -> Self {
+        Self {
+            generator: Some(generator::<T>),
+            checker: Some(checker::<T>),
+            ..Self::new::<T>(name)
+        }
+    }
+}
+
+fn make_channel<T: DAMType + 'static>(
+    builder: &mut ProgramBuilder<'_>,
+    description: &ChannelDescription,
+) -> (ChannelID, Box<dyn Any>, Option<Box<dyn Any>>) {
+    if description.to.is_none() {
+        let snd = builder.void::<T>();
+        return (snd.id(), Box::new(snd), None);
+    }
+    let (snd, rcv) = builder.channel::<T>(ChannelOptions::new(
+        description.capacity,
+        description.latency,
+        description.resp_latency,
+    ));
+    (snd.id(), Box::new(snd), Some(Box::new(rcv)))
+}
+
+macro_rules! builtin_channel {
+    ($static_name: ident, $tp: ty, $name: literal) => {
+        #[distributed_slice(CHANNEL_TYPES)]
+        static $static_name: ChannelType = ChannelType::with_sequences::<$tp>($name);
+    };
+}
+
+builtin_channel!(BOOL_CHANNEL, bool, "bool");
+builtin_channel!(U8_CHANNEL, u8, "u8");
+builtin_channel!(I8_CHANNEL, i8, "i8");
+builtin_channel!(U16_CHANNEL, u16, "u16");
+builtin_channel!(I16_CHANNEL, i16, "i16");
+builtin_channel!(U32_CHANNEL, u32, "u32");
+builtin_channel!(I32_CHANNEL, i32, "i32");
+builtin_channel!(U64_CHANNEL, u64, "u64");
+builtin_channel!(I64_CHANNEL, i64, "i64");
+builtin_channel!(USIZE_CHANNEL, usize, "usize");
+builtin_channel!(F32_CHANNEL, f32, "f32");
+builtin_channel!(F64_CHANNEL, f64, "f64");
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SequenceParams<T> {
+    values: Option<Vec<T>>,
+    range: Option<[i64; 2]>,
+}
+
+/// Reads the `values` list or `[start, end)` `range` parameter shared by the built-in generator and checker.
+fn sequence<T: DeserializeOwned>(args: &FactoryArgs) -> Result<Vec<T>, DescriptionError> {
+    let invalid = |message: String| DescriptionError::InvalidParams {
+        context: args.name().to_string(),
+        message,
+    };
+    match args.params::<SequenceParams<T>>()? {
+        SequenceParams {
+            values: Some(values),
+            range: None,
+        } => Ok(values),
+        SequenceParams {
+            values: None,
+            range: Some([start, end]),
+        } => (start..end)
+            .map(|i| serde_json::from_value(i.into()).map_err(|err| invalid(err.to_string())))
+            .collect(),
+        _ => Err(invalid(
+            "expected exactly one of `values` or `range`".to_string(),
+        )),
+    }
+}
+
+fn generator<T: DAMType + DeserializeOwned + 'static>(
+    args: &mut FactoryArgs,
+) -> Result<Box<dyn Context>, DescriptionError> {
+    let values = sequence::<T>(args)?;
+    let mut ctx = GeneratorContext::new(move || values.into_iter(), args.sender("output")?);
+    ctx.set_name(args.name());
+    Ok(Box::new(ctx))
+}
+
+fn checker<T: DAMType + DeserializeOwned + PartialEq + 'static>(
+    args: &mut FactoryArgs,
+) -> Result<Box<dyn Context>, DescriptionError> {
+    let values = sequence::<T>(args)?;
+    let mut ctx = CheckerContext::new(move || values.into_iter(), args.receiver("input")?);
+    ctx.set_name(args.name());
+    Ok(Box::new(ctx))
+}
+
+fn consumer<T: DAMType + 'static>(
+    args: &mut FactoryArgs,
+) -> Result<Box<dyn Context>, DescriptionError> {
+    let mut ctx = ConsumerContext::new(args.receiver::<T>("input")?);
+    ctx.set_name(args.name());
+    Ok(Box::new(ctx))
+}
+
+fn printer<T: DAMType + 'static>(
+    args: &mut FactoryArgs,
+) -> Result<Box<dyn Context>, DescriptionError> {
+    let mut ctx = PrinterContext::new(args.receiver::<T>("input")?);
+    ctx.set_name(args.name());
+    Ok(Box::new(ctx))
+}
+
+/// Writes `params.values` or `params.range` to the `output` port.
+#[distributed_slice(CONTEXT_FACTORIES)]
+static GENERATOR_FACTORY: ContextFactory = ContextFactory {
+    kind: "Generator",
+    build: |args| args.builtin("output", |tp| tp.generator),
+};
+
+/// Checks the `input` port against `params.values` or `params.range`.
+#[distributed_slice(CONTEXT_FACTORIES)]
+static CHECKER_FACTORY: ContextFactory = ContextFactory {
+    kind: "Checker",
+    build: |args| args.builtin("input", |tp| tp.checker),
+};
+
+/// Drains the `input` port.
+#[distributed_slice(CONTEXT_FACTORIES)]
+static CONSUMER_FACTORY: ContextFactory = ContextFactory {
+    kind: "Consumer",
+    build: |args| args.builtin("input", |tp| tp.consumer),
+};
+
+/// Prints the `input` port to STDOUT.
+#[distributed_slice(CONTEXT_FACTORIES)]
+static PRINTER_FACTORY: ContextFactory = ContextFactory {
+    kind: "Printer",
+    build: |args| args.builtin("input", |tp| tp.printer),
+};
+
+enum Endpoint {
+    Sender(Box<dyn Any>),
+    Receiver(Box<dyn Any>),
+}
+
+struct Port {
+    ty: &'static ChannelType,
+    endpoint: Endpoint,
+}
+
+/// The information available to a [ContextFactory] while constructing a context.
+pub struct FactoryArgs<'d> {
+    name: &'d str,
+    params: &'d serde_json::Value,
+    ports: FxHashMap<String, Port>,
+}
+
+impl<'d> FactoryArgs<'d> {
+    /// The instance name of the context, which factories should pass along to [crate::datastructures::ContextInfo::set_name].
+    pub fn name(&self) -> &'d str {
+        self.name
+    }
+
+    /// Deserializes the context's parameters. Missing parameters are treated as an empty table.
+    pub fn params<P: DeserializeOwned>(&self) -> Result<P, DescriptionError> {
+        let params = if self.params.is_null() {
+            serde_json::Value::Object(Default::default())
+        } else {
+            self.params.clone()
+        };
+        serde_json::from_value(params).map_err(|err| DescriptionError::InvalidParams {
+            context: self.name.to_string(),
+            message: err.to_string(),
+        })
+    }
+
+    /// The channel type name connected to a port, for factories which are generic over their element type.
+    pub fn port_type(&self, port: &str) -> Result<&'static str, DescriptionError> {
+        self.ports
+            .get(port)
+            .map(|port| port.ty.name)
+            .ok_or_else(|| self.missing(port))
+    }
+
+    /// Constructs a built-in context with the factory which the channel type of a port supplies.
+    fn builtin(
+        &mut self,
+        port: &str,
+        select: fn(&ChannelType) -> Option<BuildFn>,
+    ) -> Result<Box<dyn Context>, DescriptionError> {
+        let channel_type = self
+            .ports
+            .get(port)
+            .map(|port| port.ty)
+            .ok_or_else(|| self.missing(port))?;
+        let build = select(channel_type).ok_or_else(|| DescriptionError::UnsupportedType {
+            context: self.name.to_string(),
+            ty: channel_type.name.to_string(),
+        })?;
+        build(self)
+    }
+
+    /// Takes the receiving endpoint connected to a port.
+    pub fn receiver<T: Clone + 'static>(
+        &mut self,
+        port: &str,
+    ) -> Result<Receiver<T>, DescriptionError> {
+        match self.ports.remove(port).map(|port| port.endpoint) {
+            Some(Endpoint::Receiver(rcv)) => rcv
+                .downcast()
+                .map(|rcv| *rcv)
+                .map_err(|_| self.mismatch(port, "a receiver of", std::any::type_name::<T>())),
+            Some(Endpoint::Sender(_)) => {
+                Err(self.mismatch(port, "a receiver of", std::any::type_name::<T>()))
+            }
+            None => Err(self.missing(port)),
+        }
+    }
+
+    /// Takes the sending endpoint connected to a port.
+    pub fn sender<T: Clone + 'static>(
+        &mut self,
+        port: &str,
+    ) -> Result<Sender<T>, DescriptionError> {
+        match self.ports.remove(port).map(|port| port.endpoint) {
+            Some(Endpoint::Sender(snd)) => snd
+                .downcast()
+                .map(|snd| *snd)
+                .map_err(|_| self.mismatch(port, "a sender of", std::any::type_name::<T>())),
+            Some(Endpoint::Receiver(_)) => {
+                Err(self.mismatch(port, "a sender of", std::any::type_name::<T>()))
+            }
+            None => Err(self.missing(port)),
+        }
+    }
+
+    fn missing(&self, port: &str) -> DescriptionError {
+        DescriptionError::MissingPort {
+            context: self.name.to_string(),
+            port: port.to_string(),
+        }
+    }
+
+    fn mismatch(&self, port: &str, direction: &str, ty: &str) -> DescriptionError {
+        DescriptionError::PortMismatch {
+            context: self.name.to_string(),
+            port: port.to_string(),
+            expected: format!("{direction} {ty}"),
+        }
+    }
+}
+
+/// The IDs assigned to the contexts and channels of an instantiated [GraphDescription].
+#[derive(Debug, Clone, Default)]
+pub struct BuiltGraph {
+    contexts: FxHashMap<String, Identifier>,
+    channels: FxHashMap<String, ChannelID>,
+}
+
+impl BuiltGraph {
+    /// Looks up a context by its instance name.
+    pub fn context(&self, name: &str) -> Option<Identifier> {
+        self.contexts.get(name).copied()
+    }
+
+    /// Looks up a channel by its sending endpoint, such as `gen.output`.
+    pub fn channel(&self, from: &str) -> Option<ChannelID> {
+        self.channels.get(from).copied()
+    }
+}
+
+fn split_endpoint(endpoint: &str) -> Result<(&str, &str), DescriptionError> {
+    match endpoint.rsplit_once('.') {
+        Some((context, port)) if !context.is_empty() && !port.is_empty() => Ok((context, port)),
+        _ => Err(DescriptionError::MalformedEndpoint(endpoint.to_string())),
+    }
+}
+
+impl GraphDescription {
+    /// Parses a description from JSON.
+    pub fn from_json(text: &str) -> Result<Self, DescriptionError> {
+        serde_json::from_str(text).map_err(|err| DescriptionError::Parse(err.to_string()))
+    }
+
+    /// Parses a description from TOML.
+    #[cfg(feature = "toml")]
+    pub fn from_toml(text: &str) -> Result<Self, DescriptionError> {
+        toml::from_str(text).map_err(|err| DescriptionError::Parse(err.to_string()))
+    }
+
+    /// Reads a description from a file, choosing the format by extension.
+    /// Files ending in `.toml` require the `toml` feature, and everything else is treated as JSON.
+    pub fn from_path(path: impl AsRef<std::path::Path>) -> Result<Self, DescriptionError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            #[cfg(feature = "toml")]
+            Some("toml") => Self::from_toml(&text),
+            #[cfg(not(feature = "toml"))]
+            Some("toml") => Err(DescriptionError::Parse(
+                "TOML descriptions require the `toml` feature".to_string(),
+            )),
+            _ => Self::from_json(&text),
+        }
+    }
+
+    /// Constructs all channels and contexts of the description under a builder.
+    /// Nothing is added to the builder unless the whole description could be constructed.
+    /// The builder can still be extended afterwards, for example to attach additional checkers.
+    pub fn build_into(
+        &self,
+        builder: &mut ProgramBuilder<'_>,
+    ) -> Result<BuiltGraph, DescriptionError> {
+        let mut ports: FxHashMap<&str, FxHashMap<String, Port>> = FxHashMap::default();
+        for context in &self.contexts {
+            if ports.insert(&context.name, Default::default()).is_some() {
+                return Err(DescriptionError::DuplicateContext(context.name.clone()));
+            }
+        }
+
+        // Channels are made on a detached builder, and only moved over once every context has been constructed.
+        let mut channels = builder.detached();
+        let mut built = BuiltGraph::default();
+        for channel in &self.channels {
+            let channel_type = CHANNEL_TYPES
+                .iter()
+                .find(|tp| tp.name == channel.ty)
+                .ok_or_else(|| DescriptionError::UnknownChannelType(channel.ty.clone()))?;
+            if channel.latency == Some(0) || channel.resp_latency == Some(0) {
+                return Err(DescriptionError::InvalidChannel(channel.from.clone()));
+            }
+
+            let mut endpoints = vec![split_endpoint(&channel.from)?];
+            if let Some(to) = &channel.to {
+                endpoints.push(split_endpoint(to)?);
+            }
+            for (index, (context, port)) in endpoints.iter().enumerate() {
+                let context_ports = ports
+                    .get(context)
+                    .ok_or_else(|| DescriptionError::UnknownContext(context.to_string()))?;
+                // A channel from a port to itself would otherwise overwrite its own sender.
+                if context_ports.contains_key(*port)
+                    || endpoints[..index].contains(&(context, port))
+                {
+                    return Err(DescriptionError::DuplicatePort {
+                        context: context.to_string(),
+                        port: port.to_string(),
+                    });
+                }
+            }
+
+            let (id, snd, rcv) = (channel_type.make)(&mut channels, channel);
+            built.channels.insert(channel.from.clone(), id);
+
+            let ty = channel_type;
+            let (src_context, src_port) = endpoints[0];
+            ports.get_mut(src_context).unwrap().insert(
+                src_port.to_string(),
+                Port {
+                    ty,
+                    endpoint: Endpoint::Sender(snd),
+                },
+            );
+            if let (Some((dst_context, dst_port)), Some(rcv)) = (endpoints.get(1), rcv) {
+                ports.get_mut(dst_context).unwrap().insert(
+                    dst_port.to_string(),
+                    Port {
+                        ty,
+                        endpoint: Endpoint::Receiver(rcv),
+                    },
+                );
+            }
+        }
+
+        let mut children = Vec::with_capacity(self.contexts.len());
+        for context in &self.contexts {
+            let factory = CONTEXT_FACTORIES
+                .iter()
+                .find(|factory| factory.kind == context.kind)
+                .ok_or_else(|| DescriptionError::UnknownKind(context.kind.clone()))?;
+            let mut args = FactoryArgs {
+                name: &context.name,
+                params: &context.params,
+                ports: ports.remove(context.name.as_str()).unwrap_or_default(),
+            };
+            let child = builder.with_ids(|| (factory.build)(&mut args))?;
+            if let Some(port) = args.ports.into_keys().next() {
+                return Err(DescriptionError::UnusedPort {
+                    context: context.name.clone(),
+                    port,
+                });
+            }
+            built.contexts.insert(context.name.clone(), child.id());
+            children.push(child);
+        }
+
+        builder.absorb_channels(channels);
+        for child in children {
+            builder.add_node(child);
+        }
+        Ok(built)
+    }
+}
+
+#[cfg(feature = "dot")]
+mod inner {
+    use graphviz_rust::dot_generator::*;
+    use graphviz_rust::dot_structures::*;
+
+    use super::{split_endpoint, GraphDescription};
+    use crate::simulation::dot::DotConvertibleHelper;
+
+    impl GraphDescription {
+        fn node_name(&self, context: &str) -> String {
+            let index = self
+                .contexts
+                .iter()
+                .position(|ctx| ctx.name == context)
+                .unwrap_or(usize::MAX);
+            format!("Node_{index}")
+        }
+    }
+
+    /// Descriptions can be rendered directly, without instantiating them.
+    impl DotConvertibleHelper for GraphDescription {
+        fn add_nodes(&self) -> Vec<Stmt> {
+            self.contexts
+                .iter()
+                .map(|context| {
+                    let label_string = format!("{}: {}", context.name, context.kind);
+                    Node::new(
+                        node_id!(self.node_name(&context.name)),
+                        vec![
+                            attr!("shape", esc "rectangle"),
+                            attr!("label", esc label_string),
+                        ],
+                    )
+                    .into()
+                })
+                .collect()
+        }
+
+        fn generate_edges(&self) -> Vec<Stmt> {
+            let mut stmts = vec![];
+            for (index, channel) in self.channels.iter().enumerate() {
+                let Ok((src, _)) = split_endpoint(&channel.from) else {
+                    continue;
+                };
+                let tooltip = format!(
+                    "Type: {}\\nCapacity: {:?}\\nLatency: {}\\nRespLatency: {}",
+                    channel.ty,
+                    channel.capacity,
+                    channel.latency.unwrap_or(1),
+                    channel.resp_latency.unwrap_or(1)
+                );
+                let (dst_id, mut attributes) = match channel.to.as_deref().map(split_endpoint) {
+                    Some(Ok((dst, _))) => (node_id!(self.node_name(dst)), vec![]),
+                    _ => {
+                        let void_id = node_id!(format!("Void_{index}"));
+                        stmts.push(
+                            Node::new(void_id.clone(), vec![attr!("label", esc "void")]).into(),
+                        );
+                        (void_id, vec![attr!("style", esc "dotted")])
+                    }
+                };
+                let label_string = channel.from.clone();
+                attributes.push(attr!("label", esc label_string));
+                attributes.push(attr!("tooltip", esc tooltip));
+                stmts.push(
+                    Edge {
+                        ty: EdgeTy::Pair(node_id!(self.node_name(src)).into(), dst_id.into()),
+                        attributes,
+                    }
+                    .into(),
+                );
+            }
+            stmts
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::simulation::{InitializationOptions, ProgramBuilder, RunOptions};
+
+    use super::{DescriptionError, GraphDescription};
+
+    const PIPELINE: &str = r#"{
+        "contexts": [
+            {"name": "gen", "kind": "Generator", "params": {"range": [0, 32]}},
+            {"name": "check", "kind": "Checker", "params": {"range": [0, 32]}},
+            {"name": "side", "kind": "Generator", "params": {"values": [1.5, 2.5]}}
+        ],
+        "channels": [
+            {"type": "u32", "from": "gen.output", "to": "check.input", "capacity": 4, "latency": 3},
+            {"type": "f64", "from": "side.output"}
+        ]
+    }"#;
+
+    #[test]
+    fn test_description_pipeline() {
+        let description = GraphDescription::from_json(PIPELINE).unwrap();
+        let mut parent = ProgramBuilder::default();
+        let built = description.build_into(&mut parent).unwrap();
+        assert_eq!(parent.num_children(), 3);
+        assert!(built.channel("gen.output").is_some());
+
+        let executed = parent
+            .initialize(InitializationOptions::default())
+            .unwrap()
+            .run(RunOptions::default());
+        assert!(executed.passed());
+        let names: Vec<_> = executed
+            .summaries()
+            .iter()
+            .map(|summary| summary.id.name.clone())
+            .collect();
+        assert!(names.contains(&"check".to_string()));
+    }
+
+    #[test]
+    fn test_description_errors() {
+        let build = |text: &str| {
+            GraphDescription::from_json(text)
+                .and_then(|description| description.build_into(&mut ProgramBuilder::default()))
+        };
+
+        let unknown_kind = r#"{"contexts": [{"name": "a", "kind": "Missing"}]}"#;
+        assert!(matches!(
+            build(unknown_kind),
+            Err(DescriptionError::UnknownKind(_))
+        ));
+
+        let wrong_direction = r#"{
+            "contexts": [{"name": "a", "kind": "Generator", "params": {"values": [1]}}, {"name": "b", "kind": "Consumer"}],
+            "channels": [{"type": "u8", "from": "b.input", "to": "a.output"}]
+        }"#;
+        assert!(matches!(
+            build(wrong_direction),
+            Err(DescriptionError::PortMismatch { .. })
+        ));
+
+        let unused = r#"{
+            "contexts": [{"name": "a", "kind": "Generator", "params": {"values": [1]}}, {"name": "b", "kind": "Consumer"}],
+            "channels": [{"type": "u8", "from": "a.output", "to": "b.input"}, {"type": "u8", "from": "a.extra"}]
+        }"#;
+        assert!(matches!(
+            build(unused),
+            Err(DescriptionError::UnusedPort { .. })
+        ));
+
+        let self_loop = r#"{
+            "contexts": [{"name": "a", "kind": "Consumer"}],
+            "channels": [{"type": "u8", "from": "a.input", "to": "a.input"}]
+        }"#;
+        assert!(matches!(
+            build(self_loop),
+            Err(DescriptionError::DuplicatePort { .. })
+        ));
+
+        let mut parent = ProgramBuilder::default();
+        let description = GraphDescription::from_json(unused).unwrap();
+        assert!(description.build_into(&mut parent).is_err());
+        assert_eq!(parent.num_children(), 0);
+        assert!(parent.data.edges.is_empty() && parent.data.void_edges.is_empty());
+
+        let bad_params = r#"{
+            "contexts": [{"name": "a", "kind": "Generator", "params": {"values": [true]}}],
+            "channels": [{"type": "u8", "from": "a.output"}]
+        }"#;
+        assert!(matches!(
+            build(bad_params),
+            Err(DescriptionError::InvalidParams { .. })
+        ));
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_description_toml() {
+        let description = GraphDescription::from_toml(
+            r#"
+            [[contexts]]
+            name = "gen"
+            kind = "Generator"
+            params = { values = [1, 2, 3] }
+
+            [[contexts]]
+            name = "sink"
+            kind = "Consumer"
+
+            [[channels]]
+            type = "i32"
+            from = "gen.output"
+            to = "sink.input"
+            capacity = 2
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            description,
+            GraphDescription::from_json(&serde_json::to_string(&description).unwrap()).unwrap()
+        );
+        assert_eq!(description.channels[0].capacity, Some(2));
+    }
+}
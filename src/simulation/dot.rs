@@ -56,7 +56,7 @@ pub(super) trait DotConvertibleHelper {
                         ),
                         attributes: vec![
                             attr!("label", esc edge.id()),
-                            attr!("tooltip", esc format!("Capacity: {:?}\\nLatency: {}\\nRespLatency: {}", edge.spec().capacity(), edge.spec().latency(), edge.spec().resp_latency())),
+                            attr!("tooltip", esc format!("Capacity: {:?}\\nLatency: {}\\nRespLatency: {}\\nBandwidth: {:?}", edge.spec().capacity(), edge.spec().latency(), edge.spec().resp_latency(), edge.spec().bandwidth())),
                         ],
                     }
                     .into(),
@@ -1,6 +1,14 @@
 use std::sync::Arc;
 
-use crate::{channel::handle::ChannelHandle, context::ContextSummary};
+use crate::{
+    channel::{
+        faults::FaultSummary,
+        handle::ChannelHandle,
+        probe::{LatencyHistogram, LatencyProbe},
+    },
+    context::ContextSummary,
+    utility_contexts::{CheckReport, CheckerError},
+};
 
 use super::SimulationError;
 
@@ -9,10 +17,8 @@ use super::SimulationError;
 pub struct Executed<'a> {
     pub(super) nodes: Vec<ContextSummary>,
     pub(super) failures: Vec<SimulationError>,
-
-    // Edges might not be used if the dot cfg isn't enabled.
-    #[allow(unused)]
     pub(super) edges: Vec<Arc<dyn ChannelHandle + 'a>>,
+    pub(super) probes: Vec<Arc<LatencyProbe>>,
 }
 
 impl Executed<'_> {
@@ -22,6 +28,11 @@ impl Executed<'_> {
         self.nodes.iter().map(|node| node.max_time()).max()
     }
 
+    /// Summaries of all top-level contexts and subgraph instances.
+    pub fn summaries(&self) -> &[ContextSummary] {
+        &self.nodes
+    }
+
     /// Returns if simulation was successful with no errors.
     pub fn passed(&self) -> bool {
         self.failures.is_empty()
@@ -32,6 +43,31 @@ impl Executed<'_> {
         f(&self.failures)
     }
 
+    /// The faults injected on each channel configured with [super::ProgramBuilder::inject_faults].
+    pub fn fault_summary(&self) -> Vec<FaultSummary> {
+        self.edges
+            .iter()
+            .filter_map(|edge| edge.fault_summary())
+            .collect()
+    }
+
+    /// The latencies measured by each probe configured with [super::ProgramBuilder::probe_latency], in the order they were added.
+    pub fn latency_histograms(&self) -> Vec<LatencyHistogram> {
+        self.probes.iter().map(|probe| probe.histogram()).collect()
+    }
+
+    /// The reports of every [crate::utility_contexts::CollectingCheckerContext] which found mismatches, in no particular order.
+    /// Checkers which pass don't fail the program, so their reports are only available from their [crate::utility_contexts::CheckReportHandle].
+    pub fn check_reports(&self) -> Vec<&CheckReport> {
+        self.failures
+            .iter()
+            .filter_map(|failure| match failure.error().downcast_ref() {
+                Some(CheckerError::Report(report)) => Some(report),
+                _ => None,
+            })
+            .collect()
+    }
+
     /// Prints all of the failures in the program
     pub fn dump_failures(&self) {
         println!("{:?}", self.failures);
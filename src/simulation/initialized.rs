@@ -1,23 +1,98 @@
+use std::{collections::HashMap, io::Write, sync::Arc};
+
 use crate::{
-    datastructures::Time,
+    channel::{
+        handle::ChannelHandle,
+        tap::{ChannelTap, TapError, TapFile},
+    },
+    context::Context,
+    datastructures::{Time, VerboseIdentifier},
     logging::{initialize_log, LogEntry, LogInterface, LogProcessor},
-    shim::spawn,
+    shim::{spawn, Mutex},
 };
 
 #[cfg(feature = "log-mongo")]
 use crate::logging::mongo_logger::{mongodb, MongoLogger};
 
-use super::{executed::Executed, programdata::ProgramData, LoggingOptions, RunOptions};
+use super::{
+    executed::Executed, programdata::ProgramData, InitializationReport, LoggingOptions, RunOptions,
+    SpawnError,
+};
 
 /// An initialized program, which has passed checking after the [super::ProgramBuilder]
 pub struct Initialized<'a> {
     pub(super) data: ProgramData<'a>,
+    pub(super) report: InitializationReport,
 }
 
 impl<'a> Initialized<'a> {
+    /// The flavor chosen for each channel during initialization.
+    pub fn report(&self) -> &InitializationReport {
+        &self.report
+    }
+
     /// Executes the program with specified options.
     /// Currently will deadlock frequently if there is an error at runtime, due to blocking dequeues.
-    pub fn run(mut self, options: RunOptions) -> Executed<'a> {
+    /// If a tap in the options is invalid, the program does not run, and the [TapError] is reported as its only failure.
+    /// See [Initialized::try_run] to handle invalid taps separately.
+    pub fn run(self, options: RunOptions) -> Executed<'a> {
+        match self.install_taps(&options.taps) {
+            Ok(tap_files) => self.execute(options, tap_files),
+            Err(error) => self.abandon(error.into()),
+        }
+    }
+
+    /// Executes the program like [Initialized::run], but returns an error instead of starting if a tap is invalid.
+    pub fn try_run(self, options: RunOptions) -> Result<Executed<'a>, TapError> {
+        let tap_files = self.install_taps(&options.taps)?;
+        Ok(self.execute(options, tap_files))
+    }
+
+    /// Opens each distinct trace file once, and installs the taps onto their channels.
+    fn install_taps(&self, taps: &[ChannelTap]) -> Result<Vec<TapFile>, TapError> {
+        let mut files: HashMap<_, TapFile> = HashMap::new();
+        for tap in taps {
+            let edge = match self.data.edges.iter().find(|edge| edge.id() == tap.channel) {
+                Some(edge) => edge,
+                None if self
+                    .data
+                    .void_edges
+                    .iter()
+                    .any(|edge| edge.id() == tap.channel) =>
+                {
+                    return Err(TapError::VoidChannel(tap.channel));
+                }
+                None => return Err(TapError::UnknownChannel(tap.channel)),
+            };
+            let file = match files.get(&tap.path) {
+                Some(file) => file.clone(),
+                None => {
+                    let file = Arc::new(Mutex::new(std::io::BufWriter::new(
+                        std::fs::File::create(&tap.path)?,
+                    )));
+                    files.insert(tap.path.clone(), file.clone());
+                    file
+                }
+            };
+            edge.set_tap(file, tap.payload)?;
+        }
+        Ok(files.into_values().collect())
+    }
+
+    /// Skips running the program, and reports an error which is not specific to any context.
+    fn abandon(mut self, error: anyhow::Error) -> Executed<'a> {
+        Executed {
+            nodes: vec![],
+            failures: vec![super::SimulationError {
+                id: None,
+                underlying: error,
+            }],
+            probes: std::mem::take(&mut self.data.probes),
+            edges: self.data.edges,
+        }
+    }
+
+    fn execute(mut self, options: RunOptions, tap_files: Vec<TapFile>) -> Executed<'a> {
         // If we should make a log, then we populate this stuff
 
         // This guard is necessary because when logging is off, then the LoggingOptions enum is always None.
@@ -41,17 +116,24 @@ impl<'a> Initialized<'a> {
         let summaries = std::sync::Arc::new(crossbeam::queue::SegQueue::new());
         let failures = std::sync::Arc::new(crossbeam::queue::SegQueue::new());
 
+        let context_instances = self.data.context_instances();
+        let qualified_ids: Vec<_> = self
+            .data
+            .nodes
+            .iter()
+            .map(|child| self.data.qualify(&context_instances, child.verbose()))
+            .collect();
+
+        let spawn_queue = self.data.spawn_queue.take();
+
         crate::shim::scope(|s| {
             let base_time = std::time::Instant::now();
 
-            self.data.nodes.drain(..).for_each(|mut child| {
-                let id = child.id();
-                let name = child.name();
-                let builder = crate::shim::make_builder(options.mode).name(format!(
-                    "{}({})",
-                    child.id(),
-                    child.name()
-                ));
+            let launch = |mut child: Box<dyn Context + 'a>, verbose: VerboseIdentifier| {
+                let id = verbose.id;
+                let name = verbose.name.clone();
+                let builder = crate::shim::make_builder(options.mode)
+                    .name(format!("{}({})", verbose.id, verbose.name));
                 let filter_copy = options.log_filter.clone();
 
                 let sender = log_sender.clone();
@@ -80,30 +162,69 @@ impl<'a> Initialized<'a> {
                         }
                         Err(error) => {
                             failure_handle.push(super::SimulationError {
-                                id: child.id().id,
+                                id: Some(verbose),
                                 underlying: error,
                             });
                         }
                     }
                 })
                 .unwrap_or_else(|_| panic!("Failed to spawn child {name:?} {id:?}"));
-            });
+            };
+
+            self.data
+                .nodes
+                .drain(..)
+                .zip(qualified_ids)
+                .for_each(|(child, verbose)| launch(child, verbose));
+
+            // Contexts spawned at runtime keep arriving until every SpawnHandle has been dropped.
+            if let Some(queue) = &spawn_queue {
+                for child in queue.requests.iter() {
+                    let verbose = child.verbose();
+                    launch(child, verbose);
+                }
+                for (child, channel) in queue.channels.lock().unwrap().take_stranded() {
+                    failures.push(super::SimulationError {
+                        id: Some(child.verbose()),
+                        underlying: SpawnError::Unattached(channel).into(),
+                    });
+                }
+            }
 
             drop(log_sender);
         });
 
         handle.map(|jh| jh.join());
 
+        for file in &tap_files {
+            file.lock()
+                .unwrap()
+                .flush()
+                .expect("Could not flush a tap file");
+        }
+
         Executed {
-            nodes: std::sync::Arc::into_inner(summaries)
-                .expect("Could not obtain unique access to summaries")
-                .into_iter()
-                .collect(),
+            nodes: self.data.group_summaries(
+                std::sync::Arc::into_inner(summaries)
+                    .expect("Could not obtain unique access to summaries")
+                    .into_iter()
+                    .collect(),
+            ),
             failures: std::sync::Arc::into_inner(failures)
                 .expect("Could not obtain unique access to failures")
                 .into_iter()
                 .collect(),
-            edges: self.data.edges,
+            probes: std::mem::take(&mut self.data.probes),
+            edges: self
+                .data
+                .edges
+                .into_iter()
+                .chain(spawn_queue.into_iter().flat_map(|queue| {
+                    std::mem::take(&mut queue.channels.lock().unwrap().finalized)
+                        .into_iter()
+                        .map(|chan| -> Arc<dyn ChannelHandle + 'a> { chan })
+                }))
+                .collect(),
         }
     }
 
@@ -191,10 +312,43 @@ mod inner {
             let node_graph: HashMap<_, _> =
                 self.data.nodes.iter().flat_map(|node| node.ids()).collect();
 
+            let context_instances = self.data.context_instances();
+            let mut clusters: Vec<Vec<Stmt>> = self
+                .data
+                .instances
+                .iter()
+                .map(|instance| {
+                    let label_string = instance.path.clone();
+                    vec![stmt!(attr!("label", esc label_string))]
+                })
+                .collect();
+
             let mut stmts = vec![];
             let mut visited = FxHashSet::default();
             for node in &self.data.nodes {
-                stmts.extend(Self::emit_node(&node.verbose(), &mut visited, &node_graph))
+                let emitted = Self::emit_node(&node.verbose(), &mut visited, &node_graph);
+                match context_instances.get(&node.id()) {
+                    Some(index) => clusters[*index].extend(emitted),
+                    None => stmts.extend(emitted),
+                }
+            }
+
+            // Nested instances are always created after their parents, so handling them in reverse finishes children first.
+            for (index, instance) in self.data.instances.iter().enumerate().rev() {
+                let inner_stmts = std::mem::take(&mut clusters[index]);
+                // Only the label, so there's nothing to draw.
+                if inner_stmts.len() <= 1 {
+                    continue;
+                }
+                let cluster: Stmt = Subgraph {
+                    id: Id::Plain(format!("cluster_{}", Self::context_id_to_name(instance.id))),
+                    stmts: inner_stmts,
+                }
+                .into();
+                match instance.parent {
+                    Some(parent) => clusters[parent].push(cluster),
+                    None => stmts.push(cluster),
+                }
             }
             stmts
         }
@@ -2,11 +2,20 @@
 //! First, construct and populate a [ProgramBuilder], which can then be validated and initialized via [ProgramBuilder::initialize]
 //! The initialized graph can then be executed, returning a [Executed] object, which is a summary of the execution.
 //! Programs are run-once, so re-running a program requires starting from scratch.
+//! Many variations of a program can be run concurrently via [sweep].
+//! Programs can also be loaded from JSON or TOML files via [GraphDescription].
 
+mod boundary;
 mod building;
+mod description;
 mod executed;
 mod initialized;
 mod programdata;
+mod replace;
+mod report;
+mod spawning;
+mod subgraph;
+mod sweep;
 
 mod logging_options;
 pub use logging_options::*;
@@ -19,19 +28,29 @@ use derive_builder::Builder;
 pub use dot::DotConvertible;
 
 // Export all of the program states
+pub use boundary::{BoundaryRecording, ReplayError};
 pub use building::ProgramBuilder;
+pub use description::{
+    BuiltGraph, ChannelDescription, ChannelType, ContextDescription, ContextFactory,
+    DescriptionError, FactoryArgs, GraphDescription, CHANNEL_TYPES, CONTEXT_FACTORIES,
+};
 pub use executed::Executed;
 pub use initialized::Initialized;
-
-use crate::channel::ChannelID;
-use crate::datastructures::Identifier;
+pub use replace::{ContextSelector, ReplaceError, Rewire};
+pub use report::{ChannelReport, InitializationReport};
+pub use spawning::{SpawnError, SpawnHandle};
+pub use subgraph::{Instance, PortDirection, PortError, Subgraph, SubgraphBuilder};
+pub use sweep::{sweep, PointResult, PointStatus, SweepOptions, SweepOptionsBuilder, SweepResults};
+
+use crate::channel::{tap::ChannelTap, ChannelFlavor, ChannelID};
+use crate::datastructures::{Identifier, VerboseIdentifier};
 use crate::logging::LogFilter;
 use thiserror::Error;
 
 pub use crate::shim::RunMode;
 
 /// Options for executing an [Initialized] program.
-#[derive(Builder, Default)]
+#[derive(Builder, Default, Clone)]
 #[builder(pattern = "owned")]
 pub struct RunOptions {
     /// Options for how to schedule the child threads
@@ -45,6 +64,10 @@ pub struct RunOptions {
     /// Filters for which types of events to log
     #[builder(setter(into), default)]
     log_filter: LogFilterKind,
+
+    /// Channels whose traffic is recorded to trace files, which are checked when the program starts running
+    #[builder(setter(into), default)]
+    taps: Vec<ChannelTap>,
 }
 
 /// Defines what events should be logged
@@ -85,21 +108,77 @@ pub enum InitializationError {
     /// All contexts must be registered
     #[error("Unregistered Node: {0}")]
     UnregisteredNode(Identifier),
+
+    /// Context IDs must be unique, which can fail if contexts were constructed while a different builder was active
+    #[error("Duplicate Identifier: {0}")]
+    DuplicateIdentifier(Identifier),
+
+    /// Channel IDs must be unique, which can fail if channels were constructed while a different builder was active
+    #[error("Duplicate Channel: {0:?}")]
+    DuplicateChannel(ChannelID),
+
+    /// Subgraph ports must be bound correctly
+    #[error(transparent)]
+    Port(#[from] subgraph::PortError),
+
+    /// Forced flavors must refer to a channel in the program
+    #[error("Forced flavor on unknown channel: {0:?}")]
+    UnknownChannel(ChannelID),
+
+    /// Only Acyclic and Cyclic flavors may be forced
+    #[error("Cannot force channel {0:?} to be {1:?}")]
+    InvalidForcedFlavor(ChannelID, ChannelFlavor),
+
+    /// Channels within a cycle cannot be forced to be Acyclic
+    #[error("Channel {0:?} is part of a cycle, and cannot be forced to be Acyclic")]
+    AcyclicInCycle(ChannelID),
+
+    /// Faults must refer to a channel in the program
+    #[error("Faults injected on unknown channel: {0:?}")]
+    UnknownFaultChannel(ChannelID),
+
+    /// Faults must be well-formed
+    #[error("Invalid faults on channel {0:?}: {1}")]
+    InvalidFaults(ChannelID, String),
+
+    /// Initial elements must fit within their channel, and have non-decreasing times
+    #[error("Invalid initial elements on channel {0:?}: {1}")]
+    InvalidInitial(ChannelID, String),
+
+    /// Latency probes must start and end on channels in the program
+    #[error("Latency probe on unknown channel: {0:?}")]
+    UnknownProbeChannel(ChannelID),
 }
 
 /// Various ways a program can fail
 #[derive(Error, Debug)]
 pub struct SimulationError {
-    id: usize,
+    // The failed context, or None if the program could not be run at all.
+    id: Option<VerboseIdentifier>,
     underlying: anyhow::Error,
 }
 
+impl SimulationError {
+    /// The context which failed, or None if the program could not be run at all.
+    pub fn context(&self) -> Option<&VerboseIdentifier> {
+        self.id.as_ref()
+    }
+
+    /// What the context failed with, which can be downcast to its own error type, such as [crate::utility_contexts::CheckerError].
+    pub fn error(&self) -> &anyhow::Error {
+        &self.underlying
+    }
+}
+
 impl std::fmt::Display for SimulationError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "Simulation of {} failed with message {}",
-            self.id, self.underlying
-        )
+        match &self.id {
+            Some(id) => write!(
+                f,
+                "Simulation of {}({}) failed with message {}",
+                id.name, id.id, self.underlying
+            ),
+            None => write!(f, "Simulation failed with message {}", self.underlying),
+        }
     }
 }
@@ -1,19 +1,99 @@
 use std::sync::Arc;
 
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
 
-use crate::{channel::handle::ChannelHandle, context::Context, datastructures::Identifier};
+use crate::{
+    channel::{handle::ChannelHandle, probe::LatencyProbe},
+    context::{Context, ContextSummary},
+    datastructures::{Identifier, VerboseIdentifier},
+    view::ParentView,
+};
 
-use super::InitializationError;
+use super::{
+    spawning::SpawnQueue,
+    subgraph::{InstanceData, PortError},
+    InitializationError,
+};
 
 #[derive(Default)]
 pub(super) struct ProgramData<'a> {
     pub(super) nodes: Vec<Box<dyn Context + 'a>>,
     pub(super) edges: Vec<Arc<dyn ChannelHandle + 'a>>,
     pub(super) void_edges: Vec<Arc<dyn ChannelHandle + 'a>>,
+    pub(super) instances: Vec<InstanceData<'a>>,
+    // The number of instances whose bodies have already run.
+    pub(super) elaborated: usize,
+    // The error of a body which failed part of the way through, which leaves the program unusable.
+    pub(super) failed_body: Option<PortError>,
+    pub(super) spawn_queue: Option<SpawnQueue>,
+    pub(super) probes: Vec<Arc<LatencyProbe>>,
 }
 
 impl ProgramData<'_> {
+    /// Maps each context registered inside of a subgraph instance to that instance.
+    pub(super) fn context_instances(&self) -> FxHashMap<Identifier, usize> {
+        self.instances
+            .iter()
+            .enumerate()
+            .flat_map(|(index, instance)| instance.contexts.iter().map(move |id| (*id, index)))
+            .collect()
+    }
+
+    /// Prefixes the name of a context with the path of its subgraph instance, if it has one.
+    pub(super) fn qualify(
+        &self,
+        context_instances: &FxHashMap<Identifier, usize>,
+        id: VerboseIdentifier,
+    ) -> VerboseIdentifier {
+        match context_instances.get(&id.id) {
+            Some(index) => VerboseIdentifier {
+                name: format!("{}/{}", self.instances[*index].path, id.name),
+                id: id.id,
+            },
+            None => id,
+        }
+    }
+
+    /// Groups context summaries under summaries of the subgraph instances which contain them.
+    pub(super) fn group_summaries(&self, summaries: Vec<ContextSummary>) -> Vec<ContextSummary> {
+        let context_instances = self.context_instances();
+        let mut grouped: Vec<Vec<ContextSummary>> = self.instances.iter().map(|_| vec![]).collect();
+        let mut top_level = vec![];
+        for mut summary in summaries {
+            match context_instances.get(&summary.id.id) {
+                Some(index) => {
+                    summary.id = self.qualify(&context_instances, summary.id);
+                    grouped[*index].push(summary);
+                }
+                None => top_level.push(summary),
+            }
+        }
+
+        // Nested instances are always created after their parents, so handling them in reverse finishes children first.
+        for (index, instance) in self.instances.iter().enumerate().rev() {
+            let children = std::mem::take(&mut grouped[index]);
+            if children.is_empty() {
+                continue;
+            }
+            let summary = ContextSummary {
+                id: VerboseIdentifier {
+                    id: instance.id,
+                    name: instance.path.clone(),
+                },
+                time: ParentView {
+                    child_views: children.iter().map(|child| child.time.clone()).collect(),
+                }
+                .into(),
+                children,
+            };
+            match instance.parent {
+                Some(parent) => grouped[parent].push(summary),
+                None => top_level.push(summary),
+            }
+        }
+        top_level
+    }
+
     pub(super) fn node_identifiers(&self) -> FxHashMap<Identifier, String> {
         self.nodes
             .iter()
@@ -43,6 +123,29 @@ impl ProgramData<'_> {
             }
         }
 
+        let mut seen_channels = FxHashSet::default();
+        for edge in self.edges.iter().chain(self.void_edges.iter()) {
+            if !seen_channels.insert(edge.id()) {
+                return Err(InitializationError::DuplicateChannel(edge.id()));
+            }
+        }
+
+        let mut seen_nodes = FxHashSet::default();
+        for node in &self.nodes {
+            // Composite contexts may list a child both as a key and under its parent, so only count it once per node.
+            let own_ids: FxHashSet<_> = node
+                .ids()
+                .into_iter()
+                .flat_map(|(id, children)| std::iter::once(id).chain(children))
+                .map(|verbose| verbose.id)
+                .collect();
+            for id in own_ids {
+                if !seen_nodes.insert(id) {
+                    return Err(InitializationError::DuplicateIdentifier(id));
+                }
+            }
+        }
+
         let all_node_ids = self.node_identifiers();
         // check that all of our edge targets are in the nodes
         for edge in self.edges.iter().chain(self.void_edges.iter()) {
@@ -0,0 +1,428 @@
+use std::sync::Arc;
+
+use thiserror::Error;
+
+use crate::{
+    channel::{
+        handle::{ChannelData, ChannelHandle},
+        ChannelID, Receiver, Sender,
+    },
+    context::Context,
+    datastructures::{Identifier, VerboseIdentifier},
+    types::DAMType,
+};
+
+use super::{PortDirection, PortError, ProgramBuilder};
+
+/// Selects a context registered with a [ProgramBuilder], either by ID or by instance name.
+/// Names match either the context's own name, or its name qualified by its subgraph path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContextSelector {
+    /// Select by identifier
+    Id(Identifier),
+    /// Select by name
+    Name(String),
+}
+
+impl From<Identifier> for ContextSelector {
+    fn from(value: Identifier) -> Self {
+        Self::Id(value)
+    }
+}
+
+impl From<&str> for ContextSelector {
+    fn from(value: &str) -> Self {
+        Self::Name(value.to_string())
+    }
+}
+
+impl From<String> for ContextSelector {
+    fn from(value: String) -> Self {
+        Self::Name(value)
+    }
+}
+
+/// Errors from [ProgramBuilder::replace_context]
+#[derive(Error, Debug)]
+pub enum ReplaceError {
+    /// No registered context matched the selector
+    #[error("No context matches {0:?}")]
+    UnknownContext(ContextSelector),
+
+    /// Several registered contexts matched a name
+    #[error("Multiple contexts are named {0:?}")]
+    AmbiguousName(String),
+
+    /// An endpoint was requested for a channel that the replaced context was not attached to in that direction
+    #[error("Channel {0:?} was not connected to the replaced context in this direction")]
+    NotConnected(ChannelID),
+
+    /// Each endpoint can only be handed out once
+    #[error("An endpoint for channel {0:?} was already taken")]
+    AlreadyTaken(ChannelID),
+
+    /// An endpoint was requested with the wrong element type
+    #[error("Channel {0:?} does not carry the requested type")]
+    TypeMismatch(ChannelID),
+
+    /// The replacement must attach to every channel the replaced context was attached to
+    #[error("Replacement for {0:?} did not attach to channel {1:?}")]
+    Unattached(VerboseIdentifier, ChannelID),
+
+    /// Subgraph instances are elaborated before looking up contexts, which requires their ports to be bound
+    #[error(transparent)]
+    Port(#[from] PortError),
+}
+
+struct Detached {
+    handle: Arc<dyn ChannelHandle>,
+    taken: bool,
+}
+
+/// Hands out fresh endpoints for the channels of a context being replaced via [ProgramBuilder::replace_context].
+pub struct Rewire {
+    replaced: VerboseIdentifier,
+    inputs: Vec<Detached>,
+    outputs: Vec<Detached>,
+}
+
+impl Rewire {
+    /// The context being replaced
+    pub fn replaced(&self) -> &VerboseIdentifier {
+        &self.replaced
+    }
+
+    /// Channels which the replaced context received from
+    pub fn inputs(&self) -> Vec<ChannelID> {
+        self.inputs.iter().map(|input| input.handle.id()).collect()
+    }
+
+    /// Channels which the replaced context sent to
+    pub fn outputs(&self) -> Vec<ChannelID> {
+        self.outputs
+            .iter()
+            .map(|output| output.handle.id())
+            .collect()
+    }
+
+    fn take<T: Clone + 'static>(
+        detached: &mut [Detached],
+        id: ChannelID,
+    ) -> Result<Arc<ChannelData<T>>, ReplaceError> {
+        let entry = detached
+            .iter_mut()
+            .find(|entry| entry.handle.id() == id)
+            .ok_or(ReplaceError::NotConnected(id))?;
+        if entry.taken {
+            return Err(ReplaceError::AlreadyTaken(id));
+        }
+        let channel = entry
+            .handle
+            .clone()
+            .into_any()
+            .downcast::<Arc<ChannelData<T>>>()
+            .map_err(|_| ReplaceError::TypeMismatch(id))?;
+        entry.taken = true;
+        Ok(*channel)
+    }
+
+    /// Creates a new receiver for one of [Rewire::inputs].
+    pub fn receiver<T: DAMType + 'static>(
+        &mut self,
+        id: ChannelID,
+    ) -> Result<Receiver<T>, ReplaceError> {
+        Ok(Receiver {
+            underlying: Self::take(&mut self.inputs, id)?,
+        })
+    }
+
+    /// Creates a new sender for one of [Rewire::outputs].
+    pub fn sender<T: DAMType + 'static>(
+        &mut self,
+        id: ChannelID,
+    ) -> Result<Sender<T>, ReplaceError> {
+        Ok(Sender {
+            underlying: Self::take(&mut self.outputs, id)?,
+        })
+    }
+}
+
+impl<'a> ProgramBuilder<'a> {
+    /// Looks up a registered context, returning its identifier.
+    /// Subgraph instances are elaborated first, so contexts inside of them can be found by their qualified names,
+    /// and their ports must already be bound.
+    pub fn find_context(
+        &mut self,
+        selector: impl Into<ContextSelector>,
+    ) -> Result<Identifier, ReplaceError> {
+        self.find_node(&selector.into())
+            .map(|index| self.data.nodes[index].id())
+    }
+
+    pub(super) fn find_node(&mut self, selector: &ContextSelector) -> Result<usize, ReplaceError> {
+        self.elaborate()?;
+        let context_instances = self.data.context_instances();
+        let mut matches = self
+            .data
+            .nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| match selector {
+                ContextSelector::Id(id) => node.id() == *id,
+                ContextSelector::Name(name) => {
+                    node.name() == *name
+                        || self.data.qualify(&context_instances, node.verbose()).name == *name
+                }
+            })
+            .map(|(index, _)| index);
+        let found = matches
+            .next()
+            .ok_or_else(|| ReplaceError::UnknownContext(selector.clone()))?;
+        match (matches.next(), selector) {
+            (Some(_), ContextSelector::Name(name)) => {
+                Err(ReplaceError::AmbiguousName(name.clone()))
+            }
+            _ => Ok(found),
+        }
+    }
+}
+
+// Endpoints are recovered by downcasting channels to their element type, which requires them to be 'static.
+impl ProgramBuilder<'static> {
+    /// Replaces a registered context with another implementation that is attached to the same channels.
+    /// `make` constructs the replacement using endpoints from the provided [Rewire], with this builder's IDs in scope.
+    /// The replacement takes over the old context's position, including its subgraph instance if it had one.
+    /// Contexts inside of subgraph instances are selected as with [ProgramBuilder::find_context].
+    /// Only programs whose channels carry `'static` types can be modified this way.
+    /// If the replacement does not attach to every channel, it is dropped and the builder is left as it was.
+    pub fn replace_context<C, F>(
+        &mut self,
+        selector: impl Into<ContextSelector>,
+        make: F,
+    ) -> Result<Identifier, ReplaceError>
+    where
+        C: Context + 'static,
+        F: FnOnce(&mut Rewire) -> C,
+    {
+        let index = self.find_node(&selector.into())?;
+        let old = &self.data.nodes[index];
+        let replaced = old.verbose();
+        // Hierarchical contexts may have attached their children to channels instead of themselves.
+        let old_ids: Vec<_> = old.ids().into_keys().map(|verbose| verbose.id).collect();
+
+        let mut rewire = Rewire {
+            replaced,
+            inputs: vec![],
+            outputs: vec![],
+        };
+        // The old context keeps its endpoints until the replacement is accepted, so its attachments are recorded to restore them on failure.
+        let mut previous = vec![];
+        for edge in self.data.edges.iter().chain(self.data.void_edges.iter()) {
+            if edge.sender().is_some_and(|id| old_ids.contains(&id)) {
+                previous.push((
+                    edge.clone(),
+                    PortDirection::Output,
+                    edge.spec().sender_attachment(),
+                ));
+                edge.detach_sender();
+                rewire.outputs.push(Detached {
+                    handle: edge.clone(),
+                    taken: false,
+                });
+            }
+            if edge.receiver().is_some_and(|id| old_ids.contains(&id)) {
+                previous.push((
+                    edge.clone(),
+                    PortDirection::Input,
+                    edge.spec().receiver_attachment(),
+                ));
+                edge.detach_receiver();
+                rewire.inputs.push(Detached {
+                    handle: edge.clone(),
+                    taken: false,
+                });
+            }
+        }
+
+        let replacement = self.with_ids(|| make(&mut rewire));
+        let new_id = replacement.id();
+        let new_ids: Vec<_> = replacement
+            .ids()
+            .into_keys()
+            .map(|verbose| verbose.id)
+            .collect();
+        let attached = |id: Option<Identifier>| id.is_some_and(|id| new_ids.contains(&id));
+        let unattached = rewire
+            .outputs
+            .iter()
+            .find(|output| !attached(output.handle.sender()))
+            .or_else(|| {
+                rewire
+                    .inputs
+                    .iter()
+                    .find(|input| !attached(input.handle.receiver()))
+            })
+            .map(|detached| detached.handle.id());
+
+        // Dropping a context closes the channel ends it holds, so the surviving context's attachments are restored afterwards.
+        let survivors = match unattached {
+            Some(_) => {
+                drop(replacement);
+                previous
+            }
+            None => {
+                let current = previous
+                    .iter()
+                    .map(|(edge, direction, _)| {
+                        let attachment = match direction {
+                            PortDirection::Output => edge.spec().sender_attachment(),
+                            PortDirection::Input => edge.spec().receiver_attachment(),
+                        };
+                        (edge.clone(), *direction, attachment)
+                    })
+                    .collect();
+                drop(std::mem::replace(
+                    &mut self.data.nodes[index],
+                    Box::new(replacement),
+                ));
+                current
+            }
+        };
+        for (edge, direction, attachment) in survivors {
+            match direction {
+                PortDirection::Output => edge.reattach_sender(attachment),
+                PortDirection::Input => edge.reattach_receiver(attachment),
+            }
+        }
+
+        if let Some(channel) = unattached {
+            return Err(ReplaceError::Unattached(rewire.replaced, channel));
+        }
+        for instance in &mut self.data.instances {
+            for context in &mut instance.contexts {
+                if *context == rewire.replaced.id {
+                    *context = new_id;
+                }
+            }
+        }
+        Ok(new_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        simulation::{InitializationOptions, PortError, ProgramBuilder, RunOptions, Subgraph},
+        utility_contexts::{CheckerContext, ConsumerContext, FunctionContext, GeneratorContext},
+    };
+
+    use super::ReplaceError;
+
+    fn pipeline() -> ProgramBuilder<'static> {
+        let mut parent = ProgramBuilder::default();
+        let (snd, rcv) = parent.bounded(4);
+        let mut gen = GeneratorContext::new(|| 0..32u32, snd);
+        gen.set_name("gen");
+        parent.add_child(gen);
+        let mut sink = ConsumerContext::new(rcv);
+        sink.set_name("sink");
+        parent.add_child(sink);
+        parent
+    }
+
+    #[test]
+    fn test_replace_context() {
+        let mut parent = pipeline();
+        let old_id = parent.find_context("sink").unwrap();
+        let new_id = parent
+            .replace_context(old_id, |rewire| {
+                let input = rewire.inputs()[0];
+                // Deliberately expect the wrong values, to make sure the checker is what actually runs.
+                CheckerContext::new(|| 1..33u32, rewire.receiver(input).unwrap())
+            })
+            .unwrap();
+        assert_ne!(old_id, new_id);
+        assert_eq!(parent.num_children(), 2);
+
+        let executed = parent
+            .initialize(InitializationOptions::default())
+            .unwrap()
+            .run(RunOptions::default());
+        assert!(!executed.passed());
+    }
+
+    #[test]
+    fn test_replace_errors() {
+        let mut parent = pipeline();
+        assert!(matches!(
+            parent.find_context("missing"),
+            Err(ReplaceError::UnknownContext(_))
+        ));
+
+        let result = parent.replace_context("gen", |rewire| {
+            let output = rewire.outputs()[0];
+            assert!(matches!(
+                rewire.sender::<u64>(output),
+                Err(ReplaceError::TypeMismatch(_))
+            ));
+            assert!(matches!(
+                rewire.receiver::<u32>(output),
+                Err(ReplaceError::NotConnected(_))
+            ));
+            let _unused = rewire.sender::<u32>(output).unwrap();
+            assert!(matches!(
+                rewire.sender::<u32>(output),
+                Err(ReplaceError::AlreadyTaken(_))
+            ));
+            // Never attaches to the output channel
+            let mut ctx = FunctionContext::new();
+            ctx.set_run(|_| {});
+            ctx
+        });
+        assert!(matches!(result, Err(ReplaceError::Unattached(_, _))));
+
+        // The failed replacement left the original program intact.
+        let gen = parent.find_context("gen").unwrap();
+        assert_eq!(parent.num_children(), 2);
+        assert!(parent.data.edges[0].sender() == Some(gen));
+        assert!(parent
+            .initialize(InitializationOptions::default())
+            .unwrap()
+            .run(RunOptions::default())
+            .passed());
+    }
+
+    #[test]
+    fn test_replace_in_subgraph() {
+        let sink = Subgraph::new("Sink", |sg| {
+            let mut sink = ConsumerContext::new(sg.input::<u32>("in")?);
+            sink.set_name("sink");
+            sg.add_child(sink);
+            Ok(())
+        })
+        .input::<u32>("in");
+        let mut parent = ProgramBuilder::default();
+        let tile = parent.instantiate(&sink, "tile");
+        let (snd, rcv) = parent.bounded(4);
+        parent.add_child(GeneratorContext::new(|| 0..32u32, snd));
+
+        // Looking up a context runs the bodies of instances, so their ports must be bound.
+        assert!(matches!(
+            parent.find_context("tile/sink"),
+            Err(ReplaceError::Port(PortError::Unbound { .. }))
+        ));
+        parent.bind_input(tile, "in", rcv).unwrap();
+        parent
+            .replace_context("tile/sink", |rewire| {
+                let input = rewire.inputs()[0];
+                CheckerContext::new(|| 1..33u32, rewire.receiver(input).unwrap())
+            })
+            .unwrap();
+
+        let executed = parent
+            .initialize(InitializationOptions::default())
+            .unwrap()
+            .run(RunOptions::default());
+        assert!(!executed.passed());
+    }
+}
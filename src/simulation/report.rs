@@ -0,0 +1,48 @@
+use crate::channel::{ChannelFlavor, ChannelID};
+
+/// The flavor chosen for a single channel during initialization.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelReport {
+    /// The channel this entry describes
+    pub id: ChannelID,
+    /// The flavor the channel was initialized with
+    pub flavor: ChannelFlavor,
+    /// The (non-trivial) strongly connected component containing this channel, or None if it isn't part of a cycle.
+    pub scc: Option<usize>,
+    /// Whether the flavor was set through [super::ProgramBuilder::force_flavor] instead of being inferred.
+    pub forced: bool,
+}
+
+/// A record of the decisions made while initializing a program, obtained via [super::Initialized::report].
+#[derive(Debug, Clone, Default)]
+pub struct InitializationReport {
+    pub(super) channels: Vec<ChannelReport>,
+}
+
+impl InitializationReport {
+    /// All channels in the program, including void channels.
+    pub fn channels(&self) -> &[ChannelReport] {
+        &self.channels
+    }
+
+    /// Looks up the entry for a particular channel.
+    pub fn channel(&self, id: ChannelID) -> Option<&ChannelReport> {
+        self.channels.iter().find(|report| report.id == id)
+    }
+}
+
+impl std::fmt::Display for InitializationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for report in &self.channels {
+            write!(f, "{}: {:?}", report.id, report.flavor)?;
+            if let Some(scc) = report.scc {
+                write!(f, " (SCC {scc})")?;
+            }
+            if report.forced {
+                write!(f, " [forced]")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,410 @@
+use std::{num::NonZeroU64, ops::Deref, sync::Arc};
+
+use thiserror::Error;
+
+use crate::{
+    channel::{handle::ChannelHandle, ChannelFlavor, ChannelID, ChannelOptions, Receiver, Sender},
+    context::Context,
+    datastructures::{ContextInfo, IdAllocator, Time},
+    shim::Mutex,
+    types::DAMType,
+    view::TimeManager,
+};
+
+pub(super) type RuntimeChannel = Arc<dyn ChannelHandle + Send + Sync>;
+
+/// Channels and contexts created through a [SpawnHandle].
+/// Channels stay pending until both of their ends are attached, at which point they become Cyclic.
+/// Spawned contexts wait until none of their channels are pending, and are then handed to the program.
+/// Channels which never have both ends attached stay pending, and cannot be used.
+#[derive(Default)]
+pub(super) struct RuntimeChannels {
+    pending: Vec<RuntimeChannel>,
+    pub(super) finalized: Vec<RuntimeChannel>,
+    waiting: Vec<Box<dyn Context>>,
+}
+
+impl RuntimeChannels {
+    /// The first pending channel connected to a context, if any.
+    fn pending_for(&self, child: &dyn Context) -> Option<ChannelID> {
+        let ids: Vec<_> = child.ids().into_keys().map(|verbose| verbose.id).collect();
+        let connected = |id: Option<_>| id.is_some_and(|id| ids.contains(&id));
+        self.pending
+            .iter()
+            .find(|chan| connected(chan.sender()) || connected(chan.receiver()))
+            .map(|chan| chan.id())
+    }
+
+    /// Contexts which were spawned, but never started because one of their channels was left pending.
+    pub(super) fn take_stranded(&mut self) -> Vec<(Box<dyn Context>, ChannelID)> {
+        std::mem::take(&mut self.waiting)
+            .into_iter()
+            .map(|child| {
+                let chan = self
+                    .pending_for(child.as_ref())
+                    .expect("Waiting contexts are always connected to a pending channel");
+                (child, chan)
+            })
+            .collect()
+    }
+}
+
+/// Ways that spawning a context can fail
+#[derive(Error, Debug)]
+pub enum SpawnError {
+    /// Spawned contexts only start once every channel connected to them has both ends attached.
+    /// Reported as a failure of the spawned context if the program finishes first.
+    #[error("Channel {0:?} of the spawned context never had both ends attached")]
+    Unattached(ChannelID),
+
+    /// Contexts cannot be spawned into the past of the context spawning them
+    #[error("Cannot spawn a context at {requested:?}, which is before the spawning context's time {current:?}")]
+    InThePast {
+        /// The time the context was to be spawned at
+        requested: Time,
+        /// The time of the spawning context
+        current: Time,
+    },
+}
+
+/// The program's side of a [SpawnHandle], which receives the spawned contexts.
+pub(super) struct SpawnQueue {
+    pub(super) requests: crossbeam::channel::Receiver<Box<dyn Context>>,
+    pub(super) channels: Arc<Mutex<RuntimeChannels>>,
+}
+
+/// Allows contexts to create channels and spawn new contexts while the program is running.
+/// Obtained via [super::ProgramBuilder::spawn_handle], and typically passed into a context's constructor.
+/// [super::Initialized::run] does not return until every copy of the handle has been dropped,
+/// which normally happens when the contexts holding them finish.
+/// Channels and contexts are given IDs from the program's builder, which are deterministic as long as they are created in a deterministic order.
+#[derive(Clone)]
+pub struct SpawnHandle {
+    requests: crossbeam::channel::Sender<Box<dyn Context>>,
+    channels: Arc<Mutex<RuntimeChannels>>,
+    ids: Arc<IdAllocator>,
+}
+
+impl SpawnHandle {
+    pub(super) fn new(ids: Arc<IdAllocator>) -> (Self, SpawnQueue) {
+        let (snd, rcv) = crossbeam::channel::unbounded();
+        let channels = Arc::new(Mutex::new(RuntimeChannels::default()));
+        (
+            Self {
+                requests: snd,
+                channels: channels.clone(),
+                ids,
+            },
+            SpawnQueue {
+                requests: rcv,
+                channels,
+            },
+        )
+    }
+
+    /// Constructs a channel with a combination of settings, see [ChannelOptions].
+    /// Panics if the initial elements are invalid, which fails the calling context, see [ChannelOptions::initial].
+    pub fn channel<T: DAMType + 'static>(
+        &self,
+        options: ChannelOptions<T>,
+    ) -> (Sender<T>, Receiver<T>) {
+        if let Some(reason) = options.invalid_initial() {
+            panic!("Invalid initial elements: {reason}");
+        }
+        let spec = {
+            let _scope = self.ids.install();
+            Arc::new(options.spec())
+        };
+        let underlying = Arc::new(options.into_data(spec));
+        self.channels
+            .lock()
+            .unwrap()
+            .pending
+            .push(underlying.clone());
+        (
+            Sender {
+                underlying: underlying.clone(),
+            },
+            Receiver { underlying },
+        )
+    }
+
+    /// Constructs a bounded channel with unit latency
+    pub fn bounded<T: DAMType + 'static>(&self, capacity: usize) -> (Sender<T>, Receiver<T>) {
+        self.channel(ChannelOptions::bounded(capacity))
+    }
+
+    /// Constructs a bounded channel with a given latency
+    pub fn bounded_with_latency<T: DAMType + 'static>(
+        &self,
+        capacity: usize,
+        latency: u64,
+        resp_latency: u64,
+    ) -> (Sender<T>, Receiver<T>) {
+        self.channel(ChannelOptions::bounded(capacity).latency(latency, resp_latency))
+    }
+
+    /// See [super::ProgramBuilder::bounded_with_bandwidth]
+    pub fn bounded_with_bandwidth<T: DAMType + 'static>(
+        &self,
+        capacity: usize,
+        latency: u64,
+        resp_latency: u64,
+        bits_per_cycle: NonZeroU64,
+    ) -> (Sender<T>, Receiver<T>) {
+        self.channel(
+            ChannelOptions::bounded(capacity)
+                .latency(latency, resp_latency)
+                .bandwidth(bits_per_cycle),
+        )
+    }
+
+    /// See [super::ProgramBuilder::bounded_with_latency_fn]
+    pub fn bounded_with_latency_fn<T, F>(
+        &self,
+        capacity: usize,
+        min_latency: u64,
+        resp_latency: u64,
+        latency: F,
+    ) -> (Sender<T>, Receiver<T>)
+    where
+        T: DAMType + 'static,
+        F: Fn(&T, Time) -> u64 + Send + Sync + 'static,
+    {
+        self.channel(
+            ChannelOptions::bounded(capacity)
+                .latency(min_latency, resp_latency)
+                .latency_fn(latency),
+        )
+    }
+
+    /// See [super::ProgramBuilder::bounded_out_of_order]
+    pub fn bounded_out_of_order<T: DAMType + 'static>(
+        &self,
+        capacity: usize,
+        latency: u64,
+        resp_latency: u64,
+    ) -> (Sender<T>, Receiver<T>) {
+        self.channel(
+            ChannelOptions::bounded(capacity)
+                .latency(latency, resp_latency)
+                .out_of_order(),
+        )
+    }
+
+    /// Constructs an infinitely deep channel with unit latency
+    pub fn unbounded<T: DAMType + 'static>(&self) -> (Sender<T>, Receiver<T>) {
+        self.channel(ChannelOptions::unbounded())
+    }
+
+    /// Constructs an infinitely deep channel with given latency
+    pub fn unbounded_with_latency<T: DAMType + 'static>(
+        &self,
+        latency: u64,
+        resp_latency: u64,
+    ) -> (Sender<T>, Receiver<T>) {
+        self.channel(ChannelOptions::unbounded().latency(latency, resp_latency))
+    }
+
+    /// Spawns a context at the current time of the spawning context, see [SpawnHandle::spawn_at].
+    pub fn spawn<C, F>(&self, manager: &TimeManager, make: F) -> Result<(), SpawnError>
+    where
+        C: Context + Deref<Target = ContextInfo> + 'static,
+        F: FnOnce() -> C,
+    {
+        self.spawn_at(manager, manager.tick(), make)
+    }
+
+    /// Spawns the context constructed by `make`, which starts at a particular time that may not be earlier than the spawning context's time.
+    /// The context is constructed while the program's IDs are in scope, so it should be created inside of `make` rather than before.
+    /// The child starts once both ends of every channel connected to it are attached, either to the spawning context,
+    /// to a static context, or to another spawned context.
+    /// This allows spawning two children connected to each other, the first of which waits for the second.
+    /// Children which are still waiting when the program finishes are reported as [SpawnError::Unattached] failures.
+    pub fn spawn_at<C, F>(
+        &self,
+        manager: &TimeManager,
+        time: Time,
+        make: F,
+    ) -> Result<(), SpawnError>
+    where
+        C: Context + Deref<Target = ContextInfo> + 'static,
+        F: FnOnce() -> C,
+    {
+        let current = manager.tick();
+        if time < current {
+            return Err(SpawnError::InThePast {
+                requested: time,
+                current,
+            });
+        }
+
+        let child = {
+            let _scope = self.ids.install();
+            make()
+        };
+        child.time.advance(time);
+
+        let ready = {
+            let mut channels = self.channels.lock().unwrap();
+            channels.waiting.push(Box::new(child));
+
+            let (attached, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut channels.pending)
+                .into_iter()
+                .partition(|chan| chan.sender().is_some() && chan.receiver().is_some());
+            channels.pending = pending;
+            for chan in attached {
+                chan.set_flavor(ChannelFlavor::Cyclic);
+                channels.finalized.push(chan);
+            }
+
+            let (waiting, ready): (Vec<_>, Vec<_>) = std::mem::take(&mut channels.waiting)
+                .into_iter()
+                .partition(|child| channels.pending_for(child.as_ref()).is_some());
+            channels.waiting = waiting;
+            ready
+        };
+
+        for mut child in ready {
+            child.init();
+            // The program holds onto the queue until every handle has been dropped, so this cannot fail.
+            let _ = self.requests.send(child);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dam_macros::context_internal;
+
+    use crate::{
+        context::Context,
+        simulation::{InitializationOptions, ProgramBuilder, RunOptions},
+        utility_contexts::{CheckerContext, GeneratorContext},
+    };
+
+    use super::{SpawnError, SpawnHandle};
+
+    /// Spawns a generator per worker, and reads back all of their outputs.
+    #[context_internal]
+    struct Spawner {
+        handle: SpawnHandle,
+        workers: u32,
+    }
+
+    impl Context for Spawner {
+        fn run(&mut self) {
+            // Neither child can start until the other has been spawned, since they are only connected to each other.
+            let (snd, rcv) = self.handle.bounded::<u32>(2);
+            self.handle
+                .spawn(&self.time, || GeneratorContext::new(|| 0..8u32, snd))
+                .unwrap();
+            self.handle
+                .spawn(&self.time, || CheckerContext::new(|| 0..8u32, rcv))
+                .unwrap();
+
+            // The spawning context has moved on, so it cannot spawn a worker at the start of time.
+            self.time.incr_cycles(1);
+            let (snd, rcv) = self.handle.bounded::<u32>(2);
+            rcv.attach_receiver(self);
+            assert!(matches!(
+                self.handle.spawn_at(&self.time, self.time.tick() - 1, || {
+                    GeneratorContext::new(|| 0..8u32, snd)
+                }),
+                Err(SpawnError::InThePast { .. })
+            ));
+            drop(rcv);
+
+            let mut receivers = vec![];
+            for worker in 0..self.workers {
+                let (snd, rcv) = self.handle.bounded::<u32>(2);
+                rcv.attach_receiver(self);
+                self.handle
+                    .spawn(&self.time, || {
+                        GeneratorContext::new(move || (0..8).map(move |x| x * worker), snd)
+                    })
+                    .unwrap();
+                receivers.push(rcv);
+                self.time.incr_cycles(100);
+            }
+            for (worker, rcv) in receivers.iter().enumerate() {
+                for x in 0..8 {
+                    let element = rcv.dequeue(&self.time).unwrap();
+                    assert_eq!(element.data, x * worker as u32);
+                    // Workers cannot have produced anything before they were spawned.
+                    assert!(element.time.time() > 100 * worker as u64);
+                }
+            }
+        }
+    }
+
+    /// Returns the sorted IDs of every context which ran.
+    fn run_spawner() -> Vec<usize> {
+        let mut parent = ProgramBuilder::default();
+        let handle = parent.spawn_handle();
+        parent.add_child_with(|| Spawner {
+            handle,
+            workers: 4,
+            context_info: Default::default(),
+        });
+
+        let executed = parent
+            .initialize(InitializationOptions::default())
+            .unwrap()
+            .run(RunOptions::default());
+        assert!(executed.passed());
+        assert_eq!(executed.summaries().len(), 7);
+        let mut ids: Vec<_> = executed
+            .summaries()
+            .iter()
+            .map(|summary| summary.id.id.id)
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    /// Spawns a generator whose receiver is never attached.
+    #[context_internal]
+    struct Stranded {
+        handle: SpawnHandle,
+    }
+
+    impl Context for Stranded {
+        fn run(&mut self) {
+            let (snd, _rcv) = self.handle.bounded::<u32>(2);
+            self.handle
+                .spawn(&self.time, || GeneratorContext::new(|| 0..8u32, snd))
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_stranded_spawn() {
+        let mut parent = ProgramBuilder::default();
+        let handle = parent.spawn_handle();
+        parent.add_child_with(|| Stranded {
+            handle,
+            context_info: Default::default(),
+        });
+
+        let executed = parent
+            .initialize(InitializationOptions::default())
+            .unwrap()
+            .run(RunOptions::default());
+        assert!(!executed.passed());
+        executed.run_failures(|failures| {
+            assert_eq!(failures.len(), 1);
+            assert!(matches!(
+                failures[0].underlying.downcast_ref::<SpawnError>(),
+                Some(SpawnError::Unattached(_))
+            ));
+        });
+    }
+
+    #[test]
+    fn test_runtime_spawn() {
+        // Spawned contexts draw their IDs from the program, so they are the same on every run.
+        assert_eq!(run_spawner(), run_spawner());
+    }
+}
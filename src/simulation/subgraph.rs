@@ -0,0 +1,723 @@
+use std::{
+    any::{Any, TypeId},
+    num::NonZeroU64,
+    sync::Arc,
+};
+
+use rustc_hash::FxHashMap;
+use thiserror::Error;
+
+use crate::{
+    channel::{ChannelID, ChannelOptions, Receiver, Sender},
+    context::Context,
+    datastructures::{Identifier, Time},
+    types::DAMType,
+};
+
+use super::ProgramBuilder;
+
+/// Whether a port carries data into or out of a subgraph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortDirection {
+    /// The subgraph receives from this port
+    Input,
+    /// The subgraph sends to this port
+    Output,
+}
+
+#[derive(Debug, Clone)]
+struct PortDecl {
+    name: String,
+    direction: PortDirection,
+    type_id: TypeId,
+    type_name: &'static str,
+}
+
+/// Ways that binding or connecting subgraph ports can fail
+#[derive(Error, Debug, Clone)]
+pub enum PortError {
+    /// The subgraph does not declare a port with this name
+    #[error("{instance} has no port named {port}")]
+    UnknownPort {
+        /// Path of the instance
+        instance: String,
+        /// Name of the port
+        port: String,
+    },
+
+    /// An input was used as an output or vice versa
+    #[error("Port {instance}.{port} is not an {expected:?} port")]
+    WrongDirection {
+        /// Path of the instance
+        instance: String,
+        /// Name of the port
+        port: String,
+        /// The direction the port was used as
+        expected: PortDirection,
+    },
+
+    /// The channel type does not match the declared port type
+    #[error("Port {instance}.{port} has type {declared}, but was bound to {found}")]
+    TypeMismatch {
+        /// Path of the instance
+        instance: String,
+        /// Name of the port
+        port: String,
+        /// The declared type
+        declared: &'static str,
+        /// The type of the bound channel
+        found: &'static str,
+    },
+
+    /// Each port can only be bound once
+    #[error("Port {instance}.{port} is already bound")]
+    AlreadyBound {
+        /// Path of the instance
+        instance: String,
+        /// Name of the port
+        port: String,
+    },
+
+    /// All ports must be bound before initialization
+    #[error("Port {instance}.{port} was never bound")]
+    Unbound {
+        /// Path of the instance
+        instance: String,
+        /// Name of the port
+        port: String,
+    },
+
+    /// Each bound endpoint can only be taken once by the instance's body
+    #[error("Port {instance}.{port} was already taken")]
+    AlreadyTaken {
+        /// Path of the instance
+        instance: String,
+        /// Name of the port
+        port: String,
+    },
+
+    /// Ports cannot be bound once the instance's body has run, such as after looking up a context
+    #[error("Port {instance}.{port} cannot be bound after {instance} was elaborated")]
+    AlreadyElaborated {
+        /// Path of the instance
+        instance: String,
+        /// Name of the port
+        port: String,
+    },
+}
+
+type SubgraphBody<'a> =
+    dyn Fn(&mut SubgraphBuilder<'_, 'a>) -> Result<(), PortError> + Send + Sync + 'a;
+
+/// A reusable definition of a group of contexts and channels, with typed, named ports.
+/// Definitions are stamped out with [ProgramBuilder::instantiate], and the body is run once per instance during initialization,
+/// or when contexts are first looked up via [ProgramBuilder::find_context].
+pub struct Subgraph<'a> {
+    name: String,
+    ports: Vec<PortDecl>,
+    body: Arc<SubgraphBody<'a>>,
+}
+
+impl Clone for Subgraph<'_> {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            ports: self.ports.clone(),
+            body: self.body.clone(),
+        }
+    }
+}
+
+impl<'a> Subgraph<'a> {
+    /// Defines a new subgraph, whose contents are constructed by `body`.
+    /// Errors returned by the body, such as from taking a port with the wrong type, are reported when the instance is elaborated.
+    pub fn new(
+        name: impl Into<String>,
+        body: impl Fn(&mut SubgraphBuilder<'_, 'a>) -> Result<(), PortError> + Send + Sync + 'a,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            ports: vec![],
+            body: Arc::new(body),
+        }
+    }
+
+    fn with_port<T: 'static>(mut self, name: &str, direction: PortDirection) -> Self {
+        assert!(
+            self.ports.iter().all(|port| port.name != name),
+            "Duplicate port {name} on {}",
+            self.name
+        );
+        self.ports.push(PortDecl {
+            name: name.to_string(),
+            direction,
+            type_id: TypeId::of::<T>(),
+            type_name: std::any::type_name::<T>(),
+        });
+        self
+    }
+
+    /// Declares an input port carrying values of type T
+    pub fn input<T: 'static>(self, name: &str) -> Self {
+        self.with_port::<T>(name, PortDirection::Input)
+    }
+
+    /// Declares an output port carrying values of type T
+    pub fn output<T: 'static>(self, name: &str) -> Self {
+        self.with_port::<T>(name, PortDirection::Output)
+    }
+
+    /// The name of the definition
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// A handle to an instantiated [Subgraph], used for binding its ports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Instance {
+    index: usize,
+}
+
+pub(super) struct InstanceData<'a> {
+    pub(super) id: Identifier,
+    pub(super) path: String,
+    pub(super) parent: Option<usize>,
+    pub(super) contexts: Vec<Identifier>,
+    definition: Subgraph<'a>,
+    bindings: FxHashMap<String, Box<dyn Any + Send + Sync>>,
+}
+
+impl InstanceData<'_> {
+    /// Checks that a port is declared with this direction and type.
+    fn check<T: 'static>(&self, port: &str, direction: PortDirection) -> Result<(), PortError> {
+        let decl = self
+            .definition
+            .ports
+            .iter()
+            .find(|decl| decl.name == port)
+            .ok_or_else(|| PortError::UnknownPort {
+                instance: self.path.clone(),
+                port: port.to_string(),
+            })?;
+        if decl.direction != direction {
+            return Err(PortError::WrongDirection {
+                instance: self.path.clone(),
+                port: port.to_string(),
+                expected: direction,
+            });
+        }
+        if decl.type_id != TypeId::of::<T>() {
+            return Err(PortError::TypeMismatch {
+                instance: self.path.clone(),
+                port: port.to_string(),
+                declared: decl.type_name,
+                found: std::any::type_name::<T>(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks that a port can be bound to an endpoint of this direction and type.
+    fn check_bind<T: 'static>(
+        &self,
+        port: &str,
+        direction: PortDirection,
+    ) -> Result<(), PortError> {
+        self.check::<T>(port, direction)?;
+        if self.bindings.contains_key(port) {
+            return Err(PortError::AlreadyBound {
+                instance: self.path.clone(),
+                port: port.to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl<'a> ProgramBuilder<'a> {
+    fn check_bind<T: 'static>(
+        &self,
+        instance: Instance,
+        port: &str,
+        direction: PortDirection,
+    ) -> Result<(), PortError> {
+        let data = &self.data.instances[instance.index];
+        if instance.index < self.data.elaborated {
+            return Err(PortError::AlreadyElaborated {
+                instance: data.path.clone(),
+                port: port.to_string(),
+            });
+        }
+        data.check_bind::<T>(port, direction)
+    }
+
+    fn bind<T: 'static>(
+        &mut self,
+        instance: Instance,
+        port: &str,
+        direction: PortDirection,
+        endpoint: Box<dyn Any + Send + Sync>,
+    ) -> Result<(), PortError> {
+        self.check_bind::<T>(instance, port, direction)?;
+        self.data.instances[instance.index]
+            .bindings
+            .insert(port.to_string(), endpoint);
+        Ok(())
+    }
+
+    fn add_instance(
+        &mut self,
+        definition: &Subgraph<'a>,
+        name: String,
+        parent: Option<usize>,
+    ) -> Instance {
+        let path = match parent {
+            Some(parent) => format!("{}/{}", self.data.instances[parent].path, name),
+            None => name,
+        };
+        let id = self.with_ids(Identifier::new);
+        self.data.instances.push(InstanceData {
+            id,
+            path,
+            parent,
+            contexts: vec![],
+            definition: definition.clone(),
+            bindings: Default::default(),
+        });
+        Instance {
+            index: self.data.instances.len() - 1,
+        }
+    }
+
+    /// Stamps out a new copy of a [Subgraph] under the given instance name.
+    /// All of its ports must be bound before initialization.
+    pub fn instantiate(&mut self, definition: &Subgraph<'a>, name: impl Into<String>) -> Instance {
+        self.add_instance(definition, name.into(), None)
+    }
+
+    /// Binds an input port of an instance to the receiving end of a channel.
+    pub fn bind_input<T: Clone + Send + Sync + 'static>(
+        &mut self,
+        instance: Instance,
+        port: &str,
+        receiver: Receiver<T>,
+    ) -> Result<(), PortError> {
+        self.bind::<T>(instance, port, PortDirection::Input, Box::new(receiver))
+    }
+
+    /// Binds an output port of an instance to the sending end of a channel.
+    pub fn bind_output<T: Clone + Send + Sync + 'static>(
+        &mut self,
+        instance: Instance,
+        port: &str,
+        sender: Sender<T>,
+    ) -> Result<(), PortError> {
+        self.bind::<T>(instance, port, PortDirection::Output, Box::new(sender))
+    }
+
+    /// Connects an output port of one instance to an input port of another with a bounded channel.
+    /// Both ports are checked before the channel is created, so nothing is bound if either of them is invalid.
+    pub fn connect<T: DAMType + 'static>(
+        &mut self,
+        src: Instance,
+        src_port: &str,
+        dst: Instance,
+        dst_port: &str,
+        capacity: usize,
+    ) -> Result<ChannelID, PortError> {
+        self.check_bind::<T>(src, src_port, PortDirection::Output)?;
+        self.check_bind::<T>(dst, dst_port, PortDirection::Input)?;
+        let (snd, rcv) = self.bounded::<T>(capacity);
+        let id = snd.id();
+        self.bind_output(src, src_port, snd)?;
+        self.bind_input(dst, dst_port, rcv)?;
+        Ok(id)
+    }
+
+    /// Runs the body of every instance which hasn't been elaborated yet, including instances created by other bodies.
+    /// A body which fails may already have used up its bindings and registered part of the instance,
+    /// so every later call returns the same error instead of running it again.
+    pub(super) fn elaborate(&mut self) -> Result<(), PortError> {
+        if let Some(err) = &self.data.failed_body {
+            return Err(err.clone());
+        }
+        while self.data.elaborated < self.data.instances.len() {
+            let index = self.data.elaborated;
+            let instance = &mut self.data.instances[index];
+            if let Some(unbound) = instance
+                .definition
+                .ports
+                .iter()
+                .find(|port| !instance.bindings.contains_key(&port.name))
+            {
+                return Err(PortError::Unbound {
+                    instance: instance.path.clone(),
+                    port: unbound.name.clone(),
+                });
+            }
+            let bindings = std::mem::take(&mut instance.bindings);
+            let body = instance.definition.body.clone();
+            // Contexts constructed by the body are numbered by this program.
+            let _scope = self.install_ids();
+            if let Err(err) = body(&mut SubgraphBuilder {
+                builder: self,
+                instance: index,
+                bindings,
+            }) {
+                self.data.failed_body = Some(err.clone());
+                return Err(err);
+            }
+            self.data.elaborated += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Populates a single instance of a [Subgraph].
+/// Contexts added here are grouped under the instance in DOT graphs and execution summaries.
+pub struct SubgraphBuilder<'b, 'a> {
+    builder: &'b mut ProgramBuilder<'a>,
+    instance: usize,
+    bindings: FxHashMap<String, Box<dyn Any + Send + Sync>>,
+}
+
+impl<'a> SubgraphBuilder<'_, 'a> {
+    /// The hierarchical path of this instance, such as `tile_3/adder`
+    pub fn path(&self) -> &str {
+        &self.builder.data.instances[self.instance].path
+    }
+
+    fn take_port<T: 'static, E: 'static>(
+        &mut self,
+        port: &str,
+        direction: PortDirection,
+    ) -> Result<E, PortError> {
+        self.builder.data.instances[self.instance].check::<T>(port, direction)?;
+        let endpoint = self
+            .bindings
+            .remove(port)
+            .ok_or_else(|| PortError::AlreadyTaken {
+                instance: self.path().to_string(),
+                port: port.to_string(),
+            })?;
+        Ok(*endpoint
+            .downcast()
+            .expect("Endpoints are checked against their port when they are bound"))
+    }
+
+    /// Takes the receiver bound to an input port of this instance
+    pub fn input<T: Clone + Send + Sync + 'static>(
+        &mut self,
+        port: &str,
+    ) -> Result<Receiver<T>, PortError> {
+        self.take_port::<T, _>(port, PortDirection::Input)
+    }
+
+    /// Takes the sender bound to an output port of this instance
+    pub fn output<T: Clone + Send + Sync + 'static>(
+        &mut self,
+        port: &str,
+    ) -> Result<Sender<T>, PortError> {
+        self.take_port::<T, _>(port, PortDirection::Output)
+    }
+
+    /// Registers a context as part of this instance, see [ProgramBuilder::add_child]
+    pub fn add_child<T>(&mut self, mut child: T)
+    where
+        T: Context + 'a,
+    {
+        self.builder.renumber(&mut child);
+        self.builder.data.instances[self.instance]
+            .contexts
+            .push(child.id());
+        self.builder.add_node(Box::new(child));
+    }
+
+    /// Instantiates a nested subgraph inside of this instance
+    pub fn instantiate(&mut self, definition: &Subgraph<'a>, name: impl Into<String>) -> Instance {
+        self.builder
+            .add_instance(definition, name.into(), Some(self.instance))
+    }
+
+    /// See [ProgramBuilder::bind_input]
+    pub fn bind_input<T: Clone + Send + Sync + 'static>(
+        &mut self,
+        instance: Instance,
+        port: &str,
+        receiver: Receiver<T>,
+    ) -> Result<(), PortError> {
+        self.builder.bind_input(instance, port, receiver)
+    }
+
+    /// See [ProgramBuilder::bind_output]
+    pub fn bind_output<T: Clone + Send + Sync + 'static>(
+        &mut self,
+        instance: Instance,
+        port: &str,
+        sender: Sender<T>,
+    ) -> Result<(), PortError> {
+        self.builder.bind_output(instance, port, sender)
+    }
+
+    /// See [ProgramBuilder::connect]
+    pub fn connect<T: DAMType + 'static>(
+        &mut self,
+        src: Instance,
+        src_port: &str,
+        dst: Instance,
+        dst_port: &str,
+        capacity: usize,
+    ) -> Result<ChannelID, PortError> {
+        self.builder
+            .connect::<T>(src, src_port, dst, dst_port, capacity)
+    }
+
+    /// See [ProgramBuilder::bounded]
+    pub fn bounded<T: Clone + Send + Sync + 'a>(
+        &mut self,
+        capacity: usize,
+    ) -> (Sender<T>, Receiver<T>) {
+        self.builder.bounded(capacity)
+    }
+
+    /// See [ProgramBuilder::bounded_with_latency]
+    pub fn bounded_with_latency<T: Clone + Send + Sync + 'a>(
+        &mut self,
+        capacity: usize,
+        latency: u64,
+        resp_latency: u64,
+    ) -> (Sender<T>, Receiver<T>) {
+        self.builder
+            .bounded_with_latency(capacity, latency, resp_latency)
+    }
+
+    /// See [ProgramBuilder::channel]
+    pub fn channel<T: Clone + Send + Sync + 'a>(
+        &mut self,
+        options: ChannelOptions<T>,
+    ) -> (Sender<T>, Receiver<T>) {
+        self.builder.channel(options)
+    }
+
+    /// See [ProgramBuilder::bounded_with_bandwidth]
+    pub fn bounded_with_bandwidth<T: DAMType + 'a>(
+        &mut self,
+        capacity: usize,
+        latency: u64,
+        resp_latency: u64,
+        bits_per_cycle: NonZeroU64,
+    ) -> (Sender<T>, Receiver<T>) {
+        self.builder
+            .bounded_with_bandwidth(capacity, latency, resp_latency, bits_per_cycle)
+    }
+
+    /// See [ProgramBuilder::bounded_with_latency_fn]
+    pub fn bounded_with_latency_fn<T, F>(
+        &mut self,
+        capacity: usize,
+        min_latency: u64,
+        resp_latency: u64,
+        latency: F,
+    ) -> (Sender<T>, Receiver<T>)
+    where
+        T: Clone + Send + Sync + 'a,
+        F: Fn(&T, Time) -> u64 + Send + Sync + 'static,
+    {
+        self.builder
+            .bounded_with_latency_fn(capacity, min_latency, resp_latency, latency)
+    }
+
+    /// See [ProgramBuilder::bounded_out_of_order]
+    pub fn bounded_out_of_order<T: Clone + Send + Sync + 'a>(
+        &mut self,
+        capacity: usize,
+        latency: u64,
+        resp_latency: u64,
+    ) -> (Sender<T>, Receiver<T>) {
+        self.builder
+            .bounded_out_of_order(capacity, latency, resp_latency)
+    }
+
+    /// See [ProgramBuilder::unbounded]
+    pub fn unbounded<T: Clone + Send + Sync + 'a>(&mut self) -> (Sender<T>, Receiver<T>) {
+        self.builder.unbounded()
+    }
+
+    /// See [ProgramBuilder::unbounded_with_latency]
+    pub fn unbounded_with_latency<T: Clone + Send + Sync + 'a>(
+        &mut self,
+        latency: u64,
+        resp_latency: u64,
+    ) -> (Sender<T>, Receiver<T>) {
+        self.builder.unbounded_with_latency(latency, resp_latency)
+    }
+
+    /// See [ProgramBuilder::void]
+    pub fn void<T: Clone + Send + Sync + 'a>(&mut self) -> Sender<T> {
+        self.builder.void()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        channel::ChannelElement,
+        simulation::{InitializationError, ProgramBuilder, ReplaceError, RunOptions},
+        utility_contexts::{CheckerContext, FunctionContext, GeneratorContext},
+    };
+
+    use super::{PortError, Subgraph};
+
+    fn incrementer() -> Subgraph<'static> {
+        Subgraph::new("Incrementer", |sg| {
+            let input = sg.input::<u32>("in")?;
+            let output = sg.output::<u32>("out")?;
+            let mut ctx = FunctionContext::new();
+            input.attach_receiver(&ctx);
+            output.attach_sender(&ctx);
+            ctx.set_run(move |time| {
+                while let Ok(elem) = input.dequeue(time) {
+                    output
+                        .enqueue(time, ChannelElement::new(time.tick() + 1, elem.data + 1))
+                        .unwrap();
+                    time.incr_cycles(1);
+                }
+            });
+            sg.add_child(ctx);
+            Ok(())
+        })
+        .input::<u32>("in")
+        .output::<u32>("out")
+    }
+
+    // Two incrementers in series, forwarding the outer ports to the inner instances.
+    fn pair() -> Subgraph<'static> {
+        let inc = incrementer();
+        Subgraph::new("Pair", move |sg| {
+            let first = sg.instantiate(&inc, "first");
+            let second = sg.instantiate(&inc, "second");
+            let input = sg.input::<u32>("in")?;
+            sg.bind_input(first, "in", input)?;
+            sg.connect::<u32>(first, "out", second, "in", 2)?;
+            let output = sg.output::<u32>("out")?;
+            sg.bind_output(second, "out", output)
+        })
+        .input::<u32>("in")
+        .output::<u32>("out")
+    }
+
+    #[test]
+    fn test_nested_subgraphs() {
+        let mut parent = ProgramBuilder::default();
+        let pair = pair();
+        let a = parent.instantiate(&pair, "pair0");
+        let b = parent.instantiate(&pair, "pair1");
+
+        let (snd, rcv) = parent.bounded(2);
+        parent.add_child(GeneratorContext::new(|| 0..16u32, snd));
+        parent.bind_input(a, "in", rcv).unwrap();
+        parent.connect::<u32>(a, "out", b, "in", 2).unwrap();
+        let (snd, rcv) = parent.bounded(2);
+        parent.bind_output(b, "out", snd).unwrap();
+        parent.add_child(CheckerContext::new(|| (0..16u32).map(|x| x + 4), rcv));
+
+        let executed = parent
+            .initialize(Default::default())
+            .unwrap()
+            .run(RunOptions::default());
+        assert!(executed.passed());
+
+        let pair0 = executed
+            .summaries()
+            .iter()
+            .find(|summary| summary.id.name == "pair0")
+            .unwrap();
+        let mut names: Vec<_> = pair0
+            .children
+            .iter()
+            .map(|child| child.id.name.clone())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["pair0/first", "pair0/second"]);
+        assert_eq!(
+            pair0.children[0].children[0].id.name,
+            format!("{}/FunctionContext", pair0.children[0].id.name)
+        );
+    }
+
+    #[test]
+    fn test_port_errors() {
+        let mut parent = ProgramBuilder::default();
+        let inc = parent.instantiate(&incrementer(), "inc");
+        let (snd, rcv) = parent.bounded::<u64>(2);
+        assert!(matches!(
+            parent.bind_input(inc, "in", rcv),
+            Err(PortError::TypeMismatch { .. })
+        ));
+        assert!(matches!(
+            parent.bind_output(inc, "in", snd),
+            Err(PortError::WrongDirection { .. })
+        ));
+
+        // A failed connection leaves both ports unbound.
+        let other = parent.instantiate(&incrementer(), "other");
+        assert!(matches!(
+            parent.connect::<u32>(inc, "out", other, "missing", 2),
+            Err(PortError::UnknownPort { .. })
+        ));
+        let (_, rcv) = parent.bounded::<u32>(2);
+        parent.bind_input(inc, "in", rcv).unwrap();
+        assert!(matches!(
+            parent.initialize(Default::default()),
+            Err(InitializationError::Port(PortError::Unbound { port, .. })) if port == "out"
+        ));
+    }
+
+    #[test]
+    fn test_body_port_errors() {
+        let wrong_type = Subgraph::new("WrongType", |sg| {
+            sg.input::<u64>("in")?;
+            Ok(())
+        })
+        .input::<u32>("in");
+        let taken_twice = Subgraph::new("TakenTwice", |sg| {
+            sg.input::<u32>("in")?;
+            sg.input::<u32>("in")?;
+            Ok(())
+        })
+        .input::<u32>("in");
+
+        let elaborate = |definition: &Subgraph<'static>| {
+            let mut parent = ProgramBuilder::default();
+            let inst = parent.instantiate(definition, "inst");
+            let (_, rcv) = parent.bounded::<u32>(2);
+            parent.bind_input(inst, "in", rcv).unwrap();
+            parent.initialize(Default::default())
+        };
+        assert!(matches!(
+            elaborate(&wrong_type),
+            Err(InitializationError::Port(PortError::TypeMismatch { .. }))
+        ));
+        assert!(matches!(
+            elaborate(&taken_twice),
+            Err(InitializationError::Port(PortError::AlreadyTaken { .. }))
+        ));
+
+        // The failed body already took its input, so later lookups and initialization report the same error.
+        let mut parent = ProgramBuilder::default();
+        let inst = parent.instantiate(&taken_twice, "inst");
+        let (_, rcv) = parent.bounded::<u32>(2);
+        parent.bind_input(inst, "in", rcv).unwrap();
+        for _ in 0..2 {
+            assert!(matches!(
+                parent.find_context("inst/missing"),
+                Err(ReplaceError::Port(PortError::AlreadyTaken { .. }))
+            ));
+        }
+        assert!(matches!(
+            parent.initialize(Default::default()),
+            Err(InitializationError::Port(PortError::AlreadyTaken { .. }))
+        ));
+    }
+}
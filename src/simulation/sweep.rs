@@ -0,0 +1,500 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use derive_builder::Builder;
+
+use crate::context::ContextSummary;
+
+use super::{InitializationOptions, ProgramBuilder, RunOptions};
+
+/// Options for running a parameter sweep via [sweep].
+#[derive(Builder, Default, Clone)]
+#[builder(pattern = "owned")]
+pub struct SweepOptions {
+    /// The number of threads shared by the points in flight. Defaults to the available parallelism of the machine.
+    /// Each running point reserves one thread per context, up to the part of the budget not kept by abandoned points, so that concurrent programs don't oversubscribe the machine.
+    #[builder(setter(into, strip_option), default)]
+    parallelism: Option<usize>,
+
+    /// Wall-clock limit for running each point, not counting time spent waiting for threads.
+    /// Points exceeding it are recorded as [PointStatus::TimedOut] and abandoned, see [sweep].
+    #[builder(setter(into, strip_option), default)]
+    timeout: Option<Duration>,
+
+    /// Options used to initialize each point
+    #[builder(setter(into), default)]
+    initialization: InitializationOptions,
+
+    /// Options used to run each point
+    #[builder(setter(into), default)]
+    run: RunOptions,
+}
+
+/// The outcome of simulating a single point.
+#[derive(Debug, Clone)]
+pub enum PointStatus {
+    /// The program ran to completion without errors
+    Passed,
+    /// The program failed to initialize
+    InitializationFailed(String),
+    /// One or more contexts returned an error
+    SimulationFailed(Vec<String>),
+    /// Building or running the program panicked
+    Panicked(String),
+    /// The point did not complete within [SweepOptions::timeout], or could not start because abandoned points held every thread
+    TimedOut,
+}
+
+/// A single row of a [SweepResults] table.
+#[derive(Clone)]
+pub struct PointResult<P> {
+    /// The parameter point
+    pub point: P,
+    /// Whether the point passed, and if not, why
+    pub status: PointStatus,
+    /// Simulated cycles, see [super::Executed::elapsed_cycles]
+    pub elapsed_cycles: Option<u64>,
+    /// The summaries of the contexts which finished, see [super::Executed::summaries]
+    pub summaries: Vec<ContextSummary>,
+    /// Wall-clock time spent running the point
+    pub wall_time: Duration,
+}
+
+impl<P> PointResult<P> {
+    /// Returns if this point ran without errors.
+    pub fn passed(&self) -> bool {
+        matches!(self.status, PointStatus::Passed)
+    }
+}
+
+impl<P: std::fmt::Debug> std::fmt::Debug for PointResult<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PointResult")
+            .field("point", &self.point)
+            .field("status", &self.status)
+            .field("elapsed_cycles", &self.elapsed_cycles)
+            .field(
+                "summaries",
+                &self
+                    .summaries
+                    .iter()
+                    .map(|summary| &summary.id)
+                    .collect::<Vec<_>>(),
+            )
+            .field("wall_time", &self.wall_time)
+            .finish()
+    }
+}
+
+/// The results of a [sweep], in the same order as the points were provided.
+#[derive(Debug, Clone)]
+pub struct SweepResults<P> {
+    rows: Vec<PointResult<P>>,
+}
+
+impl<P> SweepResults<P> {
+    /// All results, in the order the points were provided.
+    pub fn rows(&self) -> &[PointResult<P>] {
+        &self.rows
+    }
+
+    /// Returns if every point passed.
+    pub fn passed(&self) -> bool {
+        self.rows.iter().all(|row| row.passed())
+    }
+
+    /// Iterates over the points which did not pass.
+    pub fn failures(&self) -> impl Iterator<Item = &PointResult<P>> {
+        self.rows.iter().filter(|row| !row.passed())
+    }
+}
+
+impl<P: std::fmt::Debug> std::fmt::Display for SweepResults<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "index\tstatus\tcycles\twall_ms\tpoint")?;
+        for (index, row) in self.rows.iter().enumerate() {
+            let status = match &row.status {
+                PointStatus::Passed => "passed",
+                PointStatus::InitializationFailed(_) => "init_failed",
+                PointStatus::SimulationFailed(_) => "failed",
+                PointStatus::Panicked(_) => "panicked",
+                PointStatus::TimedOut => "timed_out",
+            };
+            let cycles = row
+                .elapsed_cycles
+                .map_or_else(|| "-".to_string(), |cycles| cycles.to_string());
+            writeln!(
+                f,
+                "{index}\t{status}\t{cycles}\t{}\t{:?}",
+                row.wall_time.as_millis(),
+                row.point
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "Unknown panic".to_string()
+    }
+}
+
+/// The threads which are not reserved by a running point.
+struct ThreadBudget {
+    capacity: usize,
+    state: Mutex<BudgetState>,
+    freed: Condvar,
+}
+
+struct BudgetState {
+    free: usize,
+    // Threads held by timed out points, which may never be returned.
+    abandoned: usize,
+}
+
+impl ThreadBudget {
+    /// Reserves up to `threads` threads, limited to what timed out points haven't kept.
+    /// Returns [None] if timed out points hold the entire budget.
+    fn acquire(&self, threads: usize) -> Option<usize> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            let usable = self.capacity - state.abandoned;
+            if usable == 0 {
+                return None;
+            }
+            let wanted = threads.min(usable);
+            if state.free >= wanted {
+                state.free -= wanted;
+                return Some(wanted);
+            }
+            state = self.freed.wait(state).unwrap();
+        }
+    }
+
+    fn release(&self, threads: usize, abandoned: bool) {
+        let mut state = self.state.lock().unwrap();
+        state.free += threads;
+        if abandoned {
+            state.abandoned -= threads;
+        }
+        self.freed.notify_all();
+    }
+
+    /// Settles a timed out point, keeping its threads reserved until its worker returns them.
+    /// Returns false if the worker settled it first. Settling under the lock keeps the worker from returning the threads before they are counted.
+    fn abandon(&self, settled: &AtomicBool, threads: usize) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if settled.swap(true, Ordering::AcqRel) {
+            return false;
+        }
+        state.abandoned += threads;
+        // Points waiting for more threads than are left now settle for less.
+        self.freed.notify_all();
+        true
+    }
+}
+
+/// The outcome of a point, without its wall time.
+type Outcome = (PointStatus, Option<u64>, Vec<ContextSummary>);
+
+enum Event {
+    /// A point reserved its threads and started running
+    Started { index: usize, threads: usize },
+    /// A point finished, or could not be started
+    Finished { index: usize, outcome: Outcome },
+}
+
+/// State shared by the pool's workers and the thread supervising them.
+/// Workers are detached, since they may outlive the sweep while running an abandoned point.
+struct Pool<P, F> {
+    points: Vec<P>,
+    build: F,
+    options: SweepOptions,
+    next_point: AtomicUsize,
+    budget: ThreadBudget,
+    // Set by whoever settles a point first: its worker when it finishes, or the supervisor when it times out.
+    settled: Vec<AtomicBool>,
+    events: crossbeam::channel::Sender<Event>,
+}
+
+impl<P, F> Pool<P, F>
+where
+    F: Fn(&P) -> ProgramBuilder<'static>,
+{
+    /// Simulates points until there are none left, or until one of them times out, since the supervisor replaces this worker then.
+    fn work(&self) {
+        loop {
+            let index = self.next_point.fetch_add(1, Ordering::Relaxed);
+            let Some(point) = self.points.get(index) else {
+                return;
+            };
+            if !self.simulate(index, point) {
+                return;
+            }
+        }
+    }
+
+    /// Returns whether the point was settled by this worker rather than timing out.
+    fn simulate(&self, index: usize, point: &P) -> bool {
+        let built = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            (self.build)(point).initialize(self.options.initialization.clone())
+        }));
+        let initialized = match built {
+            Ok(Ok(initialized)) => initialized,
+            Ok(Err(error)) => {
+                let status = PointStatus::InitializationFailed(error.to_string());
+                return self.finish(index, 0, (status, None, vec![]));
+            }
+            Err(payload) => {
+                let status = PointStatus::Panicked(panic_message(payload));
+                return self.finish(index, 0, (status, None, vec![]));
+            }
+        };
+
+        // Large programs still run, but with whatever part of the budget they can get to themselves.
+        let Some(threads) = self.budget.acquire(initialized.data.nodes.len().max(1)) else {
+            return self.finish(index, 0, (PointStatus::TimedOut, None, vec![]));
+        };
+        let _ = self.events.send(Event::Started { index, threads });
+        let outcome = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let executed = initialized.run(self.options.run.clone());
+            let status = if executed.passed() {
+                PointStatus::Passed
+            } else {
+                executed.run_failures(|failures| {
+                    PointStatus::SimulationFailed(
+                        failures.iter().map(|err| err.to_string()).collect(),
+                    )
+                })
+            };
+            (
+                status,
+                executed.elapsed_cycles(),
+                executed.summaries().to_vec(),
+            )
+        }))
+        .unwrap_or_else(|payload| (PointStatus::Panicked(panic_message(payload)), None, vec![]));
+        self.finish(index, threads, outcome)
+    }
+
+    fn finish(&self, index: usize, threads: usize, outcome: Outcome) -> bool {
+        if self.settled[index].swap(true, Ordering::AcqRel) {
+            // The supervisor already recorded a timeout, and kept the threads reserved until now.
+            self.budget.release(threads, true);
+            return false;
+        }
+        self.budget.release(threads, false);
+        let _ = self.events.send(Event::Finished { index, outcome });
+        true
+    }
+}
+
+/// Simulates one program per parameter point on a shared pool of workers, see [SweepOptions::parallelism].
+/// Each point is built on a worker by calling `build`, so IDs are deterministic per point.
+/// Failures, panics, and timeouts are recorded per point instead of aborting the sweep.
+/// Running programs cannot be interrupted, so a timed out point is abandoned: it keeps running on its own worker in the background,
+/// and keeps its threads reserved until it finishes, while another worker takes its place in the pool.
+/// The sweep returns once every point has been settled, without waiting for abandoned points.
+/// Points which cannot start because abandoned points hold the entire budget are also recorded as [PointStatus::TimedOut].
+pub fn sweep<P, F>(
+    points: impl IntoIterator<Item = P>,
+    options: SweepOptions,
+    build: F,
+) -> SweepResults<P>
+where
+    P: Clone + Send + Sync + 'static,
+    F: Fn(&P) -> ProgramBuilder<'static> + Send + Sync + 'static,
+{
+    let points: Vec<_> = points.into_iter().collect();
+    let capacity = options
+        .parallelism
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()))
+        .max(1);
+    let timeout = options.timeout;
+    let (events, event_recv) = crossbeam::channel::unbounded();
+    let pool = Arc::new(Pool {
+        settled: points.iter().map(|_| AtomicBool::new(false)).collect(),
+        points,
+        build,
+        options,
+        next_point: AtomicUsize::new(0),
+        budget: ThreadBudget {
+            capacity,
+            state: Mutex::new(BudgetState {
+                free: capacity,
+                abandoned: 0,
+            }),
+            freed: Condvar::new(),
+        },
+        events,
+    });
+    let spawn_worker = || {
+        let pool = pool.clone();
+        std::thread::spawn(move || pool.work());
+    };
+
+    // Every point needs at least one thread, so more workers than that would only wait.
+    for _ in 0..capacity.min(pool.points.len()) {
+        spawn_worker();
+    }
+
+    let mut rows: Vec<Option<PointResult<P>>> = pool.points.iter().map(|_| None).collect();
+    let mut running: Vec<(usize, usize, Instant)> = vec![];
+    let mut remaining = pool.points.len();
+    while remaining > 0 {
+        let deadline =
+            timeout.and_then(|timeout| running.iter().map(|(_, _, start)| *start + timeout).min());
+        let event = match deadline {
+            Some(deadline) => event_recv.recv_deadline(deadline).ok(),
+            None => event_recv.recv().ok(),
+        };
+        match event {
+            Some(Event::Started { index, threads }) => {
+                running.push((index, threads, Instant::now()));
+            }
+            Some(Event::Finished { index, outcome }) => {
+                let start = running
+                    .iter()
+                    .position(|(running, ..)| *running == index)
+                    .map(|pos| running.swap_remove(pos).2);
+                let (status, elapsed_cycles, summaries) = outcome;
+                rows[index] = Some(PointResult {
+                    point: pool.points[index].clone(),
+                    status,
+                    elapsed_cycles,
+                    summaries,
+                    wall_time: start.map_or(Duration::ZERO, |start| start.elapsed()),
+                });
+                remaining -= 1;
+            }
+            None => {
+                let timeout = timeout.unwrap_or_default();
+                let (expired, live): (Vec<_>, Vec<_>) = running
+                    .into_iter()
+                    .partition(|(_, _, start)| start.elapsed() >= timeout);
+                running = live;
+                for (index, threads, start) in expired {
+                    if !pool.budget.abandon(&pool.settled[index], threads) {
+                        // It finished just in time, and its result is already queued.
+                        running.push((index, threads, start));
+                        continue;
+                    }
+                    spawn_worker();
+                    rows[index] = Some(PointResult {
+                        point: pool.points[index].clone(),
+                        status: PointStatus::TimedOut,
+                        elapsed_cycles: None,
+                        summaries: vec![],
+                        wall_time: start.elapsed(),
+                    });
+                    remaining -= 1;
+                }
+            }
+        }
+    }
+
+    SweepResults {
+        rows: rows
+            .into_iter()
+            .map(|row| row.expect("Every point is settled before the sweep returns"))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        simulation::ProgramBuilder,
+        utility_contexts::{CheckerContext, FunctionContext, GeneratorContext},
+    };
+
+    use super::{sweep, PointStatus, SweepOptionsBuilder};
+
+    #[test]
+    fn test_sweep() {
+        // The hung point blocks until the test releases it, which only happens after the sweep returned.
+        let (release, hold) = crossbeam::channel::bounded::<()>(1);
+        // Depth 0 is used to trigger a checker mismatch, and depth 100 never finishes on its own.
+        // Every program has three contexts, so the hung point keeps three of the four threads reserved.
+        let results = sweep(
+            [100usize, 1, 2, 0],
+            SweepOptionsBuilder::default()
+                .parallelism(4usize)
+                .timeout(Duration::from_millis(500))
+                .build()
+                .unwrap(),
+            move |depth| {
+                let depth = *depth;
+                let mut parent = ProgramBuilder::default();
+                let (snd, rcv) = parent.bounded(depth.max(1));
+                parent.add_child(GeneratorContext::new(|| 0..16u32, snd));
+                parent.add_child(CheckerContext::new(
+                    move || (0..16u32).map(move |x| if depth == 0 { x + 1 } else { x }),
+                    rcv,
+                ));
+                let mut extra = FunctionContext::new();
+                let hold = (depth == 100).then(|| hold.clone());
+                extra.set_run(move |_| {
+                    if let Some(hold) = &hold {
+                        let _ = hold.recv();
+                    }
+                });
+                parent.add_child(extra);
+                parent
+            },
+        );
+        release.send(()).unwrap();
+
+        let statuses: Vec<_> = results.rows().iter().map(|row| &row.status).collect();
+        assert!(matches!(statuses[0], PointStatus::TimedOut));
+        assert!(matches!(statuses[1], PointStatus::Passed));
+        assert!(matches!(statuses[2], PointStatus::Passed));
+        assert!(matches!(statuses[3], PointStatus::SimulationFailed(_)));
+        assert_eq!(results.failures().count(), 2);
+        assert!(results.rows()[1].elapsed_cycles.is_some());
+        assert_eq!(results.rows()[1].summaries.len(), 3);
+    }
+
+    #[test]
+    fn test_abandoned_points_keep_their_threads() {
+        let (release, hold) = crossbeam::channel::bounded::<()>(1);
+        // The hung point holds the whole budget, so the other point can never start.
+        let results = sweep(
+            [true, false],
+            SweepOptionsBuilder::default()
+                .parallelism(1usize)
+                .timeout(Duration::from_millis(100))
+                .build()
+                .unwrap(),
+            move |hang: &bool| {
+                let mut parent = ProgramBuilder::default();
+                let mut ctx = FunctionContext::new();
+                let hold = hang.then(|| hold.clone());
+                ctx.set_run(move |_| {
+                    if let Some(hold) = &hold {
+                        let _ = hold.recv();
+                    }
+                });
+                parent.add_child(ctx);
+                parent
+            },
+        );
+        release.send(()).unwrap();
+
+        assert!(matches!(results.rows()[0].status, PointStatus::TimedOut));
+        assert!(matches!(results.rows()[1].status, PointStatus::TimedOut));
+        assert!(results.rows()[1].elapsed_cycles.is_none());
+    }
+}
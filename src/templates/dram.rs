@@ -159,11 +159,11 @@ impl<IType: IndexLike, T: DAMType, AT: DAMType> Context for DRAM<IType, T, AT> {
                 .min_by_key(|(_, val)| *val);
             let event_id = match next_event {
                 // If the next event is Closed, then we know that all of the channels are closed so we're done!
-                Some((_, EventTime::Closed)) => {
+                Some((_, EventTime::Closed(_))) => {
                     // make sure that all of the event times are closed
                     assert!(bundle_timings
                         .iter()
-                        .all(|time| { *time == EventTime::Closed }));
+                        .all(|time| { matches!(time, EventTime::Closed(_)) }));
                     return;
                 }
                 Some((_, EventTime::Nothing(time))) => {
@@ -227,6 +227,8 @@ impl<IType: IndexLike, T: DAMType, AT: DAMType> Context for DRAM<IType, T, AT> {
                                 self.datastore.write(start + offset, data, write_time);
                             });
 
+                        // Responses belong to the transaction of the request's address.
+                        ack.set_tag(addr.last_tag());
                         ack.enqueue(
                             &self.time,
                             ChannelElement {
@@ -274,6 +276,7 @@ impl<IType: IndexLike, T: DAMType, AT: DAMType> Context for DRAM<IType, T, AT> {
                         let read_finish_time = read_time + transfer_time;
                         let mut result_time = read_finish_time;
 
+                        data.set_tag(addr.last_tag());
                         for out in read_vals {
                             result_time = std::cmp::max(self.time.tick() + 1, result_time + 1);
                             data.enqueue(&self.time, ChannelElement::new(result_time, out))
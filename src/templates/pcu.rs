@@ -200,6 +200,12 @@ impl<ElementType: DAMType> Context for PCU<ElementType> {
             }
             self.registers[0] = tmp_regs;
 
+            // Results belong to the transaction of the first tagged input.
+            let tag = self.input_channels.iter().find_map(Receiver::last_tag);
+            self.output_channels
+                .iter()
+                .for_each(|out_chan| out_chan.set_tag(tag));
+
             for stage_index in 0..self.configuration.pipeline_depth {
                 match self.stages.get(stage_index) {
                     Some(cur_stage) => {
@@ -30,6 +30,7 @@ pub struct PMU<T: DAMType, IT: IndexLike, AT: DAMType> {
     reader: ProxyContext<ReadPipeline<T, IT>>,
     writer: ProxyContext<WritePipeline<T, IT, AT>>,
     identifier: Identifier,
+    name: String,
 }
 
 impl<T: DAMType, IT: IndexLike, AT: DAMType> Context for PMU<T, IT, AT> {
@@ -74,6 +75,12 @@ impl<T: DAMType, IT: IndexLike, AT: DAMType> Context for PMU<T, IT, AT> {
         base
     }
 
+    fn renumber(&mut self, renumber: &mut dyn FnMut(Identifier) -> Identifier) {
+        self.identifier = renumber(self.identifier);
+        self.reader.renumber(renumber);
+        self.writer.renumber(renumber);
+    }
+
     fn edge_connections(&self) -> Option<ExplicitConnections> {
         let mut result =
             HashMap::<Identifier, Vec<(HashSet<ChannelID>, HashSet<ChannelID>)>>::new();
@@ -105,7 +112,11 @@ impl<T: DAMType, IT: IndexLike, AT: DAMType> Identifiable for PMU<T, IT, AT> {
     }
 
     fn name(&self) -> String {
-        "PMU".to_string()
+        self.name.clone()
+    }
+
+    fn set_id(&mut self, id: Identifier) {
+        self.identifier = id;
     }
 }
 
@@ -138,7 +149,9 @@ impl<T: DAMType, IT: IndexLike, AT: DAMType> PMU<T, IT, AT> {
             }
             .into(),
             identifier: Identifier::new(),
+            name: String::new(),
         };
+        pmu.set_name("PMU");
         pmu.reader.writer_view = Some(pmu.writer.view());
         dbg!(pmu.id());
         dbg!(pmu.reader.id());
@@ -147,6 +160,13 @@ impl<T: DAMType, IT: IndexLike, AT: DAMType> PMU<T, IT, AT> {
         pmu
     }
 
+    /// Sets the name of this PMU, which is also used as the path prefix of its read and write pipelines.
+    pub fn set_name(&mut self, name: impl Into<String>) {
+        self.name = name.into();
+        self.reader.set_name(format!("{}/ReadPipeline", self.name));
+        self.writer.set_name(format!("{}/WritePipeline", self.name));
+    }
+
     /// Registers a new reader
     pub fn add_reader(&mut self, reader: PMUReadBundle<T, IT>) {
         self.reader.add_reader(reader);
@@ -221,7 +241,7 @@ impl<T: DAMType, IT: IndexLike> Context for ReadPipeline<T, IT> {
                 .enumerate()
                 .min_by_key(|(_, index)| *index);
             let (event_ind, event_time) = match next_event {
-                None | Some((_, EventTime::Closed)) => {
+                None | Some((_, EventTime::Closed(_))) => {
                     // No more events!
                     return;
                 }
@@ -233,7 +253,7 @@ impl<T: DAMType, IT: IndexLike> Context for ReadPipeline<T, IT> {
                     self.time.advance(*time + 1);
                     continue;
                 }
-                EventTime::Closed => unreachable!(),
+                EventTime::Closed(_) => unreachable!(),
             }
             // Wait for the writer to catch up. At this point in time, self.tick should be the same as the ready time
             // so the subsequent dequeue shouldn't actually change the tick time.
@@ -245,6 +265,7 @@ impl<T: DAMType, IT: IndexLike> Context for ReadPipeline<T, IT> {
             let addr: usize = elem.data.to_usize();
             let cur_time = self.time.tick();
             let rv = self.datastore.read(addr, cur_time);
+            deq_reader.resp.set_tag(deq_reader.addr.last_tag());
             deq_reader
                 .resp
                 .enqueue(&self.time, ChannelElement::new(cur_time, rv))
@@ -290,7 +311,7 @@ impl<T: DAMType, IT: IndexLike, AT: DAMType> Context for WritePipeline<T, IT, AT
                 .enumerate()
                 .min_by_key(|(_, index)| *index);
             let (event_ind, event_time) = match next_event {
-                None | Some((_, EventTime::Closed)) => {
+                None | Some((_, EventTime::Closed(_))) => {
                     // No more events!
                     return;
                 }
@@ -303,7 +324,7 @@ impl<T: DAMType, IT: IndexLike, AT: DAMType> Context for WritePipeline<T, IT, AT
                     self.time.advance(*time + 1);
                     continue;
                 }
-                EventTime::Closed => unreachable!(),
+                EventTime::Closed(_) => unreachable!(),
             }
 
             let deq_writer = self.writers.get(event_ind).unwrap();
@@ -313,6 +334,9 @@ impl<T: DAMType, IT: IndexLike, AT: DAMType> Context for WritePipeline<T, IT, AT
             let addr: usize = addr_elem.data.to_usize();
             let cur_time = self.time.tick();
             self.datastore.write(addr, data_elem.data, self.time.tick());
+            deq_writer
+                .ack
+                .set_tag(deq_writer.addr.last_tag().or(deq_writer.data.last_tag()));
             deq_writer
                 .ack
                 .enqueue(&self.time, ChannelElement::new(cur_time, AT::default()))
@@ -338,6 +362,28 @@ mod tests {
 
     use super::PMU;
 
+    #[test]
+    fn pmu_hierarchical_names() {
+        use crate::{context::Context, datastructures::Identifiable};
+
+        let mut pmu = PMU::<u16, u16, bool>::new(
+            8,
+            Behavior {
+                mod_address: false,
+                use_default_value: false,
+            },
+        );
+        pmu.set_name("pmu_3");
+
+        let names: Vec<_> = pmu.ids()[&pmu.verbose()]
+            .iter()
+            .map(|child| child.name.clone())
+            .collect();
+        assert_eq!(pmu.name(), "pmu_3");
+        assert!(names.contains(&"pmu_3/ReadPipeline".to_string()));
+        assert!(names.contains(&"pmu_3/WritePipeline".to_string()));
+    }
+
     #[test]
     fn simple_pmu_test() {
         const TEST_SIZE: usize = 64;
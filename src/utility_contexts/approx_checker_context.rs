@@ -6,7 +6,7 @@ use crate::{
     types::DAMType,
 };
 
-use super::UtilityError;
+use super::{CheckerError, UtilityError};
 
 /// Checks that a given channel contains elements approximately equal to a reference iterator, with a user-defined function.
 #[context_internal]
@@ -35,7 +35,10 @@ where
             for (ind, val) in iter().enumerate() {
                 match self.input.dequeue(&self.time) {
                     Ok(ChannelElement { time, data }) if !(self.checker)(&val, &data) => {
-                        panic!("Mismatch on iteration {ind} at time {time:?}: Expected {val:?} but found {data:?}")
+                        Err(CheckerError::Mismatch {
+                            ind,
+                            msg: format!("{:?} vs {:?} at time {:?}", val, data, time),
+                        })?
                     }
                     Ok(_) => {}
                     Err(_) => Err(UtilityError::Receiver {
@@ -8,6 +8,7 @@ use crate::{
 use crate::context::Context;
 
 /// Since DAM channels are single-producer single-consumer, Broadcasts can be used to send from a single channel to multiple channels.
+/// When the fan-out doesn't need its own context, [crate::simulation::ProgramBuilder::broadcast] avoids the extra hop.
 #[context_internal]
 pub struct BroadcastContext<T: Clone> {
     receiver: Receiver<T>,
@@ -0,0 +1,329 @@
+use std::sync::Arc;
+
+use dam_macros::context_internal;
+
+use crate::{
+    channel::{ChannelElement, ChannelID, Receiver},
+    datastructures::Time,
+    shim::Mutex,
+    types::DAMType,
+};
+
+use crate::context::Context;
+
+use super::{CheckerError, UtilityError};
+
+/// A single difference between a channel and its reference, found by a [CollectingCheckerContext].
+/// Values are formatted with [Debug] when they are found, in case they contain references.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mismatch {
+    /// The element at a position didn't match the reference
+    Value {
+        /// The position in the stream
+        ind: usize,
+        /// The reference value
+        expected: String,
+        /// The received value
+        found: String,
+        /// When the received value arrived
+        time: Time,
+    },
+
+    /// The channel closed before a reference value was received
+    Missing {
+        /// The position in the reference
+        ind: usize,
+        /// The reference value
+        expected: String,
+    },
+
+    /// An element was received which had no counterpart in the reference
+    Extra {
+        /// The position in the stream
+        ind: usize,
+        /// The received value
+        found: String,
+        /// When the received value arrived
+        time: Time,
+    },
+}
+
+/// Every mismatch found by a [CollectingCheckerContext], see [CheckReportHandle].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckReport {
+    /// The checked channel
+    pub channel: ChannelID,
+    /// The number of elements which were received
+    pub received: usize,
+    /// The mismatches, in the order they were found
+    pub mismatches: Vec<Mismatch>,
+    /// Whether further mismatches were dropped because the limit was reached
+    pub truncated: bool,
+}
+
+/// Gives access to the report of a [CollectingCheckerContext] once it has finished.
+#[derive(Clone)]
+pub struct CheckReportHandle {
+    report: Arc<Mutex<Option<CheckReport>>>,
+}
+
+impl CheckReportHandle {
+    /// The report, whether or not the check passed. This is None if the checker didn't finish.
+    pub fn report(&self) -> Option<CheckReport> {
+        self.report.lock().unwrap().clone()
+    }
+}
+
+/// Checks a channel against an iterator, collecting every mismatch instead of stopping at the first one.
+/// Elements received after the reference ends are reported as [Mismatch::Extra], and reference values which never arrive as [Mismatch::Missing].
+/// Once the limit is reached, the channel is still drained and counted, but further mismatches are dropped.
+/// If anything mismatched, the context fails with a [CheckerError::Report], which is collected by [crate::simulation::Executed::check_reports].
+/// The report is also available from its [CheckReportHandle], whether or not the check passed.
+#[context_internal]
+pub struct CollectingCheckerContext<T: Clone, IType, FType>
+where
+    IType: Iterator<Item = T>,
+    FType: FnOnce() -> IType + Send + Sync,
+{
+    iterator: Option<FType>,
+    input: Receiver<T>,
+    limit: usize,
+    unordered: bool,
+    report: Arc<Mutex<Option<CheckReport>>>,
+}
+
+impl<T: DAMType + PartialEq, IType, FType> Context for CollectingCheckerContext<T, IType, FType>
+where
+    IType: Iterator<Item = T>,
+    FType: FnOnce() -> IType + Send + Sync,
+{
+    fn init(&mut self) {}
+
+    fn run_falliable(&mut self) -> anyhow::Result<()> {
+        let Some(iter) = self.iterator.take() else {
+            Err(UtilityError::DuplicateExec)?
+        };
+        let mut report = CheckReport {
+            channel: self.input.id(),
+            received: 0,
+            mismatches: vec![],
+            truncated: false,
+        };
+        if self.unordered {
+            self.check_unordered(iter(), &mut report);
+        } else {
+            self.check_ordered(iter(), &mut report);
+        }
+        let passed = report.mismatches.is_empty();
+        *self.report.lock().unwrap() = Some(report.clone());
+        if passed {
+            Ok(())
+        } else {
+            Err(CheckerError::Report(report))?
+        }
+    }
+}
+
+impl<T: DAMType + PartialEq, IType, FType> CollectingCheckerContext<T, IType, FType>
+where
+    IType: Iterator<Item = T>,
+    FType: FnOnce() -> IType + Send + Sync,
+{
+    /// Constructs a checker which compares elements in order and collects up to 64 mismatches, along with a handle to its report.
+    pub fn new(iterator: FType, input: Receiver<T>) -> (Self, CheckReportHandle) {
+        let report = Arc::new(Mutex::new(None));
+        let cc = CollectingCheckerContext {
+            iterator: Some(iterator),
+            input,
+            limit: 64,
+            unordered: false,
+            report: report.clone(),
+            context_info: Default::default(),
+        };
+        cc.input.attach_receiver(&cc);
+        (cc, CheckReportHandle { report })
+    }
+
+    /// Stops recording mismatches once this many have been found.
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        assert!(limit > 0, "The mismatch limit must be positive");
+        self.limit = limit;
+        self
+    }
+
+    /// Compares the stream and the reference as multisets, for outputs whose order is nondeterministic.
+    pub fn unordered(mut self) -> Self {
+        self.unordered = true;
+        self
+    }
+
+    /// Records a mismatch, unless the limit has already been reached.
+    fn record(&self, report: &mut CheckReport, mismatch: Mismatch) {
+        if report.mismatches.len() < self.limit {
+            report.mismatches.push(mismatch);
+        } else {
+            report.truncated = true;
+        }
+    }
+
+    fn receive(&self, report: &mut CheckReport) -> Option<ChannelElement<T>> {
+        let element = self.input.dequeue(&self.time).ok()?;
+        report.received += 1;
+        Some(element)
+    }
+
+    fn check_ordered(&self, reference: IType, report: &mut CheckReport) {
+        for (ind, expected) in reference.enumerate() {
+            let mismatch = match self.receive(report) {
+                Some(ChannelElement { time, data, .. }) if data != expected => Mismatch::Value {
+                    ind,
+                    expected: format!("{expected:?}"),
+                    found: format!("{data:?}"),
+                    time,
+                },
+                Some(_) => continue,
+                // The rest of the reference is missing, and can't be recorded anymore.
+                None if report.truncated => return,
+                None => Mismatch::Missing {
+                    ind,
+                    expected: format!("{expected:?}"),
+                },
+            };
+            self.record(report, mismatch);
+        }
+        self.check_extra(report);
+    }
+
+    fn check_unordered(&self, reference: IType, report: &mut CheckReport) {
+        let mut unmatched: Vec<_> = reference.map(Some).collect();
+        while let Some(ChannelElement { time, data, .. }) = self.receive(report) {
+            let counterpart = unmatched
+                .iter_mut()
+                .find(|expected| expected.as_ref() == Some(&data));
+            match counterpart {
+                Some(expected) => *expected = None,
+                None => {
+                    let extra = Mismatch::Extra {
+                        ind: report.received - 1,
+                        found: format!("{data:?}"),
+                        time,
+                    };
+                    self.record(report, extra);
+                }
+            }
+        }
+        for (ind, expected) in unmatched.into_iter().enumerate() {
+            if let Some(expected) = expected {
+                let missing = Mismatch::Missing {
+                    ind,
+                    expected: format!("{expected:?}"),
+                };
+                self.record(report, missing);
+            }
+        }
+    }
+
+    /// Drains the channel once the reference has ended.
+    fn check_extra(&self, report: &mut CheckReport) {
+        while let Some(ChannelElement { time, data, .. }) = self.receive(report) {
+            let extra = Mismatch::Extra {
+                ind: report.received - 1,
+                found: format!("{data:?}"),
+                time,
+            };
+            self.record(report, extra);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        simulation::{InitializationOptions, ProgramBuilder, RunOptions},
+        utility_contexts::{CheckerError, GeneratorContext},
+    };
+
+    use super::{CheckReport, CollectingCheckerContext, Mismatch};
+
+    /// Checks `sent` against `expected`, and returns the report if anything mismatched.
+    /// The generator fails if the checker stops receiving early, so every element of `sent` must be received.
+    fn check(
+        sent: Vec<u32>,
+        expected: Vec<u32>,
+        limit: usize,
+        unordered: bool,
+    ) -> Option<CheckReport> {
+        let received = sent.len();
+        let mut ctx = ProgramBuilder::default();
+        let (snd, rcv) = ctx.bounded::<u32>(4);
+        ctx.add_child(GeneratorContext::new(move || sent.into_iter(), snd));
+        let (checker, handle) = CollectingCheckerContext::new(move || expected.into_iter(), rcv);
+        let checker = checker.with_limit(limit);
+        ctx.add_child(if unordered {
+            checker.unordered()
+        } else {
+            checker
+        });
+        let executed = ctx
+            .initialize(InitializationOptions::default())
+            .unwrap()
+            .run(RunOptions::default());
+        let report = handle.report().unwrap();
+        assert_eq!(report.received, received);
+        assert_eq!(executed.passed(), report.mismatches.is_empty());
+        // Failed checks are also reported through the executed program.
+        let failed = (!executed.passed()).then_some(report);
+        assert_eq!(executed.check_reports(), failed.iter().collect::<Vec<_>>());
+        failed
+    }
+
+    #[test]
+    fn test_collecting_checker() {
+        assert_eq!(check(vec![0, 1, 2], vec![0, 1, 2], 8, false), None);
+
+        let report = check(vec![0, 5, 2, 7, 8], vec![0, 1, 2, 3], 8, false).unwrap();
+        assert_eq!(report.received, 5);
+        assert!(!report.truncated);
+        assert!(matches!(
+            report.mismatches.as_slice(),
+            [
+                Mismatch::Value { ind: 1, .. },
+                Mismatch::Value { ind: 3, .. },
+                Mismatch::Extra { ind: 4, .. },
+            ]
+        ));
+
+        let report = check(vec![0, 1], vec![0, 1, 2, 3], 8, false).unwrap();
+        assert_eq!(
+            report.mismatches,
+            vec![
+                Mismatch::Missing {
+                    ind: 2,
+                    expected: "2".to_string()
+                },
+                Mismatch::Missing {
+                    ind: 3,
+                    expected: "3".to_string()
+                },
+            ]
+        );
+
+        let report = check((10..20).collect(), (0..10).collect(), 3, false).unwrap();
+        assert_eq!(report.mismatches.len(), 3);
+        assert!(report.truncated);
+
+        // Order doesn't matter, but multiplicity does.
+        assert_eq!(check(vec![3, 1, 2, 1], vec![1, 1, 2, 3], 8, true), None);
+        let report = check(vec![3, 1, 2, 2], vec![1, 1, 2, 3], 8, true).unwrap();
+        assert!(matches!(
+            report.mismatches.as_slice(),
+            [
+                Mismatch::Extra { ind: 3, .. },
+                Mismatch::Missing { ind: 1, .. }
+            ]
+        ));
+        assert!(CheckerError::Report(report)
+            .to_string()
+            .contains("2 mismatches"));
+    }
+}
@@ -0,0 +1,136 @@
+use std::sync::Arc;
+
+use dam_macros::context_internal;
+
+use crate::{
+    channel::{ChannelElement, Receiver},
+    datastructures::Time,
+    shim::Mutex,
+    types::DAMType,
+};
+
+use crate::context::Context;
+
+use super::UtilityError;
+
+/// An element received by a [CollectorContext].
+#[derive(Clone, Debug)]
+pub struct Collected<T> {
+    /// The element, including its value and timestamp
+    pub element: ChannelElement<T>,
+    /// When the collector dequeued the element, which is never before its timestamp
+    pub dequeued: Time,
+    /// Cycles since the previous element was dequeued, or since the start of the run for the first element
+    pub interval: u64,
+}
+
+/// Gives access to the elements received by a [CollectorContext].
+/// Elements are kept as they arrive, so the ones received before a failed run are still available.
+#[derive(Clone)]
+pub struct CollectorHandle<T> {
+    collected: Arc<Mutex<Vec<Collected<T>>>>,
+}
+
+impl<T: Clone> CollectorHandle<T> {
+    /// Every element received by the collector so far, in order.
+    pub fn collected(&self) -> Vec<Collected<T>> {
+        self.collected.lock().unwrap().clone()
+    }
+
+    /// The received values, in order
+    pub fn values(&self) -> Vec<T> {
+        self.collected()
+            .iter()
+            .map(|collected| collected.element.data.clone())
+            .collect()
+    }
+
+    /// The timestamps of the received elements, in order
+    pub fn times(&self) -> Vec<Time> {
+        self.collected()
+            .iter()
+            .map(|collected| collected.element.time)
+            .collect()
+    }
+}
+
+/// A context which consumes a channel like a [super::ConsumerContext], but keeps every element for inspection after the run.
+/// Each element takes one cycle to consume.
+#[context_internal]
+pub struct CollectorContext<T: DAMType> {
+    chan: Receiver<T>,
+    collected: Option<Arc<Mutex<Vec<Collected<T>>>>>,
+}
+
+impl<T: DAMType> Context for CollectorContext<T> {
+    fn init(&mut self) {}
+
+    fn run_falliable(&mut self) -> anyhow::Result<()> {
+        let Some(collected) = self.collected.take() else {
+            Err(UtilityError::DuplicateExec)?
+        };
+        let mut previous = Time::new(0);
+        while let Ok(element) = self.chan.dequeue(&self.time) {
+            let dequeued = self.time.tick();
+            collected.lock().unwrap().push(Collected {
+                element,
+                dequeued,
+                interval: dequeued.time() - previous.time(),
+            });
+            previous = dequeued;
+            self.time.incr_cycles(1);
+        }
+        Ok(())
+    }
+}
+
+impl<T: DAMType> CollectorContext<T> {
+    /// Constructs a context which reads out of a channel, along with a handle to the elements it will receive.
+    pub fn new(chan: Receiver<T>) -> (Self, CollectorHandle<T>) {
+        let collected = Arc::new(Mutex::new(vec![]));
+        let s = Self {
+            chan,
+            collected: Some(collected.clone()),
+            context_info: Default::default(),
+        };
+        s.chan.attach_receiver(&s);
+        (s, CollectorHandle { collected })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        simulation::{InitializationOptions, ProgramBuilder, RunOptions},
+        utility_contexts::GeneratorContext,
+    };
+
+    use super::CollectorContext;
+
+    #[test]
+    fn test_collector() {
+        let mut ctx = ProgramBuilder::default();
+        let (snd, rcv) = ctx.bounded_with_latency::<u32>(4, 3, 1);
+        ctx.add_child(GeneratorContext::new(|| 0..16u32, snd));
+        let (collector, handle) = CollectorContext::new(rcv);
+        ctx.add_child(collector);
+        let executed = ctx
+            .initialize(InitializationOptions::default())
+            .unwrap()
+            .run(RunOptions::default());
+        assert!(executed.passed());
+
+        assert_eq!(handle.values(), (0..16).collect::<Vec<_>>());
+        let collected = handle.collected();
+        // The first element pays the channel's latency, and the rest are consumed once per cycle.
+        assert_eq!(collected[0].element.time.time(), 3);
+        assert_eq!(collected[0].interval, 3);
+        assert!(collected
+            .iter()
+            .all(|collected| collected.dequeued >= collected.element.time));
+        assert!(collected[1..]
+            .iter()
+            .all(|collected| collected.interval == 1));
+        assert_eq!(handle.times().last().unwrap().time(), 18);
+    }
+}
@@ -4,6 +4,9 @@ use crate::{channel::Receiver, types::DAMType};
 
 use crate::context::Context;
 
+// How many elements the consumer pops at a time.
+const CONSUMER_BATCH: usize = 64;
+
 /// A context which simply consumes values out of a channel
 /// This is useful for when it is not feasible to construct a Void sender instead via [crate::simulation::ProgramBuilder::void] instead
 #[context_internal]
@@ -15,12 +18,11 @@ impl<T: DAMType> Context for ConsumerContext<T> {
     fn init(&mut self) {}
 
     fn run(&mut self) {
-        loop {
-            if self.chan.dequeue(&self.time).is_err() {
-                return;
-            }
-            self.time.incr_cycles(1);
-        }
+        while self
+            .chan
+            .dequeue_many(&self.time, CONSUMER_BATCH, 1)
+            .is_ok()
+        {}
     }
 }
 
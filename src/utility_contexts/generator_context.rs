@@ -27,12 +27,12 @@ where
 
     fn run_falliable(&mut self) -> anyhow::Result<()> {
         if let Some(func) = self.iterator.take() {
-            for val in (func)() {
-                let current_time = self.time.tick();
-                self.output
-                    .enqueue(&self.time, ChannelElement::new(current_time + 1, val))?;
-                self.time.incr_cycles(1);
-            }
+            let time = &self.time;
+            self.output.enqueue_many(
+                time,
+                (func)().map(|val| ChannelElement::new(time.tick() + 1, val)),
+                1,
+            )?;
         } else {
             Err(UtilityError::DuplicateExec)?
         }
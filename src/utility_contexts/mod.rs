@@ -4,9 +4,13 @@
 mod approx_checker_context;
 mod broadcast_context;
 mod checker_context;
+mod collecting_checker_context;
+mod collector_context;
 mod consumer_context;
 mod function_context;
 mod generator_context;
+mod replay_context;
+mod timed_checker_context;
 mod trace_context;
 
 use std::fmt::Debug;
@@ -14,13 +18,19 @@ use std::fmt::Debug;
 pub use approx_checker_context::ApproxCheckerContext;
 pub use broadcast_context::BroadcastContext;
 pub use checker_context::CheckerContext;
+pub use collecting_checker_context::{
+    CheckReport, CheckReportHandle, CollectingCheckerContext, Mismatch,
+};
+pub use collector_context::{Collected, CollectorContext, CollectorHandle};
 pub use consumer_context::{ConsumerContext, PrinterContext};
 pub use function_context::FunctionContext;
 pub use generator_context::GeneratorContext;
+pub use replay_context::{ReplayCheckerContext, ReplayContext};
 use thiserror::Error;
+pub use timed_checker_context::{TimedCheckerContext, TimingTolerance};
 pub use trace_context::{random_trace, TraceContext};
 
-use crate::channel::ChannelID;
+use crate::{channel::ChannelID, datastructures::Time};
 
 /// A bundle of generic failures for utility contexts.
 #[derive(Debug, Error)]
@@ -51,4 +61,24 @@ pub enum CheckerError {
         /// The error message. Conversion must happen early in case T contains a reference.
         msg: String,
     },
+
+    /// The expected value arrived, but at a time outside of the tolerance
+    #[error("Mismatched timing on iteration {ind:?}: expected {expected:?} ({tolerance:?}) but found {found:?}")]
+    Timing {
+        /// The index of the mismatch
+        ind: usize,
+
+        /// The expected time, or the latest allowed time when checking [TimingTolerance::Throughput]
+        expected: Time,
+
+        /// The element's actual timestamp
+        found: Time,
+
+        /// The tolerance which was violated
+        tolerance: TimingTolerance,
+    },
+
+    /// Every mismatch found by a [CollectingCheckerContext]
+    #[error("{} mismatches on channel {:?}", .0.mismatches.len(), .0.channel)]
+    Report(CheckReport),
 }
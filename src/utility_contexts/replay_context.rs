@@ -0,0 +1,91 @@
+use dam_macros::context_internal;
+
+use crate::context_tools::*;
+
+use crate::context::Context;
+use crate::datastructures::Time;
+
+use super::{CheckerError, UtilityError};
+
+/// A context which re-sends a recorded stream, enqueueing each element at the time it was originally enqueued.
+/// Unlike a [super::TraceContext], element timestamps are preserved exactly, so they already include the channel's latency.
+#[context_internal]
+pub struct ReplayContext<T: Clone> {
+    trace: Vec<(Time, ChannelElement<T>)>,
+    output: Sender<T>,
+}
+
+impl<T: DAMType> Context for ReplayContext<T> {
+    fn init(&mut self) {}
+
+    fn run_falliable(&mut self) -> anyhow::Result<()> {
+        for (time, element) in std::mem::take(&mut self.trace) {
+            self.time.advance(time);
+            self.output.enqueue(&self.time, element)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: DAMType> ReplayContext<T> {
+    /// Constructs a [ReplayContext] from `(enqueue time, element)` pairs and the output channel
+    pub fn new(trace: Vec<(Time, ChannelElement<T>)>, output: Sender<T>) -> Self {
+        let rc = ReplayContext {
+            trace,
+            output,
+            context_info: Default::default(),
+        };
+        rc.output.attach_sender(&rc);
+        rc
+    }
+}
+
+/// Checks a channel against a recorded stream, dequeueing each element at the time it was originally dequeued.
+/// Both the values and the timestamps of elements must match.
+#[context_internal]
+pub struct ReplayCheckerContext<T: Clone> {
+    expected: Vec<(Time, ChannelElement<T>)>,
+    input: Receiver<T>,
+}
+
+impl<T: DAMType + PartialEq> Context for ReplayCheckerContext<T> {
+    fn init(&mut self) {}
+
+    fn run_falliable(&mut self) -> anyhow::Result<()> {
+        for (ind, (time, expected)) in std::mem::take(&mut self.expected).into_iter().enumerate() {
+            self.time.advance(time);
+            match self.input.dequeue(&self.time) {
+                Ok(ChannelElement { time, data })
+                    if data != expected.data || time != expected.time =>
+                {
+                    Err(CheckerError::Mismatch {
+                        ind,
+                        msg: format!(
+                            "{:?} at time {:?} vs {:?} at time {:?}",
+                            expected.data, expected.time, data, time
+                        ),
+                    })?
+                }
+                Ok(_) => {}
+                Err(_) => Err(UtilityError::Receiver {
+                    iteration: ind,
+                    channel: self.input.id(),
+                })?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: DAMType + PartialEq> ReplayCheckerContext<T> {
+    /// Constructs a [ReplayCheckerContext] from `(dequeue time, element)` pairs and the input channel
+    pub fn new(expected: Vec<(Time, ChannelElement<T>)>, input: Receiver<T>) -> Self {
+        let rc = ReplayCheckerContext {
+            expected,
+            input,
+            context_info: Default::default(),
+        };
+        rc.input.attach_receiver(&rc);
+        rc
+    }
+}
@@ -0,0 +1,196 @@
+use dam_macros::context_internal;
+
+use crate::{
+    channel::{ChannelElement, Receiver},
+    datastructures::Time,
+    types::DAMType,
+};
+
+use crate::context::Context;
+
+use super::{CheckerError, UtilityError};
+
+/// How closely the timestamps seen by a [TimedCheckerContext] must match the expected times.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimingTolerance {
+    /// Each element must arrive exactly at its expected time
+    Exact,
+
+    /// Each element must arrive within the given number of cycles of its expected time, in either direction
+    Within(u64),
+
+    /// Each element may arrive late, but never before its expected time
+    NoEarlierThan,
+
+    /// The stream may be shifted by any amount, but must keep up with the expected rate.
+    /// After the first element, each element must arrive no later than the previous one plus the expected gap between them.
+    Throughput,
+}
+
+impl TimingTolerance {
+    /// Returns the latest allowed time if `found` doesn't satisfy the tolerance.
+    /// `previous` holds the expected and actual times of the previous element.
+    fn check(&self, expected: Time, found: Time, previous: Option<(Time, Time)>) -> Option<Time> {
+        let passed = match self {
+            TimingTolerance::Exact => found == expected,
+            TimingTolerance::Within(cycles) => found.time().abs_diff(expected.time()) <= *cycles,
+            TimingTolerance::NoEarlierThan => found >= expected,
+            TimingTolerance::Throughput => {
+                // The first element sets the offset of the stream.
+                let (prev_expected, prev_found) = previous?;
+                let deadline = prev_found + expected.time().saturating_sub(prev_expected.time());
+                return (found > deadline).then_some(deadline);
+            }
+        };
+        (!passed).then_some(expected)
+    }
+}
+
+/// Checks a channel against an iterator of `(value, expected time)` pairs, where each element's timestamp must satisfy a [TimingTolerance].
+/// Values are compared exactly, as with [super::CheckerContext], and are checked before times.
+#[context_internal]
+pub struct TimedCheckerContext<T: Clone, IType, FType>
+where
+    IType: Iterator<Item = (T, Time)>,
+    FType: FnOnce() -> IType + Send + Sync,
+{
+    iterator: Option<FType>,
+    input: Receiver<T>,
+    tolerance: TimingTolerance,
+}
+
+impl<T: DAMType + PartialEq, IType, FType> Context for TimedCheckerContext<T, IType, FType>
+where
+    IType: Iterator<Item = (T, Time)>,
+    FType: FnOnce() -> IType + Send + Sync,
+{
+    fn init(&mut self) {}
+
+    fn run_falliable(&mut self) -> anyhow::Result<()> {
+        let Some(iter) = self.iterator.take() else {
+            Err(UtilityError::DuplicateExec)?
+        };
+        let mut previous = None;
+        for (ind, (val, expected)) in iter().enumerate() {
+            match self.input.dequeue(&self.time) {
+                Ok(ChannelElement { time, data, .. }) if data != val => {
+                    Err(CheckerError::Mismatch {
+                        ind,
+                        msg: format!("{:?} vs {:?} at time {:?}", val, data, time),
+                    })?
+                }
+                Ok(ChannelElement { time, .. }) => {
+                    if let Some(expected) = self.tolerance.check(expected, time, previous) {
+                        Err(CheckerError::Timing {
+                            ind,
+                            expected,
+                            found: time,
+                            tolerance: self.tolerance,
+                        })?
+                    }
+                    previous = Some((expected, time));
+                }
+                Err(_) => Err(UtilityError::Receiver {
+                    iteration: ind,
+                    channel: self.input.id(),
+                })?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: DAMType + PartialEq, IType, FType> TimedCheckerContext<T, IType, FType>
+where
+    IType: Iterator<Item = (T, Time)>,
+    FType: FnOnce() -> IType + Send + Sync,
+{
+    /// Constructs a timing-aware checker from an iterator of `(value, expected time)` pairs.
+    pub fn new(iterator: FType, input: Receiver<T>, tolerance: TimingTolerance) -> Self {
+        let tc = TimedCheckerContext {
+            iterator: Some(iterator),
+            input,
+            tolerance,
+            context_info: Default::default(),
+        };
+        tc.input.attach_receiver(&tc);
+        tc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        channel::ChannelElement,
+        datastructures::Time,
+        simulation::{InitializationOptions, ProgramBuilder, RunOptions},
+        utility_contexts::FunctionContext,
+    };
+
+    use super::{TimedCheckerContext, TimingTolerance};
+
+    /// Sends 0..8 with the i-th element arriving at 10 * i + 5, and checks it against `expected`.
+    /// Returns the failure message, if any.
+    fn check(expected: Vec<(u32, u64)>, tolerance: TimingTolerance) -> Option<String> {
+        let mut ctx = ProgramBuilder::default();
+        let (snd, rcv) = ctx.bounded::<u32>(8);
+        let mut producer = FunctionContext::default();
+        snd.attach_sender(&producer);
+        producer.set_run(move |time| {
+            for i in 0..8u32 {
+                // The checker stops at its first failure, which closes the channel.
+                if snd
+                    .enqueue(time, ChannelElement::new(time.tick() + 5, i))
+                    .is_err()
+                {
+                    return;
+                }
+                time.incr_cycles(10);
+            }
+        });
+        ctx.add_child(producer);
+        let expected = expected
+            .into_iter()
+            .map(|(val, time)| (val, Time::new(time)));
+        ctx.add_child(TimedCheckerContext::new(move || expected, rcv, tolerance));
+        let executed = ctx
+            .initialize(InitializationOptions::default())
+            .unwrap()
+            .run(RunOptions::default());
+        executed.run_failures(|failures| failures.first().map(|failure| failure.to_string()))
+    }
+
+    #[test]
+    fn test_timed_checker() {
+        let exact: Vec<_> = (0..8).map(|i| (i, 10 * u64::from(i) + 5)).collect();
+        let shifted = |offset: i64| -> Vec<_> {
+            exact
+                .iter()
+                .map(|(val, time)| (*val, time.checked_add_signed(offset).unwrap()))
+                .collect()
+        };
+        assert_eq!(check(exact.clone(), TimingTolerance::Exact), None);
+        assert!(check(shifted(1), TimingTolerance::Exact)
+            .unwrap()
+            .contains("timing"));
+        assert_eq!(check(shifted(-2), TimingTolerance::Within(2)), None);
+        assert!(check(shifted(3), TimingTolerance::Within(2)).is_some());
+        assert_eq!(check(shifted(-4), TimingTolerance::NoEarlierThan), None);
+        assert!(check(shifted(1), TimingTolerance::NoEarlierThan).is_some());
+
+        // Only the gaps matter when checking throughput.
+        assert_eq!(check(shifted(5), TimingTolerance::Throughput), None);
+        let mut bursty = exact.clone();
+        bursty[4].1 -= 4;
+        assert!(check(bursty, TimingTolerance::Throughput)
+            .unwrap()
+            .contains("iteration 4"));
+
+        // Values are reported separately from times.
+        let mut wrong = exact.clone();
+        wrong[2].0 = 100;
+        assert!(check(wrong, TimingTolerance::Exact)
+            .unwrap()
+            .contains("Mismatched results on iteration 2"));
+    }
+}
@@ -27,6 +27,51 @@ mod tests {
         run_channel_test(TEST_SIZE, true, None);
     }
 
+    /// Builds a sender and receiver the usual way, and returns the IDs their program gave them.
+    fn function_context_ids() -> Vec<usize> {
+        let mut ctx = ProgramBuilder::default();
+        let (snd, rcv) = ctx.bounded(2);
+
+        let mut sender = FunctionContext::new();
+        snd.attach_sender(&sender);
+        sender.set_run(move |time| {
+            snd.enqueue(time, ChannelElement::new(time.tick(), 0u32))
+                .unwrap();
+        });
+        ctx.add_child(sender);
+
+        let mut receiver = FunctionContext::new();
+        rcv.attach_receiver(&receiver);
+        receiver.set_run(move |time| {
+            rcv.dequeue(time).unwrap();
+        });
+        ctx.add_child(receiver);
+
+        let executed = ctx
+            .initialize(Default::default())
+            .unwrap()
+            .run(RunOptions::default());
+        assert!(executed.passed());
+        let mut ids: Vec<_> = executed
+            .summaries()
+            .iter()
+            .map(|summary| summary.id.id.id)
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn test_context_ids_are_per_program() {
+        assert_eq!(function_context_ids(), vec![0, 1]);
+        let concurrent: Vec<_> = (0..4)
+            .map(|_| std::thread::spawn(function_context_ids))
+            .collect();
+        for handle in concurrent {
+            assert_eq!(handle.join().unwrap(), vec![0, 1]);
+        }
+    }
+
     fn run_channel_test(test_size: i32, flavor_inference: bool, capacity: Option<usize>) {
         let mut ctx = ProgramBuilder::default();
 